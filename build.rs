@@ -0,0 +1,41 @@
+//! Reads the versions of the GUI libraries from `Cargo.lock` for the diagnostic info
+
+use std::{env, fs, path::Path};
+
+/// Version of the package `name` in the lock file, `None` if it's locked more than once
+fn locked_version(lock: &str, name: &str) -> Option<String> {
+    let mut versions = lock
+        .split("[[package]]")
+        .filter(|package| {
+            package
+                .lines()
+                .any(|line| line.trim() == format!("name = \"{name}\""))
+        })
+        .filter_map(|package| {
+            package
+                .lines()
+                .find_map(|line| line.trim().strip_prefix("version = "))
+                .map(|version| version.trim_matches('"').to_owned())
+        });
+    let version = versions.next()?;
+    versions.next().is_none().then_some(version)
+}
+
+fn main() {
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is set by cargo");
+    let lock_path = Path::new(&manifest_dir).join("Cargo.lock");
+    println!("cargo:rerun-if-changed={}", lock_path.display());
+
+    // the versions are shown as unknown without a lock file
+    let Ok(lock) = fs::read_to_string(&lock_path) else {
+        return;
+    };
+    for (name, var) in [
+        ("egui", "ELLAMA_EGUI_VERSION"),
+        ("eframe", "ELLAMA_EFRAME_VERSION"),
+    ] {
+        if let Some(version) = locked_version(&lock, name) {
+            println!("cargo:rustc-env={var}={version}");
+        }
+    }
+}
@@ -2,41 +2,109 @@
 use crate::sessions::SharedTts;
 
 use crate::{
+    commands::{self, Command, COMMANDS},
     easymark::MemoizedEasymarkHighlighter,
-    widgets::{self, ModelPicker},
+    error::AppError,
+    widgets::{self, AutoTrim, ModelPicker, ResponseFormat, SavedPrompt, SendKey, TimestampFormat},
 };
 use anyhow::{Context, Result};
 use eframe::egui::{
-    self, pos2, vec2, Align, Color32, Frame, Key, KeyboardShortcut, Layout, Margin, Modifiers,
-    Pos2, Rect, Rounding, Stroke, TextStyle,
+    self, pos2, vec2, Align, Color32, Frame, Key, Layout, Margin, Pos2, Rect, Rounding, Stroke,
+    TextStyle,
 };
 use egui_commonmark::{CommonMarkCache, CommonMarkViewer};
 use egui_modal::{Icon, Modal};
+use egui_notify::Toast;
 use egui_virtual_list::VirtualList;
 use flowync::{error::Compact, CompactFlower, CompactHandle};
 use ollama_rs::{
     generation::{
-        chat::{request::ChatMessageRequest, ChatMessage, ChatMessageResponseStream},
+        chat::{request::ChatMessageRequest, ChatMessage, MessageRole},
         images::Image,
         options::GenerationOptions,
+        parameters::KeepAlive,
     },
+    models::LocalModel,
     Ollama,
 };
+use parking_lot::RwLock;
 use std::{
-    io::Write,
-    path::PathBuf,
+    collections::HashMap,
+    io::{Read, Write},
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
-    time::Instant,
+    time::{Duration, Instant},
 };
-use tokio_stream::StreamExt;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 enum Role {
     User,
     Assistant,
+    System,
+}
+
+/// How the user rated a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Rating {
+    Up,
+    Down,
+}
+
+/// How older messages are left out of the context when the chat gets long. The system prompt
+/// and the last turn are always sent.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ContextStrategy {
+    /// Send the whole history, Ollama truncates it from the start if it doesn't fit
+    #[default]
+    KeepAll,
+    /// Leave out the oldest messages that don't fit into `num_ctx`
+    DropOldest,
+    /// Only send the last n messages
+    SlidingWindow(usize),
+}
+
+impl ContextStrategy {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::KeepAll => "Keep all messages",
+            Self::DropOldest => "Drop oldest messages that don't fit",
+            Self::SlidingWindow(_) => "Only send the last messages",
+        }
+    }
+}
+
+/// Why the model stopped generating a response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+enum DoneReason {
+    /// The model finished the response or a stop sequence was encountered
+    Stop,
+    /// The response hit the token limit (`num_predict`)
+    Length,
+    /// Generation was stopped by the user
+    Aborted,
+    /// The app was closed while the response was generating
+    Interrupted,
+}
+
+impl DoneReason {
+    const fn description(self) -> &'static str {
+        match self {
+            Self::Stop => "finished or hit a stop sequence",
+            Self::Length => "hit the token limit",
+            Self::Aborted => "stopped by the user",
+            Self::Interrupted => "interrupted when the app was closed",
+        }
+    }
+}
+
+/// Translation of a response, shown under it
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct Translation {
+    language: String,
+    text: String,
 }
 
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
@@ -45,18 +113,49 @@ pub struct Message {
     model_name: String,
     content: String,
     role: Role,
-    #[serde(skip)]
+    /// Saved so responses that were generating when the app was closed can be marked as
+    /// interrupted, see [`Chat::mark_interrupted`]
     is_generating: bool,
     #[serde(skip)]
     requested_at: Instant,
+    /// Number of chunks streamed since the response was requested, Ollama sends one
+    /// token per chunk
+    #[serde(skip)]
+    streamed_chunks: usize,
     time: chrono::DateTime<chrono::Utc>,
     #[serde(skip)]
     clicked_copy: bool,
+    /// Code block that was just copied
+    #[serde(skip)]
+    copied_code: Option<usize>,
+    /// Show the content as plain text instead of rendering the Markdown
+    #[serde(skip)]
+    show_raw: bool,
     is_error: bool,
+    /// What went wrong, `None` for errors saved before errors were categorized
+    error: Option<AppError>,
     #[serde(skip)]
     is_speaking: bool,
     images: Vec<PathBuf>,
+    /// The images are sent in their original size, even if they are larger than the limit
+    original_images: bool,
+    text_files: Vec<TextFile>,
     is_prepending: bool,
+    /// Model picked to regenerate the response with, `None` for the model that generated it
+    #[serde(skip)]
+    regenerate_model: Option<String>,
+    done_reason: Option<DoneReason>,
+    /// The response was requested in JSON mode
+    is_json: bool,
+    /// Seconds it took to generate the response
+    generation_secs: Option<f64>,
+    rating: Option<Rating>,
+    /// The pointer was over the message last frame, reveals the actions of user messages
+    #[serde(skip)]
+    hovered: bool,
+    translation: Option<Translation>,
+    #[serde(skip)]
+    is_translating: bool,
 }
 
 impl Default for Message {
@@ -66,13 +165,27 @@ impl Default for Message {
             role: Role::User,
             is_generating: false,
             requested_at: Instant::now(),
+            streamed_chunks: 0,
             time: chrono::Utc::now(),
             clicked_copy: false,
+            copied_code: None,
+            show_raw: false,
             is_error: false,
+            error: None,
             is_speaking: false,
             model_name: String::new(),
             images: Vec::new(),
+            original_images: false,
+            text_files: Vec::new(),
             is_prepending: false,
+            regenerate_model: None,
+            done_reason: None,
+            is_json: false,
+            generation_secs: None,
+            rating: None,
+            hovered: false,
+            translation: None,
+            is_translating: false,
         }
     }
 }
@@ -96,6 +209,398 @@ fn tts_control(tts: SharedTts, text: String, speak: bool) {
     });
 }
 
+/// Queue text to be spoken after the current utterance, on the calling thread so that
+/// the order is kept
+#[cfg(feature = "tts")]
+fn tts_enqueue(tts: &SharedTts, text: &str, interrupt: bool) {
+    if let Some(tts) = tts {
+        let _ = tts
+            .write()
+            .speak(text, interrupt)
+            .map_err(|e| log::error!("failed to speak: {e}"));
+    }
+}
+
+/// Find the end of the last complete sentence in `text`. A sentence ends with a newline, or
+/// with `.`, `!` or `?` followed by whitespace.
+#[cfg(feature = "tts")]
+fn last_sentence_end(text: &str) -> Option<usize> {
+    let mut end = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let is_end = match c {
+            '\n' => true,
+            '.' | '!' | '?' => chars.peek().is_some_and(|(_, next)| next.is_whitespace()),
+            _ => false,
+        };
+        if is_end {
+            end = Some(i + c.len_utf8());
+        }
+    }
+    end
+}
+
+/// Split a leading `<think>` block off of a response. Returns the reasoning and whether its
+/// closing tag arrived yet, and the answer after it.
+fn split_reasoning(content: &str) -> (Option<(&str, bool)>, &str) {
+    const THINK_START: &str = "<think>";
+    const THINK_END: &str = "</think>";
+
+    let Some(rest) = content.trim_start().strip_prefix(THINK_START) else {
+        return (None, content);
+    };
+    match rest.find(THINK_END) {
+        Some(end) => (
+            Some((rest[..end].trim(), true)),
+            rest[end + THINK_END.len()..].trim_start(),
+        ),
+        None => (Some((rest.trim(), false)), ""),
+    }
+}
+
+/// Fence of a Markdown code block the line starts with, the line should be trimmed
+fn code_fence(line: &str) -> Option<&str> {
+    let c = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.len() - line.trim_start_matches(c).len();
+    (len >= 3).then(|| &line[..len])
+}
+
+/// Whether the trimmed line closes the code block opened with `open`
+fn closes_fence(line: &str, open: &str) -> bool {
+    code_fence(line)
+        .is_some_and(|close| close.starts_with(open) && close.len() == line.trim_end().len())
+}
+
+/// Split text into parts of at most `max_len` bytes at line breaks, or spaces if a line is
+/// too long. Code blocks and words are never split, so parts with them can be longer.
+fn split_message(text: &str, max_len: usize) -> Vec<String> {
+    // code blocks and lines outside of them
+    let mut units: Vec<String> = Vec::new();
+    let mut fence: Option<&str> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        match fence {
+            Some(open) => {
+                if let Some(block) = units.last_mut() {
+                    block.push('\n');
+                    block.push_str(line);
+                }
+                if closes_fence(trimmed, open) {
+                    fence = None;
+                }
+            }
+            None => {
+                fence = code_fence(trimmed);
+                units.push(line.to_owned());
+            }
+        }
+    }
+
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut push = |piece: &str, sep: char, parts: &mut Vec<String>| {
+        if !part.is_empty() && part.len() + 1 + piece.len() > max_len {
+            parts.push(std::mem::take(&mut part));
+        }
+        if !part.is_empty() {
+            part.push(sep);
+        }
+        part.push_str(piece);
+    };
+    for unit in &units {
+        let is_block = code_fence(unit.trim_start()).is_some();
+        if unit.len() <= max_len || is_block {
+            push(unit, '\n', &mut parts);
+            continue;
+        }
+        // a long line, split it between words
+        let mut words = unit.split(' ');
+        if let Some(word) = words.next() {
+            push(word, '\n', &mut parts);
+        }
+        for word in words {
+            push(word, ' ', &mut parts);
+        }
+    }
+    if !part.is_empty() {
+        parts.push(part);
+    }
+    parts
+}
+
+/// Fenced code blocks in Markdown, as their language and contents without the fences.
+/// A block that isn't closed runs until the end of the text.
+fn code_blocks(text: &str) -> Vec<(&str, String)> {
+    let mut blocks = Vec::new();
+    // opening fence, language and lines of the block being scanned
+    let mut current: Option<(&str, &str, Vec<&str>)> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        let Some((open, _, lines)) = &mut current else {
+            if let Some(fence) = code_fence(trimmed) {
+                let info = trimmed[fence.len()..].split_whitespace().next();
+                current = Some((fence, info.unwrap_or_default(), Vec::new()));
+            }
+            continue;
+        };
+        let closes = closes_fence(trimmed, open);
+        if !closes {
+            lines.push(line);
+        } else if let Some((_, language, lines)) = current.take() {
+            blocks.push((language, lines.join("\n")));
+        }
+    }
+    if let Some((_, language, lines)) = current {
+        blocks.push((language, lines.join("\n")));
+    }
+    blocks
+}
+
+/// Offset of the answer in a response, `None` while the model is still reasoning
+#[cfg(feature = "tts")]
+fn answer_start(content: &str) -> Option<usize> {
+    match split_reasoning(content) {
+        (Some((_, false)), _) => None,
+        (_, answer) => Some(content.len() - answer.len()),
+    }
+}
+
+/// Language of a fenced code block for a file extension
+fn code_language(ext: &str) -> &str {
+    match ext {
+        "txt" | "log" => "",
+        "md" => "markdown",
+        "yml" => "yaml",
+        "h" => "c",
+        "hpp" | "cc" => "cpp",
+        "ps1" => "powershell",
+        "zsh" => "bash",
+        "exs" => "ex",
+        "patch" => "diff",
+        _ => ext,
+    }
+}
+
+/// Text file attached to a message, sent after the message text as a fenced code block
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TextFile {
+    pub name: String,
+    text: String,
+    /// Size of the file on disk, in bytes
+    size: u64,
+    /// Only the first `max_bytes` of the file were read
+    pub truncated: bool,
+}
+
+impl TextFile {
+    /// Read a text file, keeping at most `max_bytes` of it. Only that much of the file is read.
+    pub fn read(path: &Path, max_bytes: usize) -> Result<Self> {
+        let context = || format!("failed to read `{}`", path.display());
+        let file = std::fs::File::open(path).with_context(context)?;
+        let size = file.metadata().with_context(context)?.len();
+        let mut bytes = Vec::new();
+        file.take(max_bytes as u64)
+            .read_to_end(&mut bytes)
+            .with_context(context)?;
+        if bytes.contains(&0) {
+            anyhow::bail!("`{}` is not a text file", path.display());
+        }
+        let truncated = size > bytes.len() as u64;
+        // don't cut a character in half
+        if let Err(e) = std::str::from_utf8(&bytes) {
+            if truncated && e.error_len().is_none() {
+                bytes.truncate(e.valid_up_to());
+            }
+        }
+        Ok(Self {
+            name: path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            text: String::from_utf8_lossy(&bytes).trim_end().to_owned(),
+            size,
+            truncated,
+        })
+    }
+
+    /// The file as a fenced code block labeled with its name
+    fn to_markdown(&self) -> String {
+        // the fence must be longer than any backtick run in the file
+        let mut longest_run = 0;
+        let mut run = 0;
+        for c in self.text.chars() {
+            run = if c == '`' { run + 1 } else { 0 };
+            longest_run = longest_run.max(run);
+        }
+        let fence = "`".repeat((longest_run + 1).max(3));
+
+        let language = code_language(
+            Path::new(&self.name)
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or_default(),
+        );
+        let notice = if self.truncated { " (truncated)" } else { "" };
+        format!(
+            "`{}`{notice}:\n{fence}{language}\n{}\n{fence}",
+            self.name, self.text
+        )
+    }
+
+    /// Chip with the name and size of the file, with a ❌ button if `removable`. Returns
+    /// whether the button was clicked
+    fn show(&self, ui: &mut egui::Ui, removable: bool) -> bool {
+        let mut label = format!("📄 {} ({})", self.name, bytesize::ByteSize(self.size));
+        if self.truncated {
+            label += " ⚠";
+        }
+        let preview: String = self.text.lines().take(10).collect::<Vec<_>>().join("\n");
+        ui.label(RichText::new(label).small())
+            .on_hover_text(if self.truncated {
+                format!(
+                    "Only the first {} are attached\n\n{preview}",
+                    bytesize::ByteSize(self.text.len() as u64)
+                )
+            } else {
+                preview
+            });
+        removable
+            && ui
+                .small_button("❌")
+                .on_hover_text("Remove the file")
+                .clicked()
+    }
+}
+
+/// Whether the focused text edit is in the middle of an IME composition (or dead key
+/// sequence), or it finished this frame. Enter and Escape belong to the IME then.
+fn ime_composing(ui: &egui::Ui, response: &egui::Response) -> bool {
+    let id = response.id.with("ime_composing");
+    if !response.has_focus() && !response.lost_focus() {
+        ui.data_mut(|d| d.remove::<bool>(id));
+        return false;
+    }
+
+    let was_composing = ui.data(|d| d.get_temp::<bool>(id).unwrap_or(false));
+    let (composing, ime_keys) = ui.input(|i| ime_state(was_composing, &i.events));
+    ui.data_mut(|d| d.insert_temp(id, composing));
+    ime_keys
+}
+
+/// Whether a composition is still going on after the events of a frame, and whether Enter
+/// and Escape belong to the IME in it
+fn ime_state(mut composing: bool, events: &[egui::Event]) -> (bool, bool) {
+    let mut committed = false;
+    for event in events {
+        match event {
+            egui::Event::Ime(egui::ImeEvent::Preedit(text)) => composing = !text.is_empty(),
+            egui::Event::Ime(egui::ImeEvent::Commit(_) | egui::ImeEvent::Disabled) => {
+                composing = false;
+                committed = true;
+            }
+            _ => (),
+        }
+    }
+    (composing, composing || committed)
+}
+
+/// Label for the divider before the first message of a day, in local time
+fn day_label(day: chrono::NaiveDate) -> String {
+    use chrono::Datelike;
+
+    let today = chrono::Local::now().date_naive();
+    if day == today {
+        "Today".to_owned()
+    } else if today.pred_opt() == Some(day) {
+        "Yesterday".to_owned()
+    } else if day.year() == today.year() {
+        day.format("%A, %B %-d").to_string()
+    } else {
+        day.format("%B %-d, %Y").to_string()
+    }
+}
+
+/// Exchange each message belongs to, numbered by the user message that starts it. Errors and
+/// responses belong to the prompt before them, system messages and responses before the
+/// first prompt to none.
+fn exchange_numbers(messages: &[Message]) -> Vec<Option<usize>> {
+    let mut exchange = None;
+    messages
+        .iter()
+        .map(|m| {
+            if m.is_user() {
+                exchange = Some(exchange.map_or(0, |e| e + 1));
+            }
+            exchange.filter(|_| !m.is_system())
+        })
+        .collect()
+}
+
+/// Part of `rect` at most `max_width` wide, centered horizontally
+fn column_rect(rect: Rect, max_width: Option<f32>) -> Rect {
+    match max_width {
+        Some(max_width) if max_width < rect.width() => {
+            rect.shrink2(vec2((rect.width() - max_width) / 2.0, 0.0))
+        }
+        _ => rect,
+    }
+}
+
+/// Show the contents in a centered column at most `max_width` wide
+fn centered_column<R>(
+    ui: &mut egui::Ui,
+    max_width: Option<f32>,
+    add_contents: impl FnOnce(&mut egui::Ui) -> R,
+) -> R {
+    let rect = column_rect(ui.available_rect_before_wrap(), max_width);
+    ui.allocate_ui_at_rect(rect, add_contents).inner
+}
+
+/// Paint a round button floating over the messages, returns whether it was clicked. The icon
+/// is painted by the caller if it wasn't.
+fn floating_button(ui: &mut egui::Ui, radius: f32, pos: Pos2) -> bool {
+    let rect = Rect::from_min_max(pos + vec2(-radius, -radius), pos + vec2(radius, radius));
+    let (hovered, primary_clicked) = ui.input(|i| {
+        (
+            i.pointer
+                .interact_pos()
+                .map(|p| rect.contains(p))
+                .unwrap_or(false),
+            i.pointer.primary_clicked(),
+        )
+    });
+    if hovered && primary_clicked {
+        return true;
+    }
+    if hovered {
+        ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+    }
+    ui.painter().circle(
+        pos,
+        radius,
+        crate::style::floating_button_fill(ui.visuals(), hovered),
+        Stroke::new(2.0, ui.style().visuals.window_stroke.color),
+    );
+    false
+}
+
+/// Put the cursor of a text edit at the end of its text
+fn move_cursor_to_end(ctx: &egui::Context, id: egui::Id, text: &str) {
+    if let Some(mut state) = egui::TextEdit::load_state(ctx, id) {
+        let ccursor = egui::text::CCursor::new(text.chars().count());
+        state
+            .cursor
+            .set_char_range(Some(egui::text::CCursorRange::one(ccursor)));
+        state.store(ctx, id);
+    }
+}
+
+/// Rough number of tokens in a text, assuming about 4 characters per token
+fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
 /// Convert a model name into a short name.
 ///
 /// # Example
@@ -121,6 +626,8 @@ enum MessageAction {
     None,
     Retry(usize),
     Regenerate(usize),
+    Continue(usize),
+    Translate(usize),
 }
 
 impl Message {
@@ -152,6 +659,154 @@ impl Message {
         matches!(self.role, Role::User)
     }
 
+    #[inline]
+    const fn is_system(&self) -> bool {
+        matches!(self.role, Role::System)
+    }
+
+    /// "You", "System" or the short name of the model that wrote the message
+    fn role_label(&self) -> String {
+        match self.role {
+            Role::User => "You".to_owned(),
+            Role::System => "System".to_owned(),
+            Role::Assistant => make_short_name(&self.model_name),
+        }
+    }
+
+    /// Text of the message with the attached text files as fenced code blocks after it
+    fn content_with_files(&self) -> String {
+        let mut content = self.content.clone();
+        for file in &self.text_files {
+            if !content.is_empty() {
+                content += "\n\n";
+            }
+            content += &file.to_markdown();
+        }
+        content
+    }
+
+    /// The message as it's sent to the model, without images
+    fn to_chat_message(&self) -> ChatMessage {
+        match self.role {
+            Role::User => ChatMessage::user(self.content_with_files()),
+            Role::Assistant => ChatMessage::assistant(self.content.clone()),
+            Role::System => ChatMessage::system(self.content.clone()),
+        }
+    }
+
+    /// Content of the message without the reasoning
+    #[inline]
+    fn answer(&self) -> &str {
+        split_reasoning(&self.content).1
+    }
+
+    /// Format the answer with the "Copy as quote" template
+    fn to_quote(&self, template: &str) -> String {
+        let content = self.answer().trim();
+        let quote = content
+            .lines()
+            .map(|line| {
+                if line.is_empty() {
+                    ">".to_owned()
+                } else {
+                    format!("> {line}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let model = match self.role {
+            Role::Assistant => self.model_name.clone(),
+            _ => self.role_label(),
+        };
+        let date = self
+            .time
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d")
+            .to_string();
+        fill_template(
+            template,
+            &[
+                ("quote", &quote),
+                ("content", content),
+                ("model", &model),
+                ("date", &date),
+            ],
+        )
+    }
+
+    /// Button to copy the code block of the answer, or a menu to pick one if there are several
+    fn show_copy_code(&mut self, ui: &mut egui::Ui) {
+        const MAX_LABEL_CHARS: usize = 48;
+
+        let blocks = code_blocks(split_reasoning(&self.content).1);
+        match blocks.as_slice() {
+            [] => (),
+            [(_, code)] => {
+                let copied = self.copied_code.is_some();
+                let copy = ui
+                    .add(
+                        egui::Button::new(if copied { "✔" } else { "</>" })
+                            .small()
+                            .fill(egui::Color32::TRANSPARENT),
+                    )
+                    .on_hover_text(if copied { "Copied!" } else { "Copy code" });
+                if copy.clicked() {
+                    ui.ctx().copy_text(code.clone());
+                    self.copied_code = Some(0);
+                }
+                if !copy.hovered() {
+                    self.copied_code = None;
+                }
+            }
+            blocks => {
+                ui.menu_button("</>", |ui| {
+                    for (i, (language, code)) in blocks.iter().enumerate() {
+                        let first_line = code.lines().find(|l| !l.trim().is_empty());
+                        let mut label: String = first_line
+                            .unwrap_or_default()
+                            .trim()
+                            .chars()
+                            .take(MAX_LABEL_CHARS)
+                            .collect();
+                        if !language.is_empty() {
+                            label = format!("{language}: {label}");
+                        }
+                        let copied = self.copied_code == Some(i);
+                        let button = ui.button(if copied {
+                            format!("✔ {label}")
+                        } else {
+                            label
+                        });
+                        if button.clicked() {
+                            ui.ctx().copy_text(code.clone());
+                            self.copied_code = Some(i);
+                        } else if copied && !button.hovered() {
+                            self.copied_code = None;
+                        }
+                    }
+                })
+                .response
+                .on_hover_text("Copy a code block");
+            }
+        }
+    }
+
+    /// Pretty-printed content of a JSON mode response, `None` if it isn't valid JSON
+    fn pretty_json(&self) -> Option<String> {
+        if !self.is_json || self.is_generating {
+            return None;
+        }
+        serde_json::from_str::<serde_json::Value>(self.answer())
+            .and_then(|value| serde_json::to_string_pretty(&value))
+            .ok()
+    }
+
+    /// Average number of tokens generated per second since the response was requested
+    fn tokens_per_second(&self) -> f64 {
+        self.streamed_chunks as f64 / self.requested_at.elapsed().as_secs_f64().max(0.001)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn show(
         &mut self,
         ui: &mut egui::Ui,
@@ -159,142 +814,385 @@ impl Message {
         #[cfg(feature = "tts")] tts: SharedTts,
         idx: usize,
         prepend_buf: &mut String,
+        stop_sequences: &[String],
+        models: &[LocalModel],
+        timestamp_format: TimestampFormat,
+        quote_template: &str,
+        always_show_actions: bool,
+        translate_to: &str,
     ) -> MessageAction {
+        let top = ui.cursor().top();
+
         // message role
         let message_offset = ui
             .horizontal(|ui| {
-                if self.is_user() {
+                let offset = if self.is_user() {
                     let f = ui.label("👤").rect.left();
                     ui.label("You").rect.left() - f
+                } else if self.is_system() {
+                    let f = ui.label("⚙").rect.left();
+                    ui.label("System").rect.left() - f
                 } else {
                     let f = ui.label("🐱").rect.left();
+                    let hover_text = match self.done_reason {
+                        Some(DoneReason::Stop) if !stop_sequences.is_empty() => format!(
+                            "{}\nResponse {} (stop sequences: {})",
+                            self.model_name,
+                            DoneReason::Stop.description(),
+                            stop_sequences
+                                .iter()
+                                .map(|s| format!("{s:?}"))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                        Some(reason) => {
+                            format!("{}\nResponse {}", self.model_name, reason.description())
+                        }
+                        None => self.model_name.clone(),
+                    };
                     let offset = ui
                         .label(make_short_name(&self.model_name))
-                        .on_hover_text(&self.model_name)
+                        .on_hover_text(hover_text)
                         .rect
                         .left()
                         - f;
                     ui.add_enabled(false, egui::Label::new(&self.model_name));
                     offset
+                };
+                if let Some(time) = timestamp_format.format(self.time) {
+                    let mut hover = self.time.with_timezone(&chrono::Local).to_rfc3339();
+                    if let Some(secs) = self.generation_secs.filter(|_| !self.is_user()) {
+                        hover += &format!("\nGenerated in {secs:.1}s");
+                    }
+                    match self.rating {
+                        Some(Rating::Up) => hover += "\nRated 👍",
+                        Some(Rating::Down) => hover += "\nRated 👎",
+                        None => (),
+                    }
+                    ui.label(egui::RichText::new(time).small().weak())
+                        .on_hover_text(hover);
                 }
+                offset
             })
             .inner;
 
         // for some reason commonmark creates empty space above it when created,
         // compensate for that
-        let is_commonmark = !self.content.is_empty() && !self.is_error && !self.is_prepending;
+        let is_commonmark =
+            !self.content.is_empty() && !self.is_error && !self.is_prepending && !self.is_system();
         if is_commonmark {
             ui.add_space(-TextStyle::Body.resolve(ui.style()).size + 4.0);
         }
 
         // message content / spinner
         let mut action = MessageAction::None;
-        ui.horizontal(|ui| {
-            ui.add_space(message_offset);
-            if self.content.is_empty() && self.is_generating && !self.is_error {
-                ui.horizontal(|ui| {
-                    ui.add(egui::Spinner::new());
+        // attachments are shown right under the header of messages with only attachments
+        let attachments_only = self.content.is_empty()
+            && !(self.images.is_empty() && self.text_files.is_empty())
+            && !self.is_generating
+            && !self.is_error
+            && !self.is_prepending;
+        if !attachments_only {
+            ui.horizontal(|ui| {
+                ui.add_space(message_offset);
+                if self.content.is_empty() && self.is_generating && !self.is_error {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::Spinner::new());
 
-                    // show time spent waiting for response
-                    ui.add_enabled(
-                        false,
-                        egui::Label::new(format!(
-                            "{:.1}s",
-                            self.requested_at.elapsed().as_secs_f64()
-                        )),
-                    )
-                });
-            } else if self.is_error {
-                ui.label("An error occurred while requesting completion");
-                if ui
-                    .button("Retry")
-                    .on_hover_text(
-                        "Try to generate a response again. Make sure you have Ollama running",
-                    )
-                    .clicked()
-                {
-                    action = MessageAction::Retry(idx);
-                }
-            } else if self.is_prepending {
-                let textedit = ui.add(
-                    egui::TextEdit::multiline(prepend_buf).hint_text("Prepend text to response…"),
-                );
-                macro_rules! cancel_prepend {
-                    () => {
-                        self.is_prepending = false;
-                        prepend_buf.clear();
-                    };
-                }
-                if textedit.lost_focus() && ui.input(|i| i.key_pressed(Key::Escape)) {
-                    cancel_prepend!();
-                }
-                ui.vertical(|ui| {
+                        // show time spent waiting for response
+                        ui.add_enabled(
+                            false,
+                            egui::Label::new(format!(
+                                "{:.1}s",
+                                self.requested_at.elapsed().as_secs_f64()
+                            )),
+                        )
+                    });
+                } else if self.is_error {
+                    let error = self.error.as_ref();
+                    ui.label(error.map_or(
+                        "An error occurred while requesting completion",
+                        AppError::title,
+                    ));
+                    if let Some(suggestion) = error.and_then(AppError::suggestion) {
+                        ui.weak(suggestion);
+                    }
                     if ui
-                        .button("🔄 Regenerate")
+                        .button("Retry")
                         .on_hover_text(
-                            "Generate the response again, \
-                            the LLM will start after any prepended text",
+                            "Try to generate a response again. Make sure you have Ollama running",
                         )
                         .clicked()
                     {
-                        self.content = prepend_buf.clone();
-                        self.is_prepending = false;
-                        self.is_generating = true;
-                        action = MessageAction::Regenerate(idx);
+                        action = MessageAction::Retry(idx);
                     }
-                    if !prepend_buf.is_empty()
-                        && ui
-                            .button("\u{270f} Edit")
-                            .on_hover_text(
-                                "Edit the message in the context, but don't regenerate it",
-                            )
-                            .clicked()
-                    {
-                        self.content = prepend_buf.clone();
-                        cancel_prepend!();
+                } else if self.is_prepending {
+                    let textedit = ui.add(
+                        egui::TextEdit::multiline(prepend_buf)
+                            .hint_text("Prepend text to response…"),
+                    );
+                    macro_rules! cancel_prepend {
+                        () => {
+                            self.is_prepending = false;
+                            self.regenerate_model = None;
+                            prepend_buf.clear();
+                        };
                     }
-                    if ui.button("❌ Cancel").clicked() {
+                    let composing = ime_composing(ui, &textedit);
+                    if textedit.lost_focus()
+                        && !composing
+                        && ui.input(|i| i.key_pressed(Key::Escape))
+                    {
                         cancel_prepend!();
                     }
-                });
-            } else {
-                CommonMarkViewer::new().max_image_width(Some(512)).show(
-                    ui,
-                    commonmark_cache,
-                    &self.content,
-                );
-            }
-        });
-
-        // images
-        if !self.images.is_empty() {
-            if is_commonmark {
-                ui.add_space(4.0);
-            }
-            ui.horizontal(|ui| {
-                ui.add_space(message_offset);
-                egui::ScrollArea::horizontal().show(ui, |ui| {
-                    ui.horizontal(|ui| {
-                        crate::image::show_images(ui, &mut self.images, false);
+                    ui.vertical(|ui| {
+                        if ui
+                            .button("🔄 Regenerate")
+                            .on_hover_text(
+                                "Generate the response again, \
+                                the LLM will start after any prepended text",
+                            )
+                            .clicked()
+                        {
+                            self.content = prepend_buf.clone();
+                            self.is_prepending = false;
+                            self.is_generating = true;
+                            if let Some(model) = self.regenerate_model.take() {
+                                self.model_name = model;
+                            }
+                            action = MessageAction::Regenerate(idx);
+                        }
+                        if !models.is_empty() {
+                            let selected = self
+                                .regenerate_model
+                                .clone()
+                                .unwrap_or_else(|| self.model_name.clone());
+                            egui::ComboBox::from_id_source(("regenerate_model", idx))
+                                .selected_text(make_short_name(&selected))
+                                .show_ui(ui, |ui| {
+                                    for model in models {
+                                        if ui
+                                            .selectable_label(selected == model.name, &model.name)
+                                            .clicked()
+                                        {
+                                            self.regenerate_model = (model.name != self.model_name)
+                                                .then(|| model.name.clone());
+                                        }
+                                    }
+                                })
+                                .response
+                                .on_hover_text("Model to regenerate the response with");
+                        }
+                        if !prepend_buf.is_empty()
+                            && ui
+                                .button("\u{270f} Edit")
+                                .on_hover_text(
+                                    "Edit the message in the context, but don't regenerate it",
+                                )
+                                .clicked()
+                        {
+                            self.content = prepend_buf.clone();
+                            self.translation = None;
+                            cancel_prepend!();
+                        }
+                        if ui.button("❌ Cancel").clicked() {
+                            cancel_prepend!();
+                        }
                     });
-                })
+                } else if self.is_system() {
+                    ui.label(egui::RichText::new(&self.content).italics().weak());
+                } else if self.show_raw {
+                    ui.add(
+                        egui::TextEdit::multiline(&mut self.content.as_str())
+                            .font(TextStyle::Monospace)
+                            .desired_width(f32::INFINITY),
+                    );
+                } else {
+                    let (reasoning, answer) = split_reasoning(&self.content);
+                    ui.vertical(|ui| {
+                        if let Some((reasoning, closed)) = reasoning {
+                            let chars = reasoning.chars().count();
+                            egui::CollapsingHeader::new(if closed {
+                                format!("Reasoning ({chars} chars)")
+                            } else {
+                                format!("Reasoning… ({chars} chars)")
+                            })
+                            .id_source(("message_reasoning", idx))
+                            .default_open(false)
+                            .show(ui, |ui| {
+                                ui.label(egui::RichText::new(reasoning).weak());
+                            });
+                        }
+                        if let Some(json) = self.pretty_json() {
+                            CommonMarkViewer::new().show(
+                                ui,
+                                commonmark_cache,
+                                &format!("```json\n{json}\n```"),
+                            );
+                        } else if !answer.is_empty() {
+                            CommonMarkViewer::new().max_image_width(Some(512)).show(
+                                ui,
+                                commonmark_cache,
+                                answer,
+                            );
+                        }
+                    });
+                }
             });
-            ui.add_space(8.0);
-        }
-
-        if self.is_prepending {
-            return action;
         }
 
-        // copy buttons and such
-        let shift_held = !ui.ctx().wants_keyboard_input() && ui.input(|i| i.modifiers.shift);
-        if !self.is_generating
-            && !self.content.is_empty()
-            && (!self.is_user() || shift_held)
-            && !self.is_error
-        {
+        // translation
+        if self.is_translating {
+            ui.horizontal(|ui| {
+                ui.add_space(message_offset);
+                ui.add(egui::Spinner::new().size(12.0));
+                ui.label(egui::RichText::new("Translating…").small().weak());
+            });
+        } else if let Some(translation) = &self.translation {
+            let mut remove = false;
+            ui.horizontal(|ui| {
+                ui.add_space(message_offset);
+                egui::CollapsingHeader::new(format!("Translation ({})", translation.language))
+                    .id_source(("message_translation", idx))
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        CommonMarkViewer::new().show(ui, commonmark_cache, &translation.text);
+                        ui.horizontal(|ui| {
+                            let button = |icon| {
+                                egui::Button::new(icon)
+                                    .small()
+                                    .fill(egui::Color32::TRANSPARENT)
+                            };
+                            if ui
+                                .add(button("🗐"))
+                                .on_hover_text("Copy translation")
+                                .clicked()
+                            {
+                                ui.ctx().copy_text(translation.text.clone());
+                            }
+                            #[cfg(feature = "tts")]
+                            if ui
+                                .add(button("🔊"))
+                                .on_hover_text("Read the translation out loud")
+                                .clicked()
+                            {
+                                self.is_speaking = true;
+                                tts_control(tts.clone(), translation.text.clone(), true);
+                            }
+                            remove = ui
+                                .add(button("🗑"))
+                                .on_hover_text("Remove translation")
+                                .clicked();
+                        });
+                    });
+            });
+            if remove {
+                self.translation = None;
+            }
+        }
+
+        // text files
+        if !self.text_files.is_empty() {
+            ui.horizontal_wrapped(|ui| {
+                ui.add_space(message_offset);
+                for file in &self.text_files {
+                    file.show(ui, false);
+                }
+            });
+        }
+
+        // images
+        if !self.images.is_empty() {
+            if is_commonmark {
+                ui.add_space(4.0);
+            }
+            ui.horizontal(|ui| {
+                ui.add_space(message_offset);
+                egui::ScrollArea::horizontal().show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        crate::image::show_images(ui, &mut self.images, false, None);
+                    });
+                })
+            });
+            ui.add_space(8.0);
+        }
+
+        // generation speed
+        if self.is_generating && !self.content.is_empty() && !self.is_error {
+            ui.horizontal(|ui| {
+                ui.add_space(message_offset);
+                ui.add(egui::Spinner::new().size(12.0));
+                ui.add_enabled(
+                    false,
+                    egui::Label::new(
+                        egui::RichText::new(format!("~{:.1} tok/s", self.tokens_per_second()))
+                            .small(),
+                    ),
+                );
+            });
+        }
+
+        if self.is_prepending {
+            return action;
+        }
+
+        if !self.is_generating && self.done_reason == Some(DoneReason::Interrupted) {
+            ui.horizontal(|ui| {
+                ui.add_space(message_offset);
+                ui.label(
+                    egui::RichText::new("⚠ Interrupted when the app was closed")
+                        .small()
+                        .color(ui.visuals().warn_fg_color),
+                );
+                if ui
+                    .small_button("Restart")
+                    .on_hover_text("Generate the response again")
+                    .clicked()
+                {
+                    self.is_generating = true;
+                    self.done_reason = None;
+                    action = MessageAction::Regenerate(idx);
+                }
+                if !self.content.is_empty()
+                    && ui
+                        .small_button("Continue")
+                        .on_hover_text("Continue generating from where the response stopped")
+                        .clicked()
+                {
+                    action = MessageAction::Continue(idx);
+                }
+            });
+        }
+
+        if !self.is_generating && self.done_reason == Some(DoneReason::Length) {
+            ui.horizontal(|ui| {
+                ui.add_space(message_offset);
+                ui.label(
+                    egui::RichText::new("⚠ Response hit the token limit")
+                        .small()
+                        .color(ui.visuals().warn_fg_color),
+                );
+                if ui
+                    .small_button("Continue")
+                    .on_hover_text("Continue generating from where the response stopped")
+                    .clicked()
+                {
+                    action = MessageAction::Continue(idx);
+                }
+            });
+        }
+
+        // copy buttons and such, the row of user messages is always laid out and only hidden
+        // so revealing it doesn't change the height of the message
+        let show_actions = !self.is_user()
+            || always_show_actions
+            || self.hovered
+            || ui.input(|i| i.modifiers.shift);
+        if !self.is_generating && !self.content.is_empty() && !self.is_error {
+            let has_reasoning = split_reasoning(&self.content).0.is_some();
             ui.add_space(2.0);
             ui.horizontal(|ui| {
+                ui.set_visible(show_actions);
                 ui.add_space(message_offset);
                 let copy = ui
                     .add(
@@ -304,17 +1202,67 @@ impl Message {
                     )
                     .on_hover_text(if self.clicked_copy {
                         "Copied!"
+                    } else if has_reasoning {
+                        "Copy answer. Right click to copy with reasoning or as a quote"
                     } else {
-                        "Copy message"
+                        "Copy message. Right click to copy as a quote"
                     });
                 if copy.clicked() {
-                    ui.ctx().copy_text(self.content.clone());
+                    ui.ctx().copy_text(self.answer().to_owned());
                     self.clicked_copy = true;
                 }
+                copy.context_menu(|ui| {
+                    if ui.button("Copy answer").clicked() {
+                        ui.ctx().copy_text(self.answer().to_owned());
+                        ui.close_menu();
+                    }
+                    if has_reasoning && ui.button("Copy with reasoning").clicked() {
+                        ui.ctx().copy_text(self.content.clone());
+                        ui.close_menu();
+                    }
+                    if ui.button("Copy as quote").clicked() {
+                        ui.ctx().copy_text(self.to_quote(quote_template));
+                        ui.close_menu();
+                    }
+                });
                 self.clicked_copy = self.clicked_copy && copy.hovered();
 
-                #[cfg(feature = "tts")]
+                self.show_copy_code(ui);
+
+                if !self.is_system()
+                    && ui
+                        .add(
+                            egui::Button::new("⌨")
+                                .small()
+                                .selected(self.show_raw)
+                                .fill(egui::Color32::TRANSPARENT),
+                        )
+                        .on_hover_text(if self.show_raw {
+                            "Render the Markdown"
+                        } else {
+                            "Show the raw text"
+                        })
+                        .clicked()
                 {
+                    self.show_raw = !self.show_raw;
+                }
+
+                if let Some(json) = self.pretty_json() {
+                    if ui
+                        .add(
+                            egui::Button::new("{ }")
+                                .small()
+                                .fill(egui::Color32::TRANSPARENT),
+                        )
+                        .on_hover_text("Copy JSON")
+                        .clicked()
+                    {
+                        ui.ctx().copy_text(json);
+                    }
+                }
+
+                #[cfg(feature = "tts")]
+                if !self.is_system() {
                     let speak = ui
                         .add(
                             egui::Button::new(if self.is_speaking { "…" } else { "🔊" })
@@ -329,15 +1277,62 @@ impl Message {
                             tts_control(tts, String::new(), false);
                         } else {
                             self.is_speaking = true;
-                            tts_control(tts, self.content.clone(), true);
+                            tts_control(tts, self.answer().to_owned(), true);
                         }
                     } else if speak.secondary_clicked() {
                         self.is_speaking = true;
-                        tts_control(tts, self.content.clone(), true);
+                        tts_control(tts, self.answer().to_owned(), true);
+                    }
+                }
+
+                if !self.is_user() && !self.is_system() {
+                    for (rating, icon, hover) in [
+                        (Rating::Up, "👍", "Good response"),
+                        (Rating::Down, "👎", "Bad response"),
+                    ] {
+                        let rated = self.rating == Some(rating);
+                        if ui
+                            .add(
+                                egui::Button::new(icon)
+                                    .small()
+                                    .selected(rated)
+                                    .fill(egui::Color32::TRANSPARENT),
+                            )
+                            .on_hover_text(hover)
+                            .clicked()
+                        {
+                            self.rating = (!rated).then_some(rating);
+                        }
+                    }
+                }
+
+                if !self.is_user() && !self.is_system() {
+                    let language = translate_to.trim();
+                    let translated = self
+                        .translation
+                        .as_ref()
+                        .is_some_and(|t| t.language == language);
+                    if ui
+                        .add_enabled(
+                            !language.is_empty() && !translated && !self.is_translating,
+                            egui::Button::new("🌐")
+                                .small()
+                                .fill(egui::Color32::TRANSPARENT),
+                        )
+                        .on_hover_text(format!("Translate to {language}"))
+                        .on_disabled_hover_text(if language.is_empty() {
+                            "Set the language to translate to in the settings".to_owned()
+                        } else {
+                            format!("Translated to {language}")
+                        })
+                        .clicked()
+                    {
+                        action = MessageAction::Translate(idx);
                     }
                 }
 
                 if !self.is_user()
+                    && !self.is_system()
                     && prepend_buf.is_empty()
                     && ui
                         .add(
@@ -353,6 +1348,8 @@ impl Message {
                 }
             });
         }
+        let rect = egui::Rect::from_x_y_ranges(ui.max_rect().x_range(), top..=ui.cursor().top());
+        self.hovered = ui.rect_contains_pointer(rect);
         ui.add_space(12.0);
 
         action
@@ -360,8 +1357,17 @@ impl Message {
 }
 
 // <completion progress, final completion, error>
-type CompletionFlower = CompactFlower<(usize, String), (usize, String), (usize, String)>;
-type CompletionFlowerHandle = CompactHandle<(usize, String), (usize, String), (usize, String)>;
+type CompletionFlower =
+    CompactFlower<(usize, String), (usize, String, DoneReason), (usize, AppError)>;
+type CompletionFlowerHandle =
+    CompactHandle<(usize, String), (usize, String, DoneReason), (usize, AppError)>;
+
+/// How a response ended, returned by [`Chat::poll_flower`]. Stopped responses aren't reported.
+pub enum Finished {
+    /// The answer, without the reasoning
+    Response(String),
+    Failed(AppError),
+}
 
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
@@ -382,7 +1388,403 @@ pub struct Chat {
     virtual_list: VirtualList,
     pub model_picker: ModelPicker,
     pub images: Vec<PathBuf>,
+    /// Text files attached to the chatbox
+    pub text_files: Vec<TextFile>,
     prepend_buf: String,
+    /// Read responses aloud when they finish generating, `None` to follow the global setting
+    #[cfg(feature = "tts")]
+    pub auto_read: Option<bool>,
+    /// Offset into the generating message up to which it was queued for speaking, and
+    /// whether anything was queued yet
+    #[cfg(feature = "tts")]
+    #[serde(skip)]
+    tts_stream: Option<(usize, bool)>,
+    /// Scratchpad chat that is never saved to disk
+    #[serde(skip)]
+    pub ephemeral: bool,
+    pub system_prompt: String,
+    /// Error of the last command typed into the chatbox
+    #[serde(skip)]
+    command_error: Option<String>,
+    #[serde(skip)]
+    confirm_clear: bool,
+    #[serde(skip)]
+    request_preview: Option<RequestPreview>,
+    /// Estimated token counts of the messages, with the message count and total length
+    /// they were computed for
+    #[serde(skip)]
+    context_estimate: ((usize, usize), Vec<usize>),
+    pub context_strategy: ContextStrategy,
+    /// Send the chatbox as is to the generate endpoint, without the chat history or template
+    pub raw_mode: bool,
+    /// Sent when the response that was generating finishes
+    #[serde(skip)]
+    queued_message: Option<QueuedMessage>,
+    /// Follows the global setting
+    #[serde(skip)]
+    pub auto_trim: AutoTrim,
+    /// Follows the global setting
+    #[serde(skip)]
+    pub timestamp_format: TimestampFormat,
+    /// Follows the global setting
+    #[serde(skip)]
+    pub send_key: SendKey,
+    /// Follows the global setting
+    #[serde(skip)]
+    pub quote_template: String,
+    /// Follows the global setting
+    #[serde(skip)]
+    pub image_prompt: String,
+    /// Follows the global setting
+    #[serde(skip)]
+    pub confirm_duplicates: bool,
+    /// Follows the global setting
+    #[serde(skip)]
+    pub always_show_actions: bool,
+    /// Follows the global setting, `None` to use the full width of the window
+    #[serde(skip)]
+    pub max_width: Option<f32>,
+    /// Follows the global setting
+    #[serde(skip)]
+    pub group_exchanges: bool,
+    /// The chatbox repeats the last message, waiting for the user to send it again or cancel
+    #[serde(skip)]
+    duplicate_pending: bool,
+    /// Send the next message even if it repeats the last one
+    #[serde(skip)]
+    allow_duplicate: bool,
+    /// Follows the global setting, 0 if messages of any size are sent
+    #[serde(skip)]
+    pub max_message_bytes: usize,
+    /// Estimated request size of the chatbox contents, waiting for the user to split the
+    /// message, send it anyway or cancel
+    #[serde(skip)]
+    oversized_pending: Option<usize>,
+    /// Follows the global setting, `None` if images are sent in their original size
+    #[serde(skip)]
+    pub max_image_dimension: Option<u32>,
+    /// Send the attached images in their original size, even if they are larger than
+    /// `max_image_dimension`
+    #[serde(skip)]
+    send_original_images: bool,
+    /// Send the next message even if it's larger than `max_message_bytes`
+    #[serde(skip)]
+    allow_oversized: bool,
+    /// Sizes of the attached images as base64, read when they're attached
+    #[serde(skip)]
+    image_sizes: HashMap<PathBuf, u64>,
+    /// Summary of the messages left out of the context, with the key of the messages it was
+    /// made for. Written by the completion task.
+    #[serde(skip)]
+    trimmed_summary: Arc<RwLock<Option<((usize, usize), String)>>>,
+    /// Summarizing the left out messages failed, the response was generated without them.
+    /// Written by the completion task.
+    #[serde(skip)]
+    summary_error: Arc<RwLock<Option<AppError>>>,
+    #[serde(skip)]
+    image_cache: ImageCache,
+    /// Translations that finished since the last frame. Written by the translation tasks.
+    #[serde(skip)]
+    finished_translations: Arc<RwLock<Vec<FinishedTranslation>>>,
+    /// Follows the global setting
+    #[serde(skip)]
+    pub translation_language: String,
+    /// Follows the global setting, empty to translate with the selected model
+    #[serde(skip)]
+    pub translation_model: String,
+    /// Prompts are sent to several models instead of continuing the chat
+    #[serde(skip)]
+    comparison: Option<Comparison>,
+    /// Sent prompts, oldest first
+    prompt_history: Vec<String>,
+    /// Index of the recalled prompt in `prompt_history`
+    #[serde(skip)]
+    history_pos: Option<usize>,
+    /// Chatbox contents from before browsing the prompt history
+    #[serde(skip)]
+    history_draft: String,
+    /// The chatbox cursor was on the first and on the last row in the previous frame
+    #[serde(skip)]
+    cursor_rows: (bool, bool),
+    /// Filter of the prompt library popup
+    #[serde(skip)]
+    prompt_filter: String,
+    /// Name the chatbox is saved to the prompt library with
+    #[serde(skip)]
+    prompt_name: String,
+    /// Images removed from the chatbox with their indices, and when they were removed
+    #[serde(skip)]
+    removed_images: Option<(Vec<(usize, PathBuf)>, Instant)>,
+    #[serde(skip)]
+    scroll: ScrollState,
+    /// A response finished since the chat was last selected
+    #[serde(skip)]
+    pub has_unread: bool,
+}
+
+/// Where the messages are scrolled to, so scrolling up to reread something isn't undone
+/// by streamed responses
+struct ScrollState {
+    /// The messages are scrolled to the bottom
+    at_bottom: bool,
+    /// Follow new content, off after the user scrolls up until they're back at the bottom
+    stick: bool,
+    /// Scroll offset of the previous frame
+    offset: f32,
+    /// Largest scroll offset of the previous frame
+    max_offset: f32,
+    /// Scroll to the last message on the next frame
+    jump: bool,
+}
+
+impl Default for ScrollState {
+    fn default() -> Self {
+        Self {
+            at_bottom: true,
+            stick: true,
+            offset: 0.0,
+            max_offset: 0.0,
+            jump: false,
+        }
+    }
+}
+
+/// Messages left out of the context that should be summarized
+struct TrimmedMessages {
+    /// Number of messages and their total length
+    key: (usize, usize),
+    messages: Vec<ChatMessage>,
+}
+
+/// Everything sent to Ollama to generate a response
+struct CompletionRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    /// Images of `messages`, converted by the task sending the request
+    images: Vec<MessageImages>,
+    /// Summarized before the request is sent, the summary goes after the system prompt
+    trimmed: Option<TrimmedMessages>,
+    options: GenerationOptions,
+    template: Option<String>,
+    keep_alive: Option<KeepAlive>,
+    num_predict: Option<i32>,
+    format: Option<ResponseFormat>,
+    /// Sent to the generate endpoint without templating, `messages` only has the prompt
+    /// and the part of the response that was already generated
+    raw: bool,
+}
+
+/// Images attached to a message of a request
+#[derive(Clone)]
+struct MessageImages {
+    /// Index of the message in the request
+    message: usize,
+    paths: Vec<PathBuf>,
+    max_dimension: Option<u32>,
+}
+
+/// Images converted for sending, shared with the tasks sending the requests so each image is
+/// only decoded and resized once
+#[derive(Clone, Default)]
+struct ImageCache {
+    converted: Arc<RwLock<HashMap<(PathBuf, Option<u32>), Image>>>,
+    /// Names of the images that couldn't be converted since the last frame
+    failed: Arc<RwLock<Vec<String>>>,
+}
+
+impl ImageCache {
+    /// Set the images of `messages`, converting the ones that weren't sent before. Images
+    /// that can't be converted are left out.
+    fn attach(&self, messages: &mut [ChatMessage], images: &[MessageImages]) {
+        for images in images {
+            let Some(message) = messages.get_mut(images.message) else {
+                continue;
+            };
+            let converted: Vec<Image> = images
+                .paths
+                .iter()
+                .filter_map(|path| self.convert(path, images.max_dimension))
+                .collect();
+            message.images = (!converted.is_empty()).then_some(converted);
+        }
+    }
+
+    fn convert(&self, path: &Path, max_dimension: Option<u32>) -> Option<Image> {
+        let key = (path.to_path_buf(), max_dimension);
+        if let Some(image) = self.converted.read().get(&key) {
+            return Some(image.clone());
+        }
+        match crate::image::convert_image(path, max_dimension) {
+            Ok(image) => {
+                self.converted.write().insert(key, image.clone());
+                Some(image)
+            }
+            Err(e) => {
+                log::error!("failed to convert image `{}`: {e}", path.display());
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                self.failed.write().push(format!("`{name}`: {e}"));
+                None
+            }
+        }
+    }
+
+    /// Toast for the images that couldn't be converted since the last call
+    fn take_failed_toast(&self) -> Option<Toast> {
+        let failed = std::mem::take(&mut *self.failed.write());
+        (!failed.is_empty()).then(|| {
+            Toast::error(format!(
+                "Some images couldn't be read and weren't sent:\n{}",
+                failed.join("\n")
+            ))
+        })
+    }
+}
+
+/// Chatbox contents sent while a response was generating, see [`Chat::queue_message`]
+struct QueuedMessage {
+    text: String,
+    images: Vec<PathBuf>,
+    text_files: Vec<TextFile>,
+    original_images: bool,
+}
+
+/// Request built from the chatbox without sending it, see [`Chat::preview_request`]
+struct RequestPreview {
+    /// Chatbox contents it was built from, for the prompt history
+    chatbox: String,
+    prompt: String,
+    images: Vec<PathBuf>,
+    text_files: Vec<TextFile>,
+    original_images: bool,
+    request: CompletionRequest,
+}
+
+/// Response of one model in a [`Comparison`]
+struct ComparisonColumn {
+    model: String,
+    response: String,
+    flower: CompletionFlower,
+    stop_generating: Arc<AtomicBool>,
+    requested_at: Instant,
+    done_reason: Option<DoneReason>,
+    error: Option<AppError>,
+}
+
+/// The same prompt sent to several models at once, with the responses shown side by side
+struct Comparison {
+    prompt: String,
+    images: Vec<PathBuf>,
+    columns: Vec<ComparisonColumn>,
+}
+
+impl Comparison {
+    fn new(id: usize, models: Vec<String>) -> Self {
+        Self {
+            prompt: String::new(),
+            images: Vec::new(),
+            columns: models
+                .into_iter()
+                .map(|model| ComparisonColumn {
+                    model,
+                    response: String::new(),
+                    flower: CompletionFlower::new(id),
+                    stop_generating: Arc::new(AtomicBool::new(false)),
+                    requested_at: Instant::now(),
+                    done_reason: None,
+                    error: None,
+                })
+                .collect(),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.columns.iter().any(|c| c.flower.is_active())
+    }
+
+    fn stop_generating(&self) {
+        for column in self.columns.iter().filter(|c| c.flower.is_active()) {
+            column.stop_generating.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Request a response from every model, `messages` end with the prompt
+    #[allow(clippy::too_many_arguments)]
+    fn send(
+        &mut self,
+        ollama: &Ollama,
+        prompt: String,
+        images: Vec<PathBuf>,
+        messages: Vec<ChatMessage>,
+        message_images: Vec<MessageImages>,
+        image_cache: &ImageCache,
+        model_picker: &ModelPicker,
+    ) {
+        self.prompt = prompt;
+        self.images = images;
+        for (index, column) in self.columns.iter_mut().enumerate() {
+            column.response.clear();
+            column.requested_at = Instant::now();
+            column.done_reason = None;
+            column.error = None;
+            column.stop_generating.store(false, Ordering::SeqCst);
+
+            let handle = column.flower.handle();
+            let ollama = ollama.clone();
+            let mut messages = messages.clone();
+            let message_images = message_images.clone();
+            let image_cache = image_cache.clone();
+            let stop_generating = column.stop_generating.clone();
+            let model = column.model.clone();
+            let generation_options = model_picker.get_generation_options();
+            let keep_alive = model_picker.get_keep_alive();
+            let num_predict = model_picker.num_predict();
+            let format = model_picker.response_format();
+            tokio::spawn(async move {
+                handle.activate();
+                tokio::task::block_in_place(|| image_cache.attach(&mut messages, &message_images));
+                // the template is model specific, don't use it for other models
+                let _ = request_completion(
+                    ollama,
+                    messages,
+                    &handle,
+                    stop_generating,
+                    model,
+                    generation_options,
+                    None,
+                    keep_alive,
+                    num_predict,
+                    format,
+                    index,
+                )
+                .await
+                .map_err(|e| {
+                    log::error!("failed to request comparison completion: {e}");
+                    handle.error((index, AppError::from_error(&*e)));
+                });
+            });
+        }
+    }
+
+    fn poll(&mut self) {
+        for column in &mut self.columns {
+            if !column.flower.is_active() {
+                continue;
+            }
+            column
+                .flower
+                .extract(|(_, progress)| column.response += progress.as_str())
+                .finalize(|result| match result {
+                    Ok((_, response, done_reason)) => {
+                        column.response = response;
+                        column.done_reason = Some(done_reason);
+                    }
+                    Err(Compact::Suppose((_, e))) => column.error = Some(e),
+                    Err(Compact::Panicked(e)) => {
+                        column.error = Some(AppError::Panicked(format!("Tokio task panicked: {e}")))
+                    }
+                });
+        }
+    }
 }
 
 impl Default for Chat {
@@ -399,11 +1801,138 @@ impl Default for Chat {
             virtual_list: VirtualList::new(),
             model_picker: ModelPicker::default(),
             images: Vec::new(),
+            text_files: Vec::new(),
             prepend_buf: String::new(),
+            #[cfg(feature = "tts")]
+            auto_read: None,
+            #[cfg(feature = "tts")]
+            tts_stream: None,
+            ephemeral: false,
+            system_prompt: String::new(),
+            command_error: None,
+            confirm_clear: false,
+            request_preview: None,
+            context_estimate: Default::default(),
+            context_strategy: ContextStrategy::default(),
+            raw_mode: false,
+            queued_message: None,
+            auto_trim: AutoTrim::default(),
+            timestamp_format: TimestampFormat::default(),
+            send_key: SendKey::default(),
+            quote_template: String::new(),
+            image_prompt: String::new(),
+            confirm_duplicates: true,
+            always_show_actions: false,
+            group_exchanges: false,
+            max_width: None,
+            duplicate_pending: false,
+            allow_duplicate: false,
+            max_message_bytes: 0,
+            max_image_dimension: None,
+            send_original_images: false,
+            oversized_pending: None,
+            allow_oversized: false,
+            image_sizes: HashMap::new(),
+            trimmed_summary: Arc::default(),
+            summary_error: Arc::default(),
+            image_cache: ImageCache::default(),
+            finished_translations: Arc::default(),
+            translation_language: String::new(),
+            translation_model: String::new(),
+            comparison: None,
+            prompt_history: Vec::new(),
+            history_pos: None,
+            history_draft: String::new(),
+            cursor_rows: (true, true),
+            prompt_filter: String::new(),
+            prompt_name: String::new(),
+            removed_images: None,
+            scroll: ScrollState::default(),
+            has_unread: false,
         }
     }
 }
 
+/// The chatbox panel is never shorter than this, enough for one line of text
+const MIN_CHATBOX_PANEL_HEIGHT: f32 = 48.0;
+/// Space left for the messages above the chatbox panel
+const MIN_MESSAGES_HEIGHT: f32 = 96.0;
+/// Attached images are only counted instead of showing thumbnails in shorter windows
+const COMPACT_IMAGES_WINDOW_HEIGHT: f32 = 480.0;
+
+/// Tallest the chatbox panel can get, `max_fraction` of the window but always leaving
+/// some room for the messages
+fn max_chatbox_panel_height(avail_height: f32, max_fraction: f32) -> f32 {
+    (avail_height * max_fraction + 24.0)
+        .min(avail_height - MIN_MESSAGES_HEIGHT)
+        .max(MIN_CHATBOX_PANEL_HEIGHT)
+}
+
+/// Number of sent prompts remembered in each chat
+const MAX_PROMPT_HISTORY: usize = 100;
+/// Sending the same message again within this time asks for confirmation
+const DUPLICATE_SEND_WINDOW: Duration = Duration::from_secs(10);
+
+/// Tokens reserved for the summary of the messages left out of the context
+const SUMMARY_TOKENS: usize = 256;
+
+/// Summarized messages are left out in steps of this many, so a summary is reused for a few
+/// turns instead of being made again for every response
+const SUMMARY_STEP: usize = 8;
+
+const SUMMARIZE_PROMPT: &str = "Summarize the conversation so far in a few sentences. \
+    Keep names, facts, decisions and open questions, and don't add anything else.";
+
+/// System message with the summary of the messages left out of the context
+fn summary_note(summary: &str) -> ChatMessage {
+    ChatMessage::system(format!(
+        "Summary of the earlier conversation, which was left out to fit into the context: \
+        {summary}"
+    ))
+}
+
+async fn summarize_messages(
+    ollama: &Ollama,
+    model: String,
+    mut messages: Vec<ChatMessage>,
+) -> Result<String> {
+    log::info!("summarizing {} trimmed messages...", messages.len());
+    messages.push(ChatMessage::user(SUMMARIZE_PROMPT.to_owned()));
+    let response = ollama
+        .send_chat_messages(ChatMessageRequest::new(model, messages))
+        .await?;
+    Ok(response
+        .message
+        .map(|m| m.content.trim().to_owned())
+        .unwrap_or_default())
+}
+
+const TRANSLATE_PROMPT: &str = "Translate the text from the user to {language}. Keep the \
+    Markdown formatting, code and names as they are, and reply with only the translation.";
+
+/// Translation of a response, sent back to the chat by the task that made it
+struct FinishedTranslation {
+    index: usize,
+    /// Answer that was translated, the translation is dropped if the response changed
+    content: String,
+    language: String,
+    result: Result<String, AppError>,
+}
+
+async fn translate(ollama: &Ollama, model: String, language: &str, text: String) -> Result<String> {
+    let messages = vec![
+        ChatMessage::system(TRANSLATE_PROMPT.replace("{language}", language)),
+        ChatMessage::user(text),
+    ];
+    let response = ollama
+        .send_chat_messages(ChatMessageRequest::new(model, messages))
+        .await?;
+    Ok(response
+        .message
+        .map(|m| m.content.trim().to_owned())
+        .unwrap_or_default())
+}
+
 #[allow(clippy::too_many_arguments)]
 async fn request_completion(
     ollama: Ollama,
@@ -413,6 +1942,9 @@ async fn request_completion(
     selected_model: String,
     options: GenerationOptions,
     template: Option<String>,
+    keep_alive: Option<KeepAlive>,
+    num_predict: Option<i32>,
+    format: Option<ResponseFormat>,
     index: usize,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     log::info!(
@@ -438,43 +1970,232 @@ async fn request_completion(
     if let Some(template) = template {
         request = request.template(template);
     }
-    let mut stream: ChatMessageResponseStream = ollama.send_chat_messages_stream(request).await?;
+    if let Some(keep_alive) = keep_alive {
+        request = request.keep_alive(keep_alive);
+    }
+    if let Some(format) = format {
+        request = request.format(format.into());
+    }
+    // ollama-rs doesn't expose the done reason of streamed responses, read them ourselves
+    let mut body = serde_json::to_value(&request)?;
+    body["stream"] = true.into();
+    let mut res = post_streamed(ollama.url().join("api/chat")?, &body).await?;
 
     log::info!("reading response...");
 
     let mut response = String::new();
     let mut is_whitespace = true;
+    let mut done_reason = DoneReason::Stop;
+    let mut buf = Vec::new();
 
-    while let Some(Ok(res)) = stream.next().await {
-        if let Some(msg) = res.message {
-            if is_whitespace && msg.content.trim().is_empty() {
-                continue;
-            }
-            let content = if is_whitespace {
-                msg.content.trim_start()
-            } else {
-                &msg.content
-            };
-            is_whitespace = false;
+    while let Some(line) = next_json_line(&mut res, &mut buf).await? {
+        let chunk: ChatChunk = serde_json::from_slice(&line)?;
+        if let Some(error) = chunk.error {
+            return Err(error.into());
+        }
+        if chunk.done {
+            done_reason =
+                parse_done_reason(chunk.done_reason.as_deref(), chunk.eval_count, num_predict);
+            break;
+        }
+        let Some(msg) = chunk.message else {
+            continue;
+        };
+        if is_whitespace && msg.content.trim().is_empty() {
+            continue;
+        }
+        let content = if is_whitespace {
+            msg.content.trim_start()
+        } else {
+            &msg.content
+        };
+        is_whitespace = false;
 
-            // send message to gui thread
-            handle.send((index, content.to_string()));
-            response += content;
+        // send message to gui thread
+        handle.send((index, content.to_string()));
+        response += content;
 
-            if stop_generating.load(Ordering::SeqCst) {
-                log::info!("stopping generation");
-                drop(stream);
-                stop_generating.store(false, Ordering::SeqCst);
-                break;
-            }
+        if stop_generating.load(Ordering::SeqCst) {
+            log::info!("stopping generation");
+            done_reason = DoneReason::Aborted;
+            stop_generating.store(false, Ordering::SeqCst);
+            break;
+        }
+    }
+
+    log::info!(
+        "completion request complete, response length: {}, done reason: {done_reason:?}",
+        response.len()
+    );
+    handle.success((index, prepend + response.trim(), done_reason));
+    Ok(())
+}
+
+/// Why a response ended, from the `done_reason` of its last chunk. Ollama versions that
+/// don't send it leave guessing a hit token limit from the number of tokens.
+fn parse_done_reason(
+    reason: Option<&str>,
+    eval_count: u64,
+    num_predict: Option<i32>,
+) -> DoneReason {
+    match reason {
+        Some("length") => DoneReason::Length,
+        Some(_) => DoneReason::Stop,
+        None if num_predict.is_some_and(|n| n >= 0 && eval_count >= n as u64) => DoneReason::Length,
+        None => DoneReason::Stop,
+    }
+}
+
+/// Send a request with a streamed response, statuses other than success are errors
+async fn post_streamed(
+    url: url::Url,
+    body: &serde_json::Value,
+) -> Result<reqwest::Response, Box<dyn std::error::Error + Send + Sync>> {
+    let res = reqwest::Client::new().post(url).json(body).send().await?;
+    if !res.status().is_success() {
+        let status = res.status();
+        let text = res.text().await.unwrap_or_default();
+        return Err(format!("{status}: {text}").into());
+    }
+    Ok(res)
+}
+
+/// Next line of a response streamed as one JSON object per line, `None` once it ended
+async fn next_json_line(
+    res: &mut reqwest::Response,
+    buf: &mut Vec<u8>,
+) -> reqwest::Result<Option<Vec<u8>>> {
+    loop {
+        if let Some(line) = take_json_line(buf, false) {
+            return Ok(Some(line));
+        }
+        match res.chunk().await? {
+            Some(bytes) => buf.extend_from_slice(&bytes),
+            None => return Ok(take_json_line(buf, true)),
+        }
+    }
+}
+
+/// Take the next line that isn't blank out of the bytes received so far. Once the response
+/// `ended`, the rest is the last line even without a newline.
+fn take_json_line(buf: &mut Vec<u8>, ended: bool) -> Option<Vec<u8>> {
+    while let Some(end) = buf.iter().position(|b| *b == b'\n') {
+        let line: Vec<u8> = buf.drain(..=end).collect();
+        if !line.iter().all(u8::is_ascii_whitespace) {
+            return Some(line);
+        }
+    }
+    (ended && !buf.iter().all(u8::is_ascii_whitespace)).then(|| std::mem::take(buf))
+}
+
+/// Line of the streamed response of the chat endpoint
+#[derive(serde::Deserialize)]
+struct ChatChunk {
+    message: Option<ChatChunkMessage>,
+    #[serde(default)]
+    done: bool,
+    done_reason: Option<String>,
+    #[serde(default)]
+    eval_count: u64,
+    error: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct ChatChunkMessage {
+    #[serde(default)]
+    content: String,
+}
+
+/// Line of the streamed response of the generate endpoint
+#[derive(serde::Deserialize)]
+struct GenerateChunk {
+    #[serde(default)]
+    response: String,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    eval_count: u64,
+    done_reason: Option<String>,
+    error: Option<String>,
+}
+
+/// Complete the prompt as is with the generate endpoint in raw mode, which doesn't apply
+/// the chat template
+#[allow(clippy::too_many_arguments)]
+async fn request_raw_completion(
+    ollama: Ollama,
+    messages: Vec<ChatMessage>,
+    handle: &CompletionFlowerHandle,
+    stop_generating: Arc<AtomicBool>,
+    selected_model: String,
+    options: GenerationOptions,
+    keep_alive: Option<KeepAlive>,
+    num_predict: Option<i32>,
+    format: Option<ResponseFormat>,
+    index: usize,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    log::info!("requesting raw completion...");
+
+    let mut messages = messages.into_iter();
+    let prompt = messages
+        .next()
+        .unwrap_or_else(|| ChatMessage::user(String::new()));
+    // the part of the response to continue from
+    let prepend = messages.next().map(|m| m.content).unwrap_or_default();
+
+    let mut body = serde_json::json!({
+        "model": selected_model,
+        "prompt": prompt.content + &prepend,
+        "raw": true,
+        "stream": true,
+        "options": options,
+    });
+    if let Some(images) = prompt.images {
+        body["images"] = serde_json::to_value(images)?;
+    }
+    if let Some(keep_alive) = keep_alive {
+        body["keep_alive"] = serde_json::to_value(keep_alive)?;
+    }
+    if format.is_some() {
+        body["format"] = "json".into();
+    }
+
+    let mut res = post_streamed(ollama.url().join("api/generate")?, &body).await?;
+
+    log::info!("reading response...");
+
+    let mut response = String::new();
+    let mut done_reason = DoneReason::Stop;
+    let mut buf = Vec::new();
+    while let Some(line) = next_json_line(&mut res, &mut buf).await? {
+        let chunk: GenerateChunk = serde_json::from_slice(&line)?;
+        if let Some(error) = chunk.error {
+            return Err(error.into());
+        }
+        if chunk.done {
+            done_reason =
+                parse_done_reason(chunk.done_reason.as_deref(), chunk.eval_count, num_predict);
+            break;
+        }
+
+        // send message to gui thread
+        handle.send((index, chunk.response.clone()));
+        response += &chunk.response;
+
+        if stop_generating.load(Ordering::SeqCst) {
+            log::info!("stopping generation");
+            done_reason = DoneReason::Aborted;
+            stop_generating.store(false, Ordering::SeqCst);
+            break;
         }
     }
 
     log::info!(
-        "completion request complete, response length: {}",
+        "raw completion request complete, response length: {}, done reason: {done_reason:?}",
         response.len()
     );
-    handle.success((index, prepend + response.trim()));
+    // whitespace matters to raw prompts, keep the response as it was generated
+    handle.success((index, prepend + &response, done_reason));
     Ok(())
 }
 
@@ -482,6 +2203,7 @@ async fn request_completion(
 pub enum ChatExportFormat {
     #[default]
     Plaintext,
+    Markdown,
     Json,
     Ron,
 }
@@ -493,21 +2215,90 @@ impl std::fmt::Display for ChatExportFormat {
 }
 
 impl ChatExportFormat {
-    pub const ALL: [Self; 3] = [Self::Plaintext, Self::Json, Self::Ron];
+    pub const ALL: [Self; 4] = [Self::Plaintext, Self::Markdown, Self::Json, Self::Ron];
 
     #[inline]
     pub const fn extensions(self) -> &'static [&'static str] {
         match self {
             Self::Plaintext => &["txt"],
+            Self::Markdown => &["md"],
             Self::Json => &["json"],
             Self::Ron => &["ron"],
         }
     }
 }
 
+/// Replace `{name}` placeholders in a template with their values, unknown placeholders are
+/// left as is
+pub fn fill_template(template: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        filled += &rest[..start];
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            let (_, value) = values.iter().find(|(name, _)| *name == &rest[1..end])?;
+            Some((*value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                filled += value;
+                rest = &rest[end + 1..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled += rest;
+    filled
+}
+
+/// Format messages as Markdown, with a heading for each message's role.
+///
+/// Used both for Markdown exports and for copying the whole conversation.
+pub fn messages_to_markdown(messages: &[Message], timestamps: bool) -> String {
+    use std::fmt::Write as _;
+
+    let mut md = String::new();
+    for msg in messages {
+        if !md.is_empty() {
+            md.push('\n');
+        }
+        let role = match msg.role {
+            Role::Assistant => format!("{} ({})", msg.role_label(), msg.model_name),
+            _ => msg.role_label(),
+        };
+        if timestamps {
+            writeln!(md, "### {role} — {}\n", msg.time.to_rfc3339()).ok();
+        } else {
+            writeln!(md, "### {role}\n").ok();
+        }
+        md += msg.content.trim_end();
+        md.push('\n');
+    }
+    md
+}
+
+/// One line per message, starting with its timestamp if `timestamps` is set
+fn messages_to_plaintext(messages: &[Message], timestamps: bool) -> String {
+    use std::fmt::Write as _;
+
+    let mut text = String::new();
+    for msg in messages {
+        if timestamps {
+            write!(text, "{} - ", msg.time.to_rfc3339()).ok();
+        }
+        writeln!(text, "{:?} ({}): {}", msg.role, msg.model_name, msg.content).ok();
+    }
+    text
+}
+
 pub async fn export_messages(
     messages: Vec<Message>,
     format: ChatExportFormat,
+    timestamps: bool,
     task: impl std::future::Future<Output = Option<rfd::FileHandle>>,
 ) -> Result<egui_notify::Toast> {
     let Some(file) = task.await else {
@@ -519,38 +2310,51 @@ pub async fn export_messages(
         messages.len()
     );
 
-    let f = std::fs::File::create(file.path())?;
+    write_messages(file.path(), &messages, format, timestamps)?;
+
+    log::info!("export complete");
+    Ok(egui_notify::Toast::success(format!(
+        "Exported {} messages to {}",
+        messages.len(),
+        file.file_name(),
+    )))
+}
+
+/// Write messages to a file in the export format
+pub fn write_messages(
+    path: &Path,
+    messages: &[Message],
+    format: ChatExportFormat,
+    timestamps: bool,
+) -> Result<()> {
+    let f = std::fs::File::create(path)?;
     let mut f = std::io::BufWriter::new(f);
 
     match format {
         ChatExportFormat::Plaintext => {
-            for msg in &messages {
-                writeln!(
-                    f,
-                    "{} - {:?} ({}): {}",
-                    msg.time.to_rfc3339(),
-                    msg.role,
-                    msg.model_name,
-                    msg.content
-                )?;
-            }
+            f.write_all(messages_to_plaintext(messages, timestamps).as_bytes())?;
+        }
+        ChatExportFormat::Markdown => {
+            f.write_all(messages_to_markdown(messages, timestamps).as_bytes())?;
         }
         ChatExportFormat::Json => {
-            serde_json::to_writer_pretty(&mut f, &messages)?;
+            serde_json::to_writer_pretty(&mut f, messages)?;
         }
         ChatExportFormat::Ron => {
-            ron::ser::to_writer_pretty(&mut f, &messages, ron::ser::PrettyConfig::default())?;
+            ron::ser::to_writer_pretty(&mut f, messages, ron::ser::PrettyConfig::default())?;
         }
     }
 
-    f.flush().context("failed to flush writer")?;
+    f.flush().context("failed to flush writer")
+}
 
-    log::info!("export complete");
-    Ok(egui_notify::Toast::success(format!(
-        "Exported {} messages to {}",
-        messages.len(),
-        file.file_name(),
-    )))
+/// Find an installed model by name, the `:latest` tag can be left out
+fn find_model<'a>(models: &'a [LocalModel], name: &str) -> Option<&'a LocalModel> {
+    models.iter().find(|m| m.name == name).or_else(|| {
+        models
+            .iter()
+            .find(|m| m.name.strip_suffix(":latest") == Some(name))
+    })
 }
 
 fn make_summary(prompt: &str) -> String {
@@ -576,7 +2380,20 @@ fn make_summary(prompt: &str) -> String {
 #[derive(Debug, Clone, Copy)]
 pub enum ChatAction {
     None,
-    PickImages { id: usize },
+    PickImages {
+        id: usize,
+    },
+    PickFiles {
+        id: usize,
+    },
+    /// Attach all images in a folder
+    PickImageFolder {
+        id: usize,
+        recursive: bool,
+    },
+    /// Export the chat with the format selected in the edit panel
+    Export,
+    Toast(Toast),
 }
 
 impl Chat {
@@ -594,170 +2411,1560 @@ impl Chat {
         self.flower.id()
     }
 
-    fn convert_images(images: &[PathBuf]) -> Option<Vec<Image>> {
-        if !images.is_empty() {
-            Some(
-                images
-                    .iter()
-                    // TODO: handle errors
-                    .map(|i| {
-                        crate::image::convert_image(i)
-                            .map_err(|e| log::error!("failed to convert image: {e}"))
-                            .unwrap()
-                    })
-                    .collect(),
-            )
+    /// Copy of the chat with a new flower, leaving out the response that is still generating
+    pub fn duplicate(&self, id: usize) -> Self {
+        let summary = if self.summary.is_empty() {
+            "New Chat"
         } else {
-            None
+            &self.summary
+        };
+        Self {
+            messages: self
+                .messages
+                .iter()
+                .filter(|m| !m.is_generating)
+                .cloned()
+                .collect(),
+            summary: format!("{summary} (copy)"),
+            images: self.images.clone(),
+            system_prompt: self.system_prompt.clone(),
+            context_strategy: self.context_strategy,
+            prompt_history: self.prompt_history.clone(),
+            #[cfg(feature = "tts")]
+            auto_read: self.auto_read,
+            ..Self::new(id, self.model_picker.clone())
         }
     }
 
-    fn get_context_messages(messages: &[Message]) -> Vec<ChatMessage> {
-        messages
-            .iter()
-            .map(|m| {
-                let mut message = match m.role {
-                    Role::User => ChatMessage::user(m.content.clone()),
-                    Role::Assistant => ChatMessage::assistant(m.content.clone()),
-                };
-
-                // TODO: don't do this each time!
-                message.images = Self::convert_images(&m.images);
+    /// Responses that were generating when the app was closed can't be resumed, mark them
+    /// as interrupted instead of waiting for them forever
+    pub fn mark_interrupted(&mut self) {
+        for message in self.messages.iter_mut().filter(|m| m.is_generating) {
+            message.is_generating = false;
+            message.done_reason = Some(DoneReason::Interrupted);
+        }
+    }
 
-                message
-            })
-            .collect()
+    /// Replace the flower with a new one with a different id
+    pub fn reset_flower(&mut self, id: usize) {
+        self.flower = CompletionFlower::new(id);
     }
 
-    fn send_message(&mut self, ollama: &Ollama) {
-        // don't send empty messages
-        if self.chatbox.is_empty() && self.images.is_empty() {
-            return;
+    /// Index of the first of `messages[..end]` that is sent to the model with the
+    /// [`ContextStrategy`] of this chat
+    fn context_start(&mut self, end: usize) -> usize {
+        // always keep the last turn
+        let min_start = end.saturating_sub(2);
+        let strategy = match self.context_strategy {
+            ContextStrategy::KeepAll if self.auto_trim != AutoTrim::Off => {
+                ContextStrategy::DropOldest
+            }
+            strategy => strategy,
+        };
+        let start = match strategy {
+            ContextStrategy::KeepAll => 0,
+            ContextStrategy::SlidingWindow(n) => end.saturating_sub(n).min(min_start),
+            ContextStrategy::DropOldest => {
+                let mut reserved = self.model_picker.num_predict().unwrap_or(0).max(0) as usize;
+                if self.auto_trim == AutoTrim::Summarize {
+                    reserved += SUMMARY_TOKENS;
+                }
+                let mut budget = (self.model_picker.num_ctx() as usize)
+                    .saturating_sub(estimate_tokens(&self.system_prompt) + reserved);
+                let estimates = self.message_token_estimates();
+                let mut start = end;
+                while start > 0 && estimates[start - 1] <= budget {
+                    budget -= estimates[start - 1];
+                    start -= 1;
+                }
+                start.min(min_start)
+            }
+        };
+        if start > 0 && self.auto_trim == AutoTrim::Summarize {
+            start.next_multiple_of(SUMMARY_STEP).min(min_start)
+        } else {
+            start
         }
+    }
 
-        // remove old error messages
-        self.messages.retain(|m| !m.is_error);
-
-        let prompt = self.chatbox.trim_end().to_string();
-        let model_name = self.model_picker.selected_model().to_owned();
-        self.messages.push(Message::user(
-            prompt.clone(),
-            model_name.clone(),
-            self.images.clone(),
-        ));
+    /// Messages to send to the model for a response after `self.messages[..end]`, their
+    /// images and the messages that were left out if they should be summarized first
+    fn get_context_messages(
+        &mut self,
+        end: usize,
+    ) -> (
+        Vec<ChatMessage>,
+        Vec<MessageImages>,
+        Option<TrimmedMessages>,
+    ) {
+        let start = self.context_start(end);
+        let mut messages: Vec<ChatMessage> = (!self.system_prompt.is_empty())
+            .then(|| ChatMessage::system(self.system_prompt.clone()))
+            .into_iter()
+            .collect();
 
-        if self.summary.is_empty() {
-            self.summary = make_summary(&prompt);
+        let mut trimmed = None;
+        if start > 0 && self.auto_trim == AutoTrim::Summarize {
+            let key = (
+                start,
+                self.messages[..start].iter().map(|m| m.content.len()).sum(),
+            );
+            match &*self.trimmed_summary.read() {
+                Some((summary_key, summary)) if *summary_key == key => {
+                    messages.push(summary_note(summary));
+                }
+                _ => {
+                    trimmed = Some(TrimmedMessages {
+                        key,
+                        messages: self.messages[..start]
+                            .iter()
+                            .map(Message::to_chat_message)
+                            .collect(),
+                    });
+                }
+            }
         }
 
-        // clear chatbox & images
-        self.chatbox.clear();
-        self.images.clear();
-
-        // get ready for assistant response
-        self.messages
-            .push(Message::assistant(String::new(), model_name.clone()));
+        let mut images = Vec::new();
+        for m in &self.messages[start..end] {
+            if !m.images.is_empty() {
+                images.push(MessageImages {
+                    message: messages.len(),
+                    paths: m.images.clone(),
+                    max_dimension: self.max_image_dimension.filter(|_| !m.original_images),
+                });
+            }
+            messages.push(m.to_chat_message());
+        }
+        (messages, images, trimmed)
+    }
 
-        self.spawn_completion(
-            ollama.clone(),
-            Self::get_context_messages(&self.messages),
-            model_name,
+    /// Estimated token counts of the messages sent as context, only recomputed when the
+    /// messages change
+    fn message_token_estimates(&mut self) -> &[usize] {
+        let key = (
+            self.messages.len(),
+            self.messages.iter().map(|m| m.content.len()).sum(),
         );
+        if self.context_estimate.0 != key {
+            self.context_estimate = (
+                key,
+                self.messages
+                    .iter()
+                    .map(|m| estimate_tokens(&m.content))
+                    .collect(),
+            );
+        }
+        &self.context_estimate.1
     }
 
-    /// spawn a new task to generate the completion
-    fn spawn_completion(
-        &self,
-        ollama: Ollama,
-        context_messages: Vec<ChatMessage>,
-        model_name: String,
-    ) {
-        let handle = self.flower.handle(); // recv'd by gui thread
-        let stop_generation = self.stop_generating.clone();
-        let generation_options = self.model_picker.get_generation_options();
-        let template = self.model_picker.template.clone();
-        let index = self.messages.len() - 1;
-        tokio::spawn(async move {
-            handle.activate();
-            let _ = request_completion(
-                ollama,
-                context_messages,
-                &handle,
-                stop_generation,
-                model_name,
-                generation_options,
-                template,
-                index,
+    /// Show a chip noting that the chat history isn't sent in raw mode, clicking it turns raw
+    /// mode off. Returns the height it takes up
+    fn show_raw_mode(&mut self, ui: &mut egui::Ui) -> f32 {
+        let chip = ui
+            .add(
+                egui::Button::new(
+                    egui::RichText::new(
+                        "Raw mode — only the prompt is sent, without the chat history, \
+                        system prompt or template (click to turn off)",
+                    )
+                    .small()
+                    .color(ui.visuals().warn_fg_color),
+                )
+                .rounding(Rounding::same(f32::INFINITY)),
             )
-            .await
-            .map_err(|e| {
-                log::error!("failed to request completion: {e}");
-                handle.error((index, e.to_string()));
-            });
-        });
+            .on_hover_text("Send messages to the chat endpoint with the history again");
+        if chip.clicked() {
+            self.raw_mode = false;
+        }
+        chip.rect.height() + ui.spacing().item_spacing.y
     }
 
-    fn regenerate_response(&mut self, ollama: &Ollama, idx: usize) {
-        // remake context history to make the message we want to regenerate last
-        let mut messages = Self::get_context_messages(&self.messages[..idx]);
-
-        // start with the prepended message and update it in the displayed messages
-        messages.push(ChatMessage::assistant(self.prepend_buf.clone()));
-        self.messages[idx].content = self.prepend_buf.clone();
-        self.prepend_buf.clear();
-
-        // start completing the message
-        self.spawn_completion(
-            ollama.clone(),
-            messages,
-            self.messages[idx].model_name.clone(),
-        );
+    /// Show a chip for the queued message, clicking it cancels sending the message. Returns
+    /// the height it takes up
+    fn show_queued_message(&mut self, ui: &mut egui::Ui) -> f32 {
+        if self.queued_message.is_none() {
+            return 0.0;
+        }
+        let chip = ui
+            .add(
+                egui::Button::new(
+                    egui::RichText::new(
+                        "1 message queued — will send when generation finishes (click to cancel)",
+                    )
+                    .small(),
+                )
+                .rounding(Rounding::same(f32::INFINITY)),
+            )
+            .on_hover_text("Put the message back into the chatbox");
+        if chip.clicked() {
+            self.unqueue_message();
+        }
+        chip.rect.height() + ui.spacing().item_spacing.y
     }
 
-    fn show_chatbox(
+    /// Ask whether to send a message that repeats the last one again. Returns the height it
+    /// takes up and the action of sending it
+    fn show_duplicate_send(
         &mut self,
         ui: &mut egui::Ui,
-        is_max_height: bool,
-        is_generating: bool,
         ollama: &Ollama,
-    ) -> ChatAction {
-        let mut action = ChatAction::None;
-        if let Some(idx) = self.retry_message_idx.take() {
-            self.chatbox = self.messages[idx - 1].content.clone();
-            self.messages.remove(idx); // remove assistant message
-            self.messages.remove(idx - 1); // remove user message
-            self.send_message(ollama);
+        models: &[LocalModel],
+    ) -> (f32, ChatAction) {
+        if !self.duplicate_pending {
+            return (0.0, ChatAction::None);
         }
+        let mut action = ChatAction::None;
+        let response = ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("You just sent this —").small());
+            let can_send = !self.flower_active();
+            if ui
+                .add_enabled(can_send, egui::Button::new("Send again").small())
+                .clicked()
+            {
+                self.allow_duplicate = true;
+                action = self.send_message(ollama, models);
+            }
+            if ui.small_button("Cancel").clicked() {
+                self.duplicate_pending = false;
+                self.chatbox.clear();
+                self.images.clear();
+                self.text_files.clear();
+            }
+        });
+        (
+            response.response.rect.height() + ui.spacing().item_spacing.y,
+            action,
+        )
+    }
 
-        if is_max_height {
-            ui.add_space(8.0);
+    /// Rough size of the request for the chatbox contents, images are sent as base64
+    fn request_size(&mut self) -> usize {
+        self.update_image_sizes();
+        let images: u64 = self.image_sizes.values().sum();
+        let text_files: usize = self.text_files.iter().map(|f| f.text.len()).sum();
+        self.chatbox.len() + images as usize + text_files
+    }
+
+    /// Read the sizes of newly attached images and forget the ones that were removed
+    fn update_image_sizes(&mut self) {
+        self.image_sizes
+            .retain(|path, _| self.images.contains(path));
+        for path in &self.images {
+            if !self.image_sizes.contains_key(path) {
+                let len = std::fs::metadata(path).map_or(0, |meta| meta.len());
+                self.image_sizes.insert(path.clone(), len.div_ceil(3) * 4);
+            }
         }
+    }
 
-        let images_height = if !self.images.is_empty() {
-            ui.add_space(8.0);
-            let height = ui
-                .horizontal(|ui| {
-                    crate::image::show_images(ui, &mut self.images, true);
-                })
-                .response
-                .rect
-                .height();
-            height + 16.0
-        } else {
-            0.0
+    /// Warn that the message may be too large for Ollama, offering to split it into several
+    /// messages. Returns the height it takes up and the action of sending it
+    fn show_oversized_send(
+        &mut self,
+        ui: &mut egui::Ui,
+        ollama: &Ollama,
+        models: &[LocalModel],
+    ) -> (f32, ChatAction) {
+        let Some(size) = self.oversized_pending else {
+            return (0.0, ChatAction::None);
         };
-
-        ui.horizontal_centered(|ui| {
+        let mut action = ChatAction::None;
+        let response = ui.horizontal(|ui| {
+            ui.label(
+                egui::RichText::new(format!(
+                    "This message is ~{}, Ollama may reject it —",
+                    bytesize::ByteSize(size as u64)
+                ))
+                .small(),
+            );
+            let can_send = !self.flower_active();
+            // only the text can be split, and comparisons are sent as a single prompt
+            let text_limit = self
+                .max_message_bytes
+                .saturating_sub(size - self.chatbox.len());
+            let can_split = can_send && self.comparison.is_none() && text_limit > 0;
             if ui
-                .add(
-                    egui::Button::new("➕")
-                        .min_size(vec2(32.0, 32.0))
-                        .rounding(Rounding::same(f32::INFINITY)),
+                .add_enabled(can_split, egui::Button::new("Split").small())
+                .on_hover_text(
+                    "Send the text as several messages, the response is only generated \
+                    after the last one",
                 )
-                .on_hover_text_at_pointer("Pick Images")
                 .clicked()
             {
-                action = ChatAction::PickImages { id: self.id() };
+                action = self.send_split(ollama, models, text_limit);
+            }
+            if ui
+                .add_enabled(can_send, egui::Button::new("Send anyway").small())
+                .clicked()
+            {
+                self.allow_oversized = true;
+                action = self.send_message(ollama, models);
+            }
+            if ui.small_button("Cancel").clicked() {
+                self.oversized_pending = None;
+            }
+        });
+        (
+            response.response.rect.height() + ui.spacing().item_spacing.y,
+            action,
+        )
+    }
+
+    /// Send the chatbox text as several messages of at most `max_len` bytes, only generating
+    /// a response after the last one
+    fn send_split(&mut self, ollama: &Ollama, models: &[LocalModel], max_len: usize) -> ChatAction {
+        let original = self.chatbox.clone();
+        let parts = split_message(commands::unescape(original.trim_end()), max_len);
+        let Some((last, first)) = parts.split_last() else {
+            return ChatAction::None;
+        };
+        let n = parts.len();
+        let part_header = |i: usize| format!("Part {i}/{n}:\n\n");
+
+        let model_name = self.model_picker.selected_model().to_owned();
+        let earlier_parts = first
+            .iter()
+            .enumerate()
+            .map(|(i, part)| {
+                Message::user(
+                    format!("{}{part}", part_header(i + 1)),
+                    model_name.clone(),
+                    Vec::new(),
+                )
+            })
+            .collect();
+        let had_summary = !self.summary.is_empty();
+
+        // images go with the last part
+        let last = commands::escape(&format!("{}{last}", part_header(n)));
+        self.chatbox.clone_from(&last);
+        self.allow_duplicate = true;
+        self.allow_oversized = true;
+        let action = self.send_prompt(ollama, models, earlier_parts);
+        if !self.chatbox_is_empty() {
+            // not sent, bring back the whole message
+            self.chatbox = original;
+            return action;
+        }
+
+        if !had_summary {
+            self.summary = make_summary(&parts[0]);
+        }
+        // recall the whole message instead of its last part
+        if self.prompt_history.last() == Some(&last) {
+            self.prompt_history.pop();
+        }
+        self.push_prompt_history(&original);
+        action
+    }
+
+    /// Whether the chatbox repeats the last user message, sent less than
+    /// [`DUPLICATE_SEND_WINDOW`] ago. Whitespace differences are ignored
+    fn is_duplicate_send(&self) -> bool {
+        let prompt = commands::unescape(&self.chatbox);
+        self.messages
+            .iter()
+            .rev()
+            .find(|m| m.is_user())
+            .is_some_and(|m| {
+                (chrono::Utc::now() - m.time)
+                    .to_std()
+                    .is_ok_and(|elapsed| elapsed < DUPLICATE_SEND_WINDOW)
+                    && m.images == self.images
+                    && m.text_files == self.text_files
+                    && m.content.split_whitespace().eq(prompt.split_whitespace())
+            })
+    }
+
+    /// Show the text files attached to the chatbox. Returns the height they take up
+    fn show_text_files(&mut self, ui: &mut egui::Ui) -> f32 {
+        if self.text_files.is_empty() {
+            return 0.0;
+        }
+        let mut remove = None;
+        let response = ui.horizontal_wrapped(|ui| {
+            for (i, file) in self.text_files.iter().enumerate() {
+                if file.show(ui, true) {
+                    remove = Some(i);
+                }
+            }
+        });
+        if let Some(i) = remove {
+            self.text_files.remove(i);
+        }
+        response.response.rect.height() + ui.spacing().item_spacing.y
+    }
+
+    /// Show a chip to undo removing images for a few seconds after they were removed. Returns
+    /// the height it takes up
+    fn show_removed_images(&mut self, ui: &mut egui::Ui) -> f32 {
+        const UNDO_TIMEOUT: Duration = Duration::from_secs(5);
+
+        let Some((count, removed_at)) = self
+            .removed_images
+            .as_ref()
+            .map(|(images, at)| (images.len(), *at))
+        else {
+            return 0.0;
+        };
+        let Some(remaining) = UNDO_TIMEOUT.checked_sub(removed_at.elapsed()) else {
+            self.removed_images = None;
+            return 0.0;
+        };
+        ui.ctx().request_repaint_after(remaining);
+
+        let text = if count == 1 {
+            "Image removed — Undo".to_owned()
+        } else {
+            format!("{count} images removed — Undo")
+        };
+        let chip = ui.add(
+            egui::Button::new(egui::RichText::new(text).small())
+                .rounding(Rounding::same(f32::INFINITY)),
+        );
+        if chip.clicked() {
+            // in ascending order, so every image goes back to where it was
+            for (idx, path) in self
+                .removed_images
+                .take()
+                .map(|(r, _)| r)
+                .unwrap_or_default()
+            {
+                self.images.insert(idx.min(self.images.len()), path);
+            }
+        }
+        chip.rect.height() + ui.spacing().item_spacing.y
+    }
+
+    /// Show a thin bar with the estimated context usage against the model's `num_ctx`,
+    /// returns the height it takes up
+    fn show_context_usage(&mut self, ui: &mut egui::Ui) -> f32 {
+        const MAX_LISTED: usize = 16;
+
+        let num_ctx = self.model_picker.num_ctx() as usize;
+        let system = estimate_tokens(&self.system_prompt);
+        let draft = estimate_tokens(&self.chatbox);
+        let start = self.context_start(self.messages.len());
+        let estimates = self.message_token_estimates().to_vec();
+        let total = system + draft + estimates[start..].iter().sum::<usize>();
+        let usage = total as f32 / num_ctx.max(1) as f32;
+
+        let color = if usage >= 1.0 {
+            ui.visuals().error_fg_color
+        } else if usage >= 0.8 {
+            ui.visuals().warn_fg_color
+        } else {
+            ui.visuals().selection.bg_fill
+        };
+        let bar = ui.add(
+            egui::ProgressBar::new(usage.min(1.0))
+                .desired_height(3.0)
+                .fill(color),
+        );
+        let height = bar.rect.height() + ui.spacing().item_spacing.y;
+        bar.on_hover_ui(|ui| {
+            ui.label(format!(
+                "~{total} of {num_ctx} context tokens used ({:.0}%)",
+                usage * 100.0
+            ));
+            if usage >= 1.0 && self.context_strategy == ContextStrategy::KeepAll {
+                ui.label(
+                    egui::RichText::new("The model will forget the start of the conversation")
+                        .color(ui.visuals().warn_fg_color),
+                );
+            }
+            ui.separator();
+            egui::Grid::new("context_usage_grid")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    if system > 0 {
+                        ui.label("System prompt");
+                        ui.label(format!("~{system}"));
+                        ui.end_row();
+                    }
+                    if start > 0 {
+                        ui.label(format!("{start} older messages"));
+                        ui.label("not sent");
+                        ui.end_row();
+                    }
+                    let skipped = estimates.len().saturating_sub(MAX_LISTED).max(start);
+                    if skipped > start {
+                        ui.label(format!("{} earlier messages", skipped - start));
+                        ui.label(format!(
+                            "~{}",
+                            estimates[start..skipped].iter().sum::<usize>()
+                        ));
+                        ui.end_row();
+                    }
+                    for (message, tokens) in self.messages.iter().zip(&estimates).skip(skipped) {
+                        ui.label(message.role_label());
+                        ui.label(format!("~{tokens}"));
+                        ui.end_row();
+                    }
+                    if draft > 0 {
+                        ui.label("Draft");
+                        ui.label(format!("~{draft}"));
+                        ui.end_row();
+                    }
+                });
+            ui.label(egui::RichText::new("Estimated at ~4 characters per token").weak());
+        });
+        height
+    }
+
+    /// Show a stacked bar of how the context window is split between the system prompt, the
+    /// messages sent to the model and the draft. Hovering a segment shows its message.
+    pub fn show_context_budget(&mut self, ui: &mut egui::Ui) {
+        const SNIPPET_LEN: usize = 120;
+
+        let num_ctx = self.model_picker.num_ctx() as usize;
+        let start = self.context_start(self.messages.len());
+        let estimates = self.message_token_estimates().to_vec();
+        let visuals = ui.visuals().clone();
+
+        // label, text, estimated tokens, color
+        let mut segments: Vec<(String, &str, usize, Color32)> = Vec::new();
+        if !self.system_prompt.is_empty() {
+            segments.push((
+                "System prompt".to_owned(),
+                &self.system_prompt,
+                estimate_tokens(&self.system_prompt),
+                visuals.hyperlink_color,
+            ));
+        }
+        for (message, tokens) in self.messages.iter().zip(&estimates).skip(start) {
+            let color = match message.role {
+                Role::User => visuals.selection.bg_fill,
+                Role::Assistant => visuals.selection.bg_fill.gamma_multiply(0.5),
+                Role::System => visuals.hyperlink_color,
+            };
+            segments.push((message.role_label(), &message.content, *tokens, color));
+        }
+        if !self.chatbox.is_empty() {
+            segments.push((
+                "Draft".to_owned(),
+                &self.chatbox,
+                estimate_tokens(&self.chatbox),
+                visuals.warn_fg_color,
+            ));
+        }
+        let total: usize = segments.iter().map(|s| s.2).sum();
+
+        let (rect, response) =
+            ui.allocate_exact_size(vec2(ui.available_width(), 12.0), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+
+        // overflowing contexts are scaled to fit, with a line where the context ends
+        let scale = rect.width() / total.max(num_ctx).max(1) as f32;
+        let mut x = rect.left();
+        let mut hovered = None;
+        for (i, (.., tokens, color)) in segments.iter().enumerate() {
+            let width = *tokens as f32 * scale;
+            let segment = Rect::from_x_y_ranges(x..=x + width, rect.y_range());
+            painter.rect_filled(segment, 0.0, *color);
+            if response
+                .hover_pos()
+                .is_some_and(|p| segment.x_range().contains(p.x))
+            {
+                hovered = Some(i);
+            }
+            x += width;
+        }
+        if total > num_ctx {
+            let x = rect.left() + num_ctx as f32 * scale;
+            painter.vline(x, rect.y_range(), Stroke::new(2.0, visuals.error_fg_color));
+        }
+
+        response.on_hover_ui_at_pointer(|ui| match hovered.map(|i| &segments[i]) {
+            Some((label, text, tokens, _)) => {
+                ui.strong(label);
+                ui.label(format!("~{tokens} tokens"));
+                let mut snippet: String = text.chars().take(SNIPPET_LEN).collect();
+                if text.chars().nth(SNIPPET_LEN).is_some() {
+                    snippet.push('…');
+                }
+                ui.weak(snippet);
+            }
+            None => {
+                ui.label(format!("~{} tokens free", num_ctx.saturating_sub(total)));
+            }
+        });
+
+        let mut summary = format!("~{total} of {num_ctx} context tokens");
+        if start > 0 {
+            summary += &format!(", {start} older messages left out");
+        }
+        ui.label(egui::RichText::new(summary).small().weak());
+    }
+
+    fn run_command(&mut self, command: Command, models: &[LocalModel]) -> ChatAction {
+        let toast = match command {
+            Command::Model(name) => {
+                let Some(model) = find_model(models, &name) else {
+                    self.command_error = Some(format!("Model `{name}` is not installed"));
+                    return ChatAction::None;
+                };
+                self.model_picker.select(model);
+                Toast::success(format!("Switched to `{}`", model.name))
+            }
+            Command::System(prompt) => {
+                let toast = if prompt.is_empty() {
+                    Toast::info("System prompt removed")
+                } else {
+                    Toast::success("System prompt set")
+                };
+                self.system_prompt = prompt;
+                toast
+            }
+            Command::Clear => {
+                if self.flower_active() {
+                    self.command_error = Some("Can't clear the chat while generating".to_owned());
+                    return ChatAction::None;
+                }
+                self.chatbox.clear();
+                self.confirm_clear = true;
+                return ChatAction::None;
+            }
+            Command::Export => {
+                self.chatbox.clear();
+                return ChatAction::Export;
+            }
+            Command::Temperature(temperature) => {
+                self.model_picker.set_temperature(temperature);
+                Toast::success(format!("Temperature set to {temperature}"))
+            }
+            Command::Compare(names) => {
+                if self.flower_active() {
+                    self.command_error =
+                        Some("Can't start a comparison while generating".to_owned());
+                    return ChatAction::None;
+                }
+                let mut compared = Vec::with_capacity(names.len());
+                for name in names {
+                    let Some(model) = find_model(models, &name) else {
+                        self.command_error = Some(format!("Model `{name}` is not installed"));
+                        return ChatAction::None;
+                    };
+                    compared.push(model.name.clone());
+                }
+                let toast = Toast::success(format!("Comparing {} models", compared.len()));
+                self.comparison = Some(Comparison::new(self.id(), compared));
+                toast
+            }
+        };
+        self.chatbox.clear();
+        ChatAction::Toast(toast)
+    }
+
+    /// Replace the chatbox with an older or newer sent prompt, going past the newest one
+    /// brings back what was typed before browsing. Returns whether the chatbox changed.
+    fn recall_prompt(&mut self, older: bool) -> bool {
+        let len = self.prompt_history.len();
+        let pos = match (self.history_pos, older) {
+            (None, true) if len > 0 => Some(len - 1),
+            (Some(0), true) => return false,
+            (Some(i), true) => Some(i - 1),
+            (Some(i), false) if i + 1 < len => Some(i + 1),
+            (Some(_), false) => None,
+            _ => return false,
+        };
+        if self.history_pos.is_none() {
+            self.history_draft = std::mem::take(&mut self.chatbox);
+        }
+        self.chatbox = match pos {
+            Some(i) => self.prompt_history[i].clone(),
+            None => std::mem::take(&mut self.history_draft),
+        };
+        self.history_pos = pos;
+        true
+    }
+
+    /// Remember a sent prompt so it can be recalled with the arrow keys
+    fn push_prompt_history(&mut self, prompt: &str) {
+        self.history_pos = None;
+        self.history_draft.clear();
+        if prompt.trim().is_empty() || self.prompt_history.last().is_some_and(|p| p == prompt) {
+            return;
+        }
+        if self.prompt_history.len() >= MAX_PROMPT_HISTORY {
+            self.prompt_history.remove(0);
+        }
+        self.prompt_history.push(prompt.to_owned());
+    }
+
+    /// Queue the chatbox contents to be sent when the response finishes generating
+    fn queue_message(&mut self) {
+        if self.queued_message.is_some() || self.chatbox_is_empty() {
+            return;
+        }
+        self.queued_message = Some(QueuedMessage {
+            text: std::mem::take(&mut self.chatbox),
+            images: std::mem::take(&mut self.images),
+            text_files: std::mem::take(&mut self.text_files),
+            original_images: std::mem::take(&mut self.send_original_images),
+        });
+    }
+
+    /// Put the queued message back into the chatbox
+    fn unqueue_message(&mut self) {
+        let Some(queued) = self.queued_message.take() else {
+            return;
+        };
+        if self.chatbox.is_empty() {
+            self.chatbox = queued.text;
+        } else {
+            self.chatbox = format!("{}\n{}", queued.text, self.chatbox);
+        }
+        self.images.splice(0..0, queued.images);
+        self.text_files.splice(0..0, queued.text_files);
+        self.send_original_images |= queued.original_images;
+    }
+
+    /// Send the queued message if the response finished generating. If it can't be sent,
+    /// e.g. because it's an invalid command, it's put back into the chatbox.
+    pub fn send_queued_message(&mut self, ollama: &Ollama, models: &[LocalModel]) -> ChatAction {
+        if self.flower_active() {
+            return ChatAction::None;
+        }
+        let Some(queued) = self.queued_message.take() else {
+            return ChatAction::None;
+        };
+
+        // keep what was typed after queueing the message
+        let draft = std::mem::replace(&mut self.chatbox, queued.text);
+        let draft_images = std::mem::replace(&mut self.images, queued.images);
+        let draft_files = std::mem::replace(&mut self.text_files, queued.text_files);
+        let draft_original =
+            std::mem::replace(&mut self.send_original_images, queued.original_images);
+        // the user already chose to send it
+        self.allow_duplicate = true;
+        self.allow_oversized = true;
+        let action = self.send_message(ollama, models);
+        // what wasn't sent, e.g. an invalid command, is still in the chatbox
+        let sent = self.chatbox_is_empty();
+        let queued = QueuedMessage {
+            text: std::mem::replace(&mut self.chatbox, draft),
+            images: std::mem::replace(&mut self.images, draft_images),
+            text_files: std::mem::replace(&mut self.text_files, draft_files),
+            original_images: std::mem::replace(&mut self.send_original_images, draft_original),
+        };
+        if !sent {
+            self.queued_message = Some(queued);
+            self.unqueue_message();
+        }
+        action
+    }
+
+    /// Send the prompt of the last failed response again, e.g. after its model was pulled
+    pub fn retry_failed(&mut self, ollama: &Ollama, models: &[LocalModel]) {
+        if self.flower_active() {
+            return;
+        }
+        let Some(idx) = self
+            .messages
+            .iter()
+            .rposition(|m| m.is_error)
+            .filter(|&idx| idx > 0 && self.messages[idx - 1].is_user())
+        else {
+            return;
+        };
+        self.messages.remove(idx);
+        let prompt = self.messages.remove(idx - 1);
+
+        // keep what was typed while the model was pulled
+        // don't run messages starting with `/` as commands
+        let draft = std::mem::replace(&mut self.chatbox, commands::escape(&prompt.content));
+        let draft_images = std::mem::replace(&mut self.images, prompt.images);
+        let draft_files = std::mem::replace(&mut self.text_files, prompt.text_files);
+        let draft_original =
+            std::mem::replace(&mut self.send_original_images, prompt.original_images);
+        // the user already sent it once
+        self.allow_duplicate = true;
+        self.allow_oversized = true;
+        self.send_message(ollama, models);
+        self.chatbox = draft;
+        self.images = draft_images;
+        self.text_files = draft_files;
+        self.send_original_images = draft_original;
+    }
+
+    fn send_message(&mut self, ollama: &Ollama, models: &[LocalModel]) -> ChatAction {
+        self.send_prompt(ollama, models, Vec::new())
+    }
+
+    /// Send the chatbox contents after `earlier_parts`, the messages it was split from. They
+    /// are only added to the chat if the prompt is sent.
+    fn send_prompt(
+        &mut self,
+        ollama: &Ollama,
+        models: &[LocalModel],
+        earlier_parts: Vec<Message>,
+    ) -> ChatAction {
+        if let Some(command) = commands::parse(&self.chatbox) {
+            return match command {
+                Ok(command) => self.run_command(command, models),
+                Err(e) => {
+                    self.command_error = Some(e);
+                    ChatAction::None
+                }
+            };
+        }
+
+        // don't send empty messages
+        if self.chatbox_is_empty() {
+            return ChatAction::None;
+        }
+        let allow_duplicate = std::mem::take(&mut self.allow_duplicate);
+        self.duplicate_pending =
+            self.confirm_duplicates && !allow_duplicate && self.is_duplicate_send();
+        if self.duplicate_pending {
+            return ChatAction::None;
+        }
+        let allow_oversized = std::mem::take(&mut self.allow_oversized);
+        let size = self.request_size();
+        self.oversized_pending =
+            (self.max_message_bytes > 0 && !allow_oversized && size > self.max_message_bytes)
+                .then_some(size);
+        if self.oversized_pending.is_some() {
+            return ChatAction::None;
+        }
+        self.push_prompt_history(&self.chatbox.clone());
+
+        let mut prompt = Message::user(
+            self.chatbox_prompt(),
+            self.model_picker.selected_model().to_owned(),
+            self.images.clone(),
+        );
+        prompt.text_files = std::mem::take(&mut self.text_files);
+        prompt.original_images = std::mem::take(&mut self.send_original_images);
+        if let Some(comparison) = &mut self.comparison {
+            let mut messages: Vec<ChatMessage> = (!self.system_prompt.is_empty())
+                .then(|| ChatMessage::system(self.system_prompt.clone()))
+                .into_iter()
+                .collect();
+            let message_images = vec![MessageImages {
+                message: messages.len(),
+                paths: self.images.clone(),
+                max_dimension: self.max_image_dimension.filter(|_| !prompt.original_images),
+            }];
+            // the files become part of the prompt text if a response is kept
+            let prompt = prompt.content_with_files();
+            messages.push(ChatMessage::user(prompt.clone()));
+
+            self.chatbox.clear();
+            let images = std::mem::take(&mut self.images);
+            comparison.send(
+                ollama,
+                prompt,
+                images,
+                messages,
+                message_images,
+                &self.image_cache,
+                &self.model_picker,
+            );
+            return ChatAction::None;
+        }
+
+        // remove old error messages
+        self.messages.retain(|m| !m.is_error);
+
+        let model_name = prompt.model_name.clone();
+        if self.summary.is_empty() {
+            self.summary = make_summary(&prompt.content_with_files());
+        }
+        self.messages.extend(earlier_parts);
+        self.messages.push(prompt);
+
+        // clear chatbox & images
+        self.chatbox.clear();
+        self.images.clear();
+        self.removed_images = None;
+
+        // get ready for assistant response
+        let mut response = Message::assistant(String::new(), model_name.clone());
+        response.is_json = self.model_picker.response_format() == Some(ResponseFormat::Json);
+        self.messages.push(response);
+
+        let (context_messages, images, trimmed) = self.get_context_messages(self.messages.len());
+        self.spawn_completion(
+            ollama.clone(),
+            context_messages,
+            images,
+            trimmed,
+            model_name,
+            self.messages.len() - 1,
+        );
+        ChatAction::None
+    }
+
+    /// Nothing to send: no text, images or text files
+    fn chatbox_is_empty(&self) -> bool {
+        self.chatbox.is_empty() && self.images.is_empty() && self.text_files.is_empty()
+    }
+
+    /// Prompt sent for the chatbox contents, raw prompts are sent verbatim. Images without
+    /// text are sent with the image-only prompt.
+    fn chatbox_prompt(&self) -> String {
+        if self.chatbox.trim().is_empty() && !self.images.is_empty() {
+            self.image_prompt.clone()
+        } else if self.raw_mode {
+            commands::unescape(&self.chatbox).to_owned()
+        } else {
+            commands::unescape(self.chatbox.trim_end()).to_owned()
+        }
+    }
+
+    /// Request with the settings of the model picker
+    fn completion_request(
+        &self,
+        model: String,
+        mut messages: Vec<ChatMessage>,
+        mut images: Vec<MessageImages>,
+        trimmed: Option<TrimmedMessages>,
+    ) -> CompletionRequest {
+        if self.raw_mode {
+            // only the prompt and the response to continue from, without the history
+            let start = messages
+                .iter()
+                .rposition(|m| m.role == MessageRole::User)
+                .unwrap_or(messages.len());
+            messages.drain(..start);
+            images.retain(|images| images.message >= start);
+            for images in &mut images {
+                images.message -= start;
+            }
+        }
+        CompletionRequest {
+            model,
+            messages,
+            images,
+            trimmed: trimmed.filter(|_| !self.raw_mode),
+            options: self.model_picker.get_generation_options(),
+            template: self.model_picker.template.clone(),
+            keep_alive: self.model_picker.get_keep_alive(),
+            num_predict: self.model_picker.num_predict(),
+            format: self.model_picker.response_format(),
+            raw: self.raw_mode,
+        }
+    }
+
+    /// spawn a new task to generate the completion
+    fn spawn_completion(
+        &self,
+        ollama: Ollama,
+        context_messages: Vec<ChatMessage>,
+        images: Vec<MessageImages>,
+        trimmed: Option<TrimmedMessages>,
+        model_name: String,
+        index: usize,
+    ) {
+        let request = self.completion_request(model_name, context_messages, images, trimmed);
+        self.spawn_request(ollama, request, index);
+    }
+
+    fn spawn_request(&self, ollama: Ollama, request: CompletionRequest, index: usize) {
+        let handle = self.flower.handle(); // recv'd by gui thread
+        let stop_generation = self.stop_generating.clone();
+        let CompletionRequest {
+            model: model_name,
+            messages: mut context_messages,
+            images,
+            trimmed,
+            options: generation_options,
+            template,
+            keep_alive,
+            num_predict,
+            format,
+            raw,
+        } = request;
+        let trimmed_summary = self.trimmed_summary.clone();
+        let summary_error = self.summary_error.clone();
+        let image_cache = self.image_cache.clone();
+        tokio::spawn(async move {
+            handle.activate();
+            // decoding and resizing images would block the task's thread
+            tokio::task::block_in_place(|| image_cache.attach(&mut context_messages, &images));
+            if raw {
+                let _ = request_raw_completion(
+                    ollama,
+                    context_messages,
+                    &handle,
+                    stop_generation,
+                    model_name,
+                    generation_options,
+                    keep_alive,
+                    num_predict,
+                    format,
+                    index,
+                )
+                .await
+                .map_err(|e| {
+                    log::error!("failed to request raw completion: {e}");
+                    handle.error((index, AppError::from_error(&*e)));
+                });
+                return;
+            }
+            if let Some(trimmed) = trimmed {
+                match summarize_messages(&ollama, model_name.clone(), trimmed.messages).await {
+                    Ok(summary) => {
+                        // after the system prompt
+                        let at = context_messages
+                            .iter()
+                            .take_while(|m| m.role == MessageRole::System)
+                            .count();
+                        context_messages.insert(at, summary_note(&summary));
+                        *trimmed_summary.write() = Some((trimmed.key, summary));
+                    }
+                    Err(e) => {
+                        log::error!("failed to summarize trimmed messages: {e}");
+                        *summary_error.write() = Some(AppError::from_error(&*e));
+                    }
+                }
+            }
+            let _ = request_completion(
+                ollama,
+                context_messages,
+                &handle,
+                stop_generation,
+                model_name,
+                generation_options,
+                template,
+                keep_alive,
+                num_predict,
+                format,
+                index,
+            )
+            .await
+            .map_err(|e| {
+                log::error!("failed to request completion: {e}");
+                handle.error((index, AppError::from_error(&*e)));
+            });
+        });
+    }
+
+    /// Build the request [`Self::send_message`] would send for the chatbox, to be shown
+    /// instead of sending it
+    fn preview_request(&mut self) {
+        let prompt = self.chatbox_prompt();
+        let model_name = self.model_picker.selected_model().to_owned();
+
+        // the messages as they would be after sending
+        let messages = std::mem::take(&mut self.messages);
+        self.messages = messages.iter().filter(|m| !m.is_error).cloned().collect();
+        let mut message = Message::user(prompt.clone(), model_name.clone(), self.images.clone());
+        message.text_files.clone_from(&self.text_files);
+        message.original_images = self.send_original_images;
+        self.messages.push(message);
+        self.messages
+            .push(Message::assistant(String::new(), model_name.clone()));
+        let (context_messages, images, trimmed) = self.get_context_messages(self.messages.len());
+        self.messages = messages;
+
+        self.request_preview = Some(RequestPreview {
+            chatbox: self.chatbox.clone(),
+            prompt,
+            images: self.images.clone(),
+            text_files: self.text_files.clone(),
+            original_images: self.send_original_images,
+            request: self.completion_request(model_name, context_messages, images, trimmed),
+        });
+    }
+
+    /// Send the previewed request as it was built
+    fn send_preview(&mut self, ollama: &Ollama) {
+        let Some(preview) = self.request_preview.take() else {
+            return;
+        };
+        self.push_prompt_history(&preview.chatbox);
+        self.messages.retain(|m| !m.is_error);
+        let model_name = preview.request.model.clone();
+        let mut message = Message::user(preview.prompt, model_name.clone(), preview.images);
+        message.text_files = preview.text_files;
+        message.original_images = preview.original_images;
+        if self.summary.is_empty() {
+            self.summary = make_summary(&message.content_with_files());
+        }
+        self.messages.push(message);
+        self.chatbox.clear();
+        self.images.clear();
+        self.text_files.clear();
+        self.removed_images = None;
+
+        let mut response = Message::assistant(String::new(), model_name);
+        response.is_json = preview.request.format == Some(ResponseFormat::Json);
+        self.messages.push(response);
+        self.spawn_request(ollama.clone(), preview.request, self.messages.len() - 1);
+    }
+
+    /// Inspector of the previewed request, with a button to send it
+    fn show_request_preview(
+        &mut self,
+        ui: &mut egui::Ui,
+        modal: &Modal,
+        ollama: &Ollama,
+        can_send: bool,
+    ) {
+        let Some(preview) = &self.request_preview else {
+            return;
+        };
+        let request = &preview.request;
+        modal.title(ui, "Request Preview");
+        modal.frame(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(ui.ctx().screen_rect().height() * 0.6)
+                .show(ui, |ui| {
+                    egui::Grid::new("request_preview_grid")
+                        .num_columns(2)
+                        .show(ui, |ui| {
+                            ui.label("Model");
+                            ui.label(&request.model);
+                            ui.end_row();
+
+                            ui.label("Template");
+                            ui.label(if request.raw {
+                                "Not applied, raw mode"
+                            } else if request.template.is_some() {
+                                "Overridden"
+                            } else {
+                                "From the Modelfile"
+                            });
+                            ui.end_row();
+
+                            if let Some(keep_alive) = &request.keep_alive {
+                                ui.label("Keep alive");
+                                ui.label(format!("{keep_alive:?}"));
+                                ui.end_row();
+                            }
+                            if request.format.is_some() {
+                                ui.label("Format");
+                                ui.label("JSON");
+                                ui.end_row();
+                            }
+                        });
+
+                    if let Some(template) = request.template.as_ref().filter(|_| !request.raw) {
+                        ui.collapsing("Template", |ui| {
+                            ui.code_editor(&mut template.as_str());
+                        });
+                    }
+                    let options = serde_json::to_string_pretty(&request.options)
+                        .unwrap_or_else(|e| e.to_string());
+                    ui.collapsing("Options", |ui| {
+                        ui.code_editor(&mut options.as_str());
+                    });
+                    if let Some(trimmed) = &request.trimmed {
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "{} older messages will be summarized first, the summary is \
+                                sent after the system prompt",
+                                trimmed.messages.len()
+                            ))
+                            .color(ui.visuals().warn_fg_color),
+                        );
+                    }
+
+                    ui.separator();
+                    for (i, message) in request.messages.iter().enumerate() {
+                        let images = request
+                            .images
+                            .iter()
+                            .find(|images| images.message == i)
+                            .map_or(0, |images| images.paths.len());
+                        ui.horizontal(|ui| {
+                            ui.strong(format!("{:?}", message.role));
+                            if images > 0 {
+                                ui.weak(format!("+ {images} images"));
+                            }
+                        });
+                        ui.add(
+                            egui::TextEdit::multiline(&mut message.content.as_str())
+                                .font(TextStyle::Monospace)
+                                .desired_rows(1)
+                                .desired_width(f32::INFINITY),
+                        );
+                    }
+                });
+        });
+
+        let mut send = false;
+        modal.buttons(ui, |ui| {
+            if modal.button(ui, "Close").clicked() {
+                self.request_preview = None;
+            }
+            let can_send = can_send && !self.flower_active();
+            ui.add_enabled_ui(can_send, |ui| {
+                if modal.suggested_button(ui, "Send it").clicked() {
+                    send = true;
+                }
+            });
+        });
+        if send {
+            self.send_preview(ollama);
+        }
+        if self.request_preview.is_none() {
+            modal.close();
+        }
+    }
+
+    fn regenerate_response(&mut self, ollama: &Ollama, idx: usize) {
+        // remake context history to make the message we want to regenerate last
+        let (mut messages, images, trimmed) = self.get_context_messages(idx);
+
+        // start with the prepended message and update it in the displayed messages
+        messages.push(ChatMessage::assistant(self.prepend_buf.clone()));
+        self.messages[idx].content = self.prepend_buf.clone();
+        self.messages[idx].requested_at = Instant::now();
+        self.messages[idx].streamed_chunks = 0;
+        self.messages[idx].rating = None;
+        self.messages[idx].translation = None;
+        self.messages[idx].is_json =
+            self.model_picker.response_format() == Some(ResponseFormat::Json);
+        self.prepend_buf.clear();
+
+        // start completing the message
+        self.spawn_completion(
+            ollama.clone(),
+            messages,
+            images,
+            trimmed,
+            self.messages[idx].model_name.clone(),
+            idx,
+        );
+    }
+
+    /// Translate a response in the background with the translation model from the settings
+    fn translate_message(&mut self, ctx: &egui::Context, ollama: &Ollama, idx: usize) {
+        let model = if self.translation_model.is_empty() {
+            self.model_picker.selected_model().to_owned()
+        } else {
+            self.translation_model.clone()
+        };
+        let language = self.translation_language.trim().to_owned();
+        let Some(message) = self.messages.get_mut(idx) else {
+            return;
+        };
+        message.is_translating = true;
+        let content = message.answer().to_owned();
+        log::info!("translating message {idx} to {language} with `{model}`...");
+
+        let finished = self.finished_translations.clone();
+        let ollama = ollama.clone();
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            let result = translate(&ollama, model, &language, content.clone())
+                .await
+                .map_err(|e| AppError::from_error(&*e));
+            finished.write().push(FinishedTranslation {
+                index: idx,
+                content,
+                language,
+                result,
+            });
+            ctx.request_repaint();
+        });
+    }
+
+    /// Put finished translations under their messages, returns a toast if one failed
+    fn poll_translations(&mut self) -> Option<Toast> {
+        let finished = std::mem::take(&mut *self.finished_translations.write());
+        let mut toast = None;
+        for translation in finished {
+            let Some(message) = self
+                .messages
+                .get_mut(translation.index)
+                .filter(|m| m.is_translating)
+            else {
+                continue;
+            };
+            message.is_translating = false;
+            if message.answer() != translation.content {
+                log::warn!(
+                    "message {} changed while it was translated, dropping the translation",
+                    translation.index
+                );
+                continue;
+            }
+            match translation.result {
+                Ok(text) => {
+                    message.translation = Some(Translation {
+                        language: translation.language,
+                        text,
+                    });
+                }
+                Err(e) => {
+                    log::error!("failed to translate message {}: {e:?}", translation.index);
+                    toast = Some(Toast::error(format!("Translation failed: {}", e.title())));
+                }
+            }
+        }
+        toast
+    }
+
+    /// Continue generating a response that hit the token limit
+    fn continue_response(&mut self, ollama: &Ollama, idx: usize) {
+        if self.flower_active() {
+            return;
+        }
+        let message = &mut self.messages[idx];
+        message.is_generating = true;
+        message.done_reason = None;
+        self.prepend_buf = message.content.clone();
+        self.regenerate_response(ollama, idx);
+    }
+
+    /// Saved prompts matching the filter, clicking one replaces the chatbox with it or appends
+    /// it with Shift held
+    fn show_prompt_library(&mut self, ui: &mut egui::Ui, prompts: &mut Vec<SavedPrompt>) {
+        ui.set_min_width(240.0);
+        ui.add(
+            egui::TextEdit::singleline(&mut self.prompt_filter)
+                .hint_text("Filter prompts…")
+                .desired_width(f32::INFINITY),
+        );
+        let filter = self.prompt_filter.to_lowercase();
+        let mut remove = None;
+        egui::ScrollArea::vertical()
+            .max_height(240.0)
+            .show(ui, |ui| {
+                let matching = prompts.iter().enumerate().filter(|(_, p)| {
+                    p.name.to_lowercase().contains(&filter)
+                        || p.text.to_lowercase().contains(&filter)
+                });
+                let mut any = false;
+                for (i, prompt) in matching {
+                    any = true;
+                    ui.horizontal(|ui| {
+                        if ui
+                            .small_button("❌")
+                            .on_hover_text("Remove from the library")
+                            .clicked()
+                        {
+                            remove = Some(i);
+                        }
+                        let button = ui
+                            .add(egui::Button::new(&prompt.name).frame(false))
+                            .on_hover_ui(|ui| {
+                                ui.set_max_width(360.0);
+                                const MAX_PREVIEW_CHARS: usize = 400;
+                                let mut preview: String =
+                                    prompt.text.chars().take(MAX_PREVIEW_CHARS).collect();
+                                if preview.len() < prompt.text.len() {
+                                    preview.push('…');
+                                }
+                                ui.label(preview);
+                                ui.weak("Click to replace the chatbox, Shift+click to append");
+                            });
+                        if button.clicked() {
+                            if ui.input(|i| i.modifiers.shift) {
+                                if !self.chatbox.is_empty() && !self.chatbox.ends_with('\n') {
+                                    self.chatbox.push('\n');
+                                }
+                                self.chatbox += &prompt.text;
+                            } else {
+                                self.chatbox.clone_from(&prompt.text);
+                            }
+                            ui.close_menu();
+                        }
+                    });
+                }
+                if !any {
+                    ui.weak(if prompts.is_empty() {
+                        "No saved prompts yet"
+                    } else {
+                        "No prompts match the filter"
+                    });
+                }
+            });
+        if let Some(i) = remove {
+            prompts.remove(i);
+        }
+
+        ui.separator();
+        ui.label("Save current draft as prompt");
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.prompt_name)
+                    .hint_text("Name")
+                    .desired_width(160.0),
+            );
+            let name = self.prompt_name.trim();
+            let exists = prompts.iter().any(|p| p.name == name);
+            if ui
+                .add_enabled(
+                    !name.is_empty() && !self.chatbox.trim().is_empty(),
+                    egui::Button::new(if exists { "Replace" } else { "Save" }),
+                )
+                .clicked()
+            {
+                let text = self.chatbox.clone();
+                match prompts.iter_mut().find(|p| p.name == name) {
+                    Some(prompt) => prompt.text = text,
+                    None => prompts.push(SavedPrompt {
+                        name: name.to_owned(),
+                        text,
+                    }),
+                }
+                self.prompt_name.clear();
+            }
+        });
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn show_chatbox(
+        &mut self,
+        ui: &mut egui::Ui,
+        is_max_height: bool,
+        is_generating: bool,
+        can_send: bool,
+        ollama: &Ollama,
+        models: &[LocalModel],
+        prompts: &mut Vec<SavedPrompt>,
+    ) -> ChatAction {
+        let mut action = ChatAction::None;
+        if let Some(idx) = self.retry_message_idx.take() {
+            // don't run messages starting with `/` as commands
+            self.chatbox = commands::escape(&self.messages[idx - 1].content);
+            self.messages.remove(idx); // remove assistant message
+            self.messages.remove(idx - 1); // remove user message
+            self.send_message(ollama, models);
+        }
+
+        if is_max_height {
+            ui.add_space(8.0);
+        }
+
+        let context_bar_height = if self.raw_mode {
+            self.show_raw_mode(ui)
+        } else {
+            self.show_context_usage(ui)
+        };
+        let queued_height = self.show_queued_message(ui);
+        let removed_height = self.show_removed_images(ui);
+        let (duplicate_height, duplicate_action) = self.show_duplicate_send(ui, ollama, models);
+        let (oversized_height, oversized_action) = self.show_oversized_send(ui, ollama, models);
+        for sent in [duplicate_action, oversized_action] {
+            if !matches!(sent, ChatAction::None) {
+                action = sent;
+            }
+        }
+        let text_files_height = self.show_text_files(ui);
+
+        // thumbnails would take up the whole window
+        let compact_images = ui.ctx().screen_rect().height() < COMPACT_IMAGES_WINDOW_HEIGHT;
+        let images_height = if !self.images.is_empty() && compact_images {
+            let height = ui
+                .horizontal(|ui| {
+                    ui.label(match self.images.len() {
+                        1 => "🖼 1 image attached".to_owned(),
+                        n => format!("🖼 {n} images attached"),
+                    })
+                    .on_hover_text(
+                        self.images
+                            .iter()
+                            .map(|p| p.display().to_string())
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    );
+                    if ui
+                        .small_button("❌")
+                        .on_hover_text("Remove all images")
+                        .clicked()
+                    {
+                        self.images.clear();
+                    }
+                })
+                .response
+                .rect
+                .height();
+            height + ui.spacing().item_spacing.y
+        } else if !self.images.is_empty() {
+            ui.add_space(8.0);
+            let height = ui
+                .horizontal(|ui| {
+                    let removed = crate::image::show_images(
+                        ui,
+                        &mut self.images,
+                        true,
+                        Some(&mut self.send_original_images),
+                    );
+                    if !removed.is_empty() {
+                        self.removed_images = Some((removed, Instant::now()));
+                    }
+                })
+                .response
+                .rect
+                .height();
+            height + 16.0
+        } else {
+            0.0
+        };
+
+        ui.horizontal_centered(|ui| {
+            let pick_images = ui
+                .add(
+                    egui::Button::new("➕")
+                        .min_size(vec2(32.0, 32.0))
+                        .rounding(Rounding::same(f32::INFINITY)),
+                )
+                .on_hover_text_at_pointer("Pick Images. Right click to attach a folder");
+            if pick_images.clicked() {
+                action = ChatAction::PickImages { id: self.id() };
+            }
+            pick_images.context_menu(|ui| {
+                let recursive_id = egui::Id::new("attach_folder_recursive");
+                let mut recursive =
+                    ui.data_mut(|d| *d.get_persisted_mut_or_default::<bool>(recursive_id));
+                if ui.button("Pick Images…").clicked() {
+                    action = ChatAction::PickImages { id: self.id() };
+                    ui.close_menu();
+                }
+                if ui.button("Attach Folder…").clicked() {
+                    action = ChatAction::PickImageFolder {
+                        id: self.id(),
+                        recursive,
+                    };
+                    ui.close_menu();
+                }
+                if ui.checkbox(&mut recursive, "Include subfolders").changed() {
+                    ui.data_mut(|d| d.insert_persisted(recursive_id, recursive));
+                }
+            });
+            egui::menu::menu_custom_button(
+                ui,
+                egui::Button::new("📋")
+                    .min_size(vec2(32.0, 32.0))
+                    .rounding(Rounding::same(f32::INFINITY)),
+                |ui| self.show_prompt_library(ui, prompts),
+            )
+            .response
+            .on_hover_text_at_pointer("Prompt Library");
+            if ui
+                .add(
+                    egui::Button::new("📄")
+                        .min_size(vec2(32.0, 32.0))
+                        .rounding(Rounding::same(f32::INFINITY)),
+                )
+                .on_hover_text_at_pointer("Attach Text or Audio Files")
+                .clicked()
+            {
+                action = ChatAction::PickFiles { id: self.id() };
+            }
+            let can_preview = !self.chatbox_is_empty()
+                && commands::parse(&self.chatbox).is_none()
+                && self.comparison.is_none();
+            if ui
+                .add_enabled(
+                    can_preview,
+                    egui::Button::new("👁")
+                        .min_size(vec2(32.0, 32.0))
+                        .rounding(Rounding::same(f32::INFINITY)),
+                )
+                .on_hover_text_at_pointer("Preview Request, show exactly what would be sent")
+                .clicked()
+            {
+                self.preview_request();
             }
             ui.with_layout(
                 Layout::left_to_right(Align::Center).with_main_justify(true),
@@ -772,76 +3979,382 @@ impl Chat {
                         ui.fonts(|f| f.layout_job(layout_job))
                     };
 
-                    self.chatbox_height = egui::TextEdit::multiline(&mut self.chatbox)
-                        .return_key(KeyboardShortcut::new(Modifiers::SHIFT, Key::Enter))
-                        .hint_text("Ask me anything…")
+                    let output = egui::TextEdit::multiline(&mut self.chatbox)
+                        .return_key(self.send_key.newline_shortcut())
+                        .hint_text(if !can_send {
+                            "Ollama is unreachable, sending is disabled…"
+                        } else if self.raw_mode {
+                            "Raw prompt, sent as is without the chat history or template…"
+                        } else {
+                            "Ask me anything… (type / for commands)"
+                        })
                         .layouter(&mut layouter)
-                        .show(ui)
-                        .response
-                        .rect
-                        .height()
-                        + images_height;
-                    if !is_generating
-                        && ui.input(|i| i.key_pressed(Key::Enter) && i.modifiers.is_none())
+                        .show(ui);
+                    let chatbox = output.response;
+                    self.chatbox_height = chatbox.rect.height()
+                        + images_height
+                        + context_bar_height
+                        + queued_height
+                        + removed_height
+                        + duplicate_height
+                        + oversized_height
+                        + text_files_height;
+                    if chatbox.changed() {
+                        self.command_error = None;
+                        self.history_pos = None;
+                        self.duplicate_pending = false;
+                        self.oversized_pending = None;
+                    }
+                    self.show_command_popup(ui, &chatbox, models);
+
+                    // commands don't need the server
+                    let composing = ime_composing(ui, &chatbox);
+
+                    // only recall prompts if the arrows wouldn't move the cursor otherwise,
+                    // the cursor is checked from the previous frame before it moved
+                    if chatbox.has_focus() && !composing {
+                        let (up, down) = ui.input(|i| {
+                            (
+                                i.key_pressed(Key::ArrowUp) && i.modifiers.is_none(),
+                                i.key_pressed(Key::ArrowDown) && i.modifiers.is_none(),
+                            )
+                        });
+                        let (first_row, last_row) = self.cursor_rows;
+                        let recalled = (up && first_row && self.recall_prompt(true))
+                            || (down && last_row && self.recall_prompt(false));
+                        if recalled {
+                            move_cursor_to_end(ui.ctx(), chatbox.id, &self.chatbox);
+                        }
+                    }
+                    let last_row = output.galley.rows.len().saturating_sub(1);
+                    self.cursor_rows = output.cursor_range.map_or((true, true), |range| {
+                        let rows = (range.primary.rcursor.row, range.secondary.rcursor.row);
+                        (rows == (0, 0), rows == (last_row, last_row))
+                    });
+                    let is_command = commands::parse(&self.chatbox).is_some();
+                    if (can_send || is_command)
+                        && !composing
+                        && ui.input(|i| self.send_key.pressed(i))
                     {
-                        self.send_message(ollama);
+                        if !is_generating {
+                            action = self.send_message(ollama, models);
+                        } else if !is_command {
+                            // commands could change the messages that are being generated
+                            self.queue_message();
+                        }
                     }
                 },
             );
         });
 
-        if is_max_height {
-            ui.add_space(8.0);
+        if is_max_height {
+            ui.add_space(8.0);
+        }
+
+        action
+    }
+
+    /// Show completions for the command being typed, or the error of the last command,
+    /// above the chatbox
+    fn show_command_popup(
+        &mut self,
+        ui: &mut egui::Ui,
+        chatbox: &egui::Response,
+        models: &[LocalModel],
+    ) {
+        const MAX_MODELS: usize = 8;
+
+        let text = self.chatbox.as_str();
+        let typing_command =
+            text.starts_with('/') && !text.starts_with("//") && !text.contains('\n');
+        if self.command_error.is_none() && !typing_command {
+            return;
+        }
+
+        let mut completion = None;
+        egui::Area::new(chatbox.id.with("command_popup"))
+            .order(egui::Order::Foreground)
+            .fixed_pos(chatbox.rect.left_top() - vec2(0.0, 4.0))
+            .pivot(egui::Align2::LEFT_BOTTOM)
+            .show(ui.ctx(), |ui| {
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    if let Some(error) = &self.command_error {
+                        ui.label(egui::RichText::new(error).color(ui.visuals().error_fg_color));
+                        return;
+                    }
+
+                    // `/compare` takes several models, complete the last one
+                    let model_arg = text
+                        .strip_prefix("/model ")
+                        .map(|filter| ("/model ".to_owned(), filter, ""))
+                        .or_else(|| {
+                            let rest = text.strip_prefix("/compare ")?;
+                            Some(match rest.rsplit_once(' ') {
+                                Some((done, filter)) => (format!("/compare {done} "), filter, " "),
+                                None => ("/compare ".to_owned(), rest, " "),
+                            })
+                        });
+                    if let Some((prefix, filter, suffix)) = model_arg {
+                        let filter = filter.trim().to_lowercase();
+                        let mut shown = models
+                            .iter()
+                            .filter(|m| m.name.to_lowercase().contains(&filter))
+                            .take(MAX_MODELS)
+                            .peekable();
+                        if shown.peek().is_none() {
+                            ui.label("No matching models");
+                        }
+                        for model in shown {
+                            if ui.selectable_label(false, &model.name).clicked() {
+                                completion = Some(format!("{prefix}{}{suffix}", model.name));
+                            }
+                        }
+                        return;
+                    }
+
+                    let typed = text.split_whitespace().next().unwrap_or_default();
+                    let mut shown = COMMANDS
+                        .iter()
+                        .filter(|(name, ..)| name.starts_with(typed))
+                        .peekable();
+                    if shown.peek().is_none() {
+                        ui.label("No matching commands, start with // to send a message");
+                    }
+                    egui::Grid::new("command_popup_grid")
+                        .num_columns(3)
+                        .show(ui, |ui| {
+                            for (name, args, description) in shown {
+                                if ui
+                                    .selectable_label(false, egui::RichText::new(*name).monospace())
+                                    .clicked()
+                                {
+                                    completion = Some(if args.is_empty() {
+                                        name.to_string()
+                                    } else {
+                                        format!("{name} ")
+                                    });
+                                }
+                                ui.label(egui::RichText::new(*args).monospace().weak());
+                                ui.label(*description);
+                                ui.end_row();
+                            }
+                        });
+                });
+            });
+
+        if let Some(completion) = completion {
+            self.chatbox = completion;
+
+            // move the cursor to the end and focus the chatbox again
+            move_cursor_to_end(ui.ctx(), chatbox.id, &self.chatbox);
+            chatbox.request_focus();
         }
+    }
 
-        action
+    /// Nothing was sent, typed or set up in the chat since it was created
+    pub fn is_untouched(&self) -> bool {
+        self.messages.is_empty()
+            && self.chatbox_is_empty()
+            && self.system_prompt.is_empty()
+            && self.comparison.is_none()
+            && !self.flower_active()
     }
 
     #[inline]
     pub fn flower_active(&self) -> bool {
-        self.flower.is_active()
+        self.flower.is_active() || self.comparison.as_ref().is_some_and(Comparison::is_active)
+    }
+
+    /// Stop generating the response, if one is being generated
+    pub fn stop_generating(&self) {
+        if self.flower.is_active() {
+            self.stop_generating.store(true, Ordering::SeqCst);
+        }
+        if let Some(comparison) = &self.comparison {
+            comparison.stop_generating();
+        }
     }
 
-    pub fn poll_flower(&mut self, modal: &mut Modal) {
+    /// Returns whether the completion failed
+    pub fn poll_flower(
+        &mut self,
+        #[cfg(feature = "tts")] tts: SharedTts,
+        #[cfg(feature = "tts")] auto_read: bool,
+        #[cfg(feature = "tts")] read_while_generating: bool,
+    ) -> Option<Finished> {
+        #[cfg(feature = "tts")]
+        let auto_read = self.auto_read.unwrap_or(auto_read);
+        #[cfg(feature = "tts")]
+        let read_while_generating = auto_read && read_while_generating;
+        let mut finished = None;
+        if let Some(comparison) = &mut self.comparison {
+            comparison.poll();
+        }
         self.flower
             .extract(|(idx, progress)| {
+                #[cfg(feature = "tts")]
+                let start = self.messages[idx].content.len();
                 self.messages[idx].content += progress.as_str();
+                self.messages[idx].streamed_chunks += 1;
+
+                // speak the sentences that were completed by this chunk
+                #[cfg(feature = "tts")]
+                if read_while_generating {
+                    let (spoken_until, started) = *self.tts_stream.get_or_insert((start, false));
+                    let content = &self.messages[idx].content;
+                    // don't read the reasoning
+                    let spoken_until = answer_start(content).map(|a| spoken_until.max(a));
+                    if let Some((spoken_until, end)) = spoken_until.and_then(|spoken_until| {
+                        last_sentence_end(&content[spoken_until..]).map(|end| (spoken_until, end))
+                    }) {
+                        let end = spoken_until + end;
+                        let sentences = content[spoken_until..end].trim();
+                        if !sentences.is_empty() {
+                            // the first sentence interrupts whatever was being read before
+                            tts_enqueue(&tts, sentences, !started);
+                            if !started {
+                                for msg in self.messages.iter_mut() {
+                                    msg.is_speaking = false;
+                                }
+                                self.messages[idx].is_speaking = true;
+                            }
+                            self.tts_stream = Some((end, true));
+                        }
+                    }
+                }
             })
             .finalize(|result| {
-                if let Ok((idx, content)) = result {
+                #[cfg(feature = "tts")]
+                let tts_stream = self.tts_stream.take();
+                if let Ok((idx, content, done_reason)) = result {
+                    #[cfg(feature = "tts")]
+                    if let Some((spoken_until, started)) = tts_stream {
+                        if done_reason == DoneReason::Aborted {
+                            // stopping the generation also stops reading it
+                            if started {
+                                self.messages[idx].is_speaking = false;
+                                tts_control(tts.clone(), String::new(), false);
+                            }
+                        } else {
+                            // read what's left after the last complete sentence
+                            let content = &self.messages[idx].content;
+                            let rest = answer_start(content)
+                                .and_then(|a| content.get(spoken_until.max(a)..))
+                                .unwrap_or_default()
+                                .trim();
+                            if !rest.is_empty() {
+                                tts_enqueue(&tts, rest, !started);
+                                if !started {
+                                    for msg in self.messages.iter_mut() {
+                                        msg.is_speaking = false;
+                                    }
+                                    self.messages[idx].is_speaking = true;
+                                }
+                            }
+                        }
+                    } else if auto_read && !content.is_empty() {
+                        // speaking interrupts the previous message, update the icons
+                        for msg in self.messages.iter_mut() {
+                            msg.is_speaking = false;
+                        }
+                        self.messages[idx].is_speaking = true;
+                        tts_control(tts.clone(), split_reasoning(&content).1.to_owned(), true);
+                    }
                     let message = &mut self.messages[idx];
                     message.content = content.clone();
                     message.is_generating = false;
+                    message.done_reason = Some(done_reason);
+                    message.generation_secs = Some(message.requested_at.elapsed().as_secs_f64());
+                    if done_reason == DoneReason::Aborted {
+                        self.unqueue_message();
+                    } else {
+                        self.has_unread = true;
+                        finished = Some(Finished::Response(split_reasoning(&content).1.to_owned()));
+                    }
                 } else if let Err(e) = result {
-                    let (idx, msg) = match e {
-                        Compact::Panicked(e) => {
-                            (self.messages.len() - 1, format!("Tokio task panicked: {e}"))
-                        }
+                    let (idx, e) = match e {
+                        Compact::Panicked(e) => (
+                            self.messages.len() - 1,
+                            AppError::Panicked(format!("Tokio task panicked: {e}")),
+                        ),
                         Compact::Suppose((idx, e)) => (idx, e),
                     };
                     let message = &mut self.messages[idx];
-                    message.content = msg.clone();
+                    message.content = e.reason().into_owned();
                     message.is_error = true;
-                    modal
-                        .dialog()
-                        .with_body(msg)
-                        .with_title("Failed to generate completion!")
-                        .with_icon(Icon::Error)
-                        .open();
+                    message.error = Some(e.clone());
                     message.is_generating = false;
+                    finished = Some(Finished::Failed(e));
+                    self.has_unread = true;
+                    self.unqueue_message();
                 }
             });
+        finished
+    }
+
+    /// Model, rating and generation time of the rated responses
+    pub fn ratings(
+        &self,
+    ) -> impl Iterator<Item = (&str, Rating, chrono::DateTime<chrono::Utc>)> + '_ {
+        self.messages
+            .iter()
+            .filter_map(|m| Some((m.model_name.as_str(), m.rating?, m.time)))
+    }
+
+    /// Role labels and contents of the last `n` non-empty messages, oldest first
+    pub fn recent_messages(&self, n: usize) -> Vec<(String, &str)> {
+        let mut messages: Vec<_> = self
+            .messages
+            .iter()
+            .rev()
+            .filter(|m| !m.answer().is_empty())
+            .take(n)
+            .map(|m| (m.role_label(), m.answer()))
+            .collect();
+        messages.reverse();
+        messages
+    }
+
+    /// Add the transcript of an audio file to the chatbox as a quote
+    pub fn insert_transcript(&mut self, file_name: &str, transcript: &str) {
+        if !self.chatbox.is_empty() && !self.chatbox.ends_with('\n') {
+            self.chatbox.push('\n');
+        }
+        self.chatbox += &format!("`{file_name}`:\n");
+        for line in transcript.lines() {
+            self.chatbox += &format!("> {line}\n");
+        }
+        self.chatbox.push('\n');
+    }
+
+    /// Unsent chatbox text, or the number of attached images or files if there is no text
+    pub fn draft(&self) -> Option<String> {
+        let text = self.chatbox.trim();
+        if !text.is_empty() {
+            Some(text.lines().next().unwrap_or_default().to_owned())
+        } else if !self.images.is_empty() {
+            Some(match self.images.len() {
+                1 => "1 image".to_owned(),
+                n => format!("{n} images"),
+            })
+        } else if !self.text_files.is_empty() {
+            Some(match self.text_files.len() {
+                1 => "1 file".to_owned(),
+                n => format!("{n} files"),
+            })
+        } else {
+            None
+        }
     }
 
     pub fn last_message_contents(&self) -> Option<String> {
         for message in self.messages.iter().rev() {
-            if message.content.is_empty() {
+            if message.answer().is_empty() {
                 continue;
             }
-            return Some(if message.is_user() {
-                format!("You: {}", message.content)
-            } else {
-                message.content.to_string()
+            return Some(match message.role {
+                Role::Assistant => message.answer().to_string(),
+                _ => format!("{}: {}", message.role_label(), message.content),
             });
         }
         None
@@ -849,102 +4362,343 @@ impl Chat {
 
     fn stop_generating_button(&self, ui: &mut egui::Ui, radius: f32, pos: Pos2) {
         let rect = Rect::from_min_max(pos + vec2(-radius, -radius), pos + vec2(radius, radius));
-        let (hovered, primary_clicked) = ui.input(|i| {
-            (
-                i.pointer
-                    .interact_pos()
-                    .map(|p| rect.contains(p))
-                    .unwrap_or(false),
-                i.pointer.primary_clicked(),
-            )
-        });
-        if hovered && primary_clicked {
-            self.stop_generating.store(true, Ordering::SeqCst);
+        ui.interact(rect, ui.id().with("stop_generating"), egui::Sense::hover())
+            .on_hover_text(crate::shortcuts::hint(
+                ui.ctx(),
+                "Stop generating",
+                &crate::shortcuts::STOP,
+            ));
+        if floating_button(ui, radius, pos) {
+            self.stop_generating();
         } else {
-            ui.painter().circle(
-                pos,
-                radius,
-                if hovered {
-                    ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
-                    if ui.style().visuals.dark_mode {
-                        let c = ui.style().visuals.faint_bg_color;
-                        Color32::from_rgb(c.r(), c.g(), c.b())
-                    } else {
-                        Color32::WHITE
-                    }
-                } else {
-                    ui.style().visuals.window_fill
-                },
-                Stroke::new(2.0, ui.style().visuals.window_stroke.color),
-            );
             ui.painter().rect_stroke(
                 rect.shrink(radius / 2.0 + 1.2),
                 2.0,
-                Stroke::new(2.0, Color32::DARK_GRAY),
+                crate::style::icon_stroke(ui),
             );
         }
     }
 
+    /// Floating ⬇ button that scrolls to the last message and follows new content again
+    fn scroll_to_bottom_button(&mut self, ui: &mut egui::Ui, radius: f32, pos: Pos2) {
+        if floating_button(ui, radius, pos) {
+            self.scroll.jump = true;
+            self.scroll.stick = true;
+        } else {
+            let stroke = crate::style::icon_stroke(ui);
+            let size = radius / 2.5;
+            let tip = pos + vec2(0.0, size);
+            ui.painter()
+                .line_segment([pos - vec2(0.0, size), tip], stroke);
+            ui.painter()
+                .line_segment([tip - vec2(size, size), tip], stroke);
+            ui.painter()
+                .line_segment([tip + vec2(size, -size), tip], stroke);
+        }
+    }
+
     fn show_chat_scrollarea(
         &mut self,
         ui: &mut egui::Ui,
         ollama: &Ollama,
         commonmark_cache: &mut CommonMarkCache,
         #[cfg(feature = "tts")] tts: SharedTts,
+        models: &[LocalModel],
     ) -> Option<usize> {
         let mut new_speaker: Option<usize> = None;
         let mut any_prepending = false;
         let mut regenerate_response_idx = None;
-        egui::ScrollArea::both()
-            .stick_to_bottom(true)
+        let mut continue_response_idx = None;
+        let mut translate_idx = None;
+        let translate_to = self.translation_language.clone();
+        let stop_sequences = self.model_picker.stop_sequences().to_vec();
+        let context_start = self.context_start(self.messages.len());
+        let summarize_trimmed = self.auto_trim == AutoTrim::Summarize;
+        let timestamp_format = self.timestamp_format;
+        let quote_template = self.quote_template.clone();
+        let always_show_actions = self.always_show_actions;
+        let max_width = self.max_width;
+        let exchanges = if self.group_exchanges {
+            exchange_numbers(&self.messages)
+        } else {
+            Vec::new()
+        };
+        // the streaming message grows every frame, follow its bottom as it's laid out
+        // instead of the content size of the previous frame
+        let streaming = self.messages.last().is_some_and(|m| m.is_generating);
+        let mut streaming_bottom = None;
+        let output = egui::ScrollArea::both()
+            .id_source(("chat_scrollarea", self.id()))
+            .stick_to_bottom(self.scroll.stick && !streaming)
             .auto_shrink(false)
             .show(ui, |ui| {
-                ui.add_space(16.0);
-                self.virtual_list
-                    .ui_custom_layout(ui, self.messages.len(), |ui, index| {
-                        let Some(message) = self.messages.get_mut(index) else {
-                            return 0;
-                        };
-                        let prev_speaking = message.is_speaking;
-                        if any_prepending && message.is_prepending {
-                            message.is_prepending = false;
-                        }
-                        let action = message.show(
-                            ui,
-                            commonmark_cache,
-                            #[cfg(feature = "tts")]
-                            tts.clone(),
-                            index,
-                            &mut self.prepend_buf,
-                        );
-                        match action {
-                            MessageAction::None => (),
-                            MessageAction::Retry(idx) => {
-                                self.retry_message_idx = Some(idx);
+                centered_column(ui, max_width, |ui| {
+                    ui.add_space(16.0);
+                    let scroll_to_bottom = std::mem::take(&mut self.scroll.jump);
+                    self.virtual_list
+                        .ui_custom_layout(ui, self.messages.len(), |ui, index| {
+                            let local_day =
+                                |m: &Message| m.time.with_timezone(&chrono::Local).date_naive();
+                            let prev_day = index
+                                .checked_sub(1)
+                                .and_then(|i| self.messages.get(i))
+                                .map(local_day);
+                            let Some(message) = self.messages.get_mut(index) else {
+                                return 0;
+                            };
+                            let day = local_day(message);
+                            // the first day is only labeled if it isn't today
+                            let new_day = match prev_day {
+                                Some(prev) => prev != day,
+                                None => day != chrono::Local::now().date_naive(),
+                            };
+                            if new_day {
+                                ui.separator();
+                                ui.vertical_centered(|ui| {
+                                    ui.label(egui::RichText::new(day_label(day)).small().weak());
+                                });
                             }
-                            MessageAction::Regenerate(idx) => {
-                                regenerate_response_idx = Some(idx);
+                            let prev_speaking = message.is_speaking;
+                            if any_prepending && message.is_prepending {
+                                message.is_prepending = false;
                             }
-                        }
-                        any_prepending |= message.is_prepending;
-                        if !prev_speaking && message.is_speaking {
-                            new_speaker = Some(index);
-                        }
-                        1 // 1 rendered item per row
-                    });
+                            if index > 0 && index == context_start {
+                                ui.separator();
+                                ui.vertical_centered(|ui| {
+                                    ui.label(
+                                        egui::RichText::new(if summarize_trimmed {
+                                            "older messages summarized for the model"
+                                        } else {
+                                            "older messages not sent to the model"
+                                        })
+                                        .small()
+                                        .weak(),
+                                    );
+                                });
+                            }
+                            // painted behind the message once its height is known, the
+                            // layout stays the same so the virtual list heights don't change
+                            let exchange = exchanges.get(index).copied().flatten();
+                            let background = ui.painter().add(egui::Shape::Noop);
+                            let row_top = ui.cursor().top();
+                            let action = message.show(
+                                ui,
+                                commonmark_cache,
+                                #[cfg(feature = "tts")]
+                                tts.clone(),
+                                index,
+                                &mut self.prepend_buf,
+                                &stop_sequences,
+                                models,
+                                timestamp_format,
+                                &quote_template,
+                                always_show_actions,
+                                &translate_to,
+                            );
+                            match action {
+                                MessageAction::None => (),
+                                MessageAction::Retry(idx) => {
+                                    self.retry_message_idx = Some(idx);
+                                }
+                                MessageAction::Regenerate(idx) => {
+                                    regenerate_response_idx = Some(idx);
+                                }
+                                MessageAction::Continue(idx) => {
+                                    continue_response_idx = Some(idx);
+                                }
+                                MessageAction::Translate(idx) => {
+                                    translate_idx = Some(idx);
+                                }
+                            }
+                            if let Some(exchange) = exchange {
+                                let same = |i: Option<usize>| {
+                                    i.and_then(|i| exchanges.get(i)).copied().flatten()
+                                        == Some(exchange)
+                                };
+                                // rows of an exchange join up across the item spacing
+                                let joins_prev = same(index.checked_sub(1))
+                                    && !new_day
+                                    && index != context_start;
+                                let joins_next = same(Some(index + 1));
+                                let bottom = ui.cursor().top()
+                                    + if joins_next {
+                                        ui.spacing().item_spacing.y
+                                    } else {
+                                        0.0
+                                    };
+                                let radius = |joined: bool| if joined { 0.0 } else { 6.0 };
+                                let rounding = Rounding {
+                                    nw: radius(joins_prev),
+                                    ne: radius(joins_prev),
+                                    sw: radius(joins_next),
+                                    se: radius(joins_next),
+                                };
+                                let tint = if exchange % 2 == 0 { 0.04 } else { 0.08 };
+                                let fill = ui.visuals().text_color().gamma_multiply(tint);
+                                let rect = Rect::from_x_y_ranges(
+                                    ui.max_rect().x_range(),
+                                    row_top..=bottom,
+                                );
+                                ui.painter().set(
+                                    background,
+                                    egui::Shape::rect_filled(rect, rounding, fill),
+                                );
+                            }
+                            any_prepending |= message.is_prepending;
+                            if message.is_generating {
+                                streaming_bottom = Some(ui.cursor().top());
+                            }
+                            if !prev_speaking && message.is_speaking {
+                                new_speaker = Some(index);
+                            }
+                            1 // 1 rendered item per row
+                        });
+                    if scroll_to_bottom {
+                        ui.scroll_to_cursor(Some(Align::BOTTOM));
+                    } else if let Some(bottom) = streaming_bottom.filter(|_| self.scroll.stick) {
+                        let anchor =
+                            Rect::from_x_y_ranges(ui.max_rect().x_range(), bottom..=bottom);
+                        ui.scroll_to_rect(anchor, Some(Align::BOTTOM));
+                    }
+                })
             });
+        let max_offset = output.content_size.y - output.inner_rect.height();
+        let offset = output.state.offset.y;
+        self.scroll.at_bottom = offset >= max_offset - 1.0;
+        if self.scroll.at_bottom {
+            self.scroll.stick = true;
+        } else if offset < self.scroll.offset - 1.0 && max_offset >= self.scroll.max_offset - 1.0 {
+            // the user scrolled up, content growing only moves the bottom away. The offset
+            // also goes down when the virtual list estimates the content shorter than before,
+            // which shouldn't stop following the response.
+            self.scroll.stick = false;
+        }
+        self.scroll.offset = offset;
+        self.scroll.max_offset = max_offset;
         if let Some(regenerate_idx) = regenerate_response_idx {
             self.regenerate_response(ollama, regenerate_idx);
         }
+        if let Some(continue_idx) = continue_response_idx {
+            self.continue_response(ollama, continue_idx);
+        }
+        if let Some(translate_idx) = translate_idx {
+            self.translate_message(ui.ctx(), ollama, translate_idx);
+        }
         new_speaker
     }
 
+    /// Show the responses of the compared models side by side
+    fn show_comparison(&mut self, ui: &mut egui::Ui, commonmark_cache: &mut CommonMarkCache) {
+        let Some(comparison) = &self.comparison else {
+            return;
+        };
+        let mut close = false;
+        let mut keep = None;
+
+        ui.add_space(8.0);
+        ui.horizontal(|ui| {
+            ui.heading("Comparison");
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                if ui
+                    .button("✖ Close")
+                    .on_hover_text("Stop comparing and go back to the chat")
+                    .clicked()
+                {
+                    close = true;
+                }
+            });
+        });
+        if comparison.prompt.is_empty() {
+            ui.weak("Type a prompt to send it to all models at once");
+        } else {
+            ui.label(egui::RichText::new(&comparison.prompt).strong());
+        }
+        ui.separator();
+
+        let sent = !comparison.prompt.is_empty();
+        ui.columns(comparison.columns.len(), |uis| {
+            for (i, (ui, column)) in uis.iter_mut().zip(&comparison.columns).enumerate() {
+                ui.horizontal(|ui| {
+                    ui.strong(make_short_name(&column.model))
+                        .on_hover_text(&column.model);
+                    if column.flower.is_active() {
+                        if ui
+                            .small_button("⏹")
+                            .on_hover_text(crate::shortcuts::hint(
+                                ui.ctx(),
+                                "Stop generating",
+                                &crate::shortcuts::STOP,
+                            ))
+                            .clicked()
+                        {
+                            column.stop_generating.store(true, Ordering::SeqCst);
+                        }
+                        ui.spinner();
+                        ui.weak(format!(
+                            "{:.1}s",
+                            column.requested_at.elapsed().as_secs_f64()
+                        ));
+                    } else if sent && column.error.is_none() {
+                        if ui
+                            .small_button("Keep")
+                            .on_hover_text("Add the prompt and this response to the chat")
+                            .clicked()
+                        {
+                            keep = Some(i);
+                        }
+                        if let Some(done_reason) = column.done_reason {
+                            ui.weak(done_reason.description());
+                        }
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .id_source(("comparison_column", i))
+                    .auto_shrink(false)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        if let Some(error) = &column.error {
+                            ui.colored_label(ui.visuals().error_fg_color, error.title());
+                            ui.label(&*error.reason());
+                            if let Some(suggestion) = error.suggestion() {
+                                ui.weak(suggestion);
+                            }
+                        } else {
+                            CommonMarkViewer::new().show(ui, commonmark_cache, &column.response);
+                        }
+                    });
+            }
+        });
+
+        if let Some(i) = keep {
+            let column = &comparison.columns[i];
+            let mut response = Message::assistant(column.response.clone(), column.model.clone());
+            response.is_generating = false;
+            response.done_reason = column.done_reason;
+            if self.summary.is_empty() {
+                self.summary = make_summary(&comparison.prompt);
+            }
+            self.messages.push(Message::user(
+                comparison.prompt.clone(),
+                column.model.clone(),
+                comparison.images.clone(),
+            ));
+            self.messages.push(response);
+            close = true;
+        }
+        if close {
+            comparison.stop_generating();
+            self.comparison = None;
+        }
+    }
+
     fn send_text(&mut self, ollama: &Ollama, text: &str) {
+        // suggestions are never commands
         self.chatbox = text.to_owned();
-        self.send_message(ollama);
+        self.send_message(ollama, &[]);
     }
 
-    fn show_suggestions(&mut self, ui: &mut egui::Ui, ollama: &Ollama) {
+    fn show_suggestions(&mut self, ui: &mut egui::Ui, ollama: &Ollama, can_send: bool) {
         egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
             widgets::centerer(ui, |ui| {
                 let avail_width = ui.available_rect_before_wrap().width() - 24.0;
@@ -956,46 +4710,53 @@ impl Chat {
                         });
                     }
                 });
-                egui::Grid::new("suggestions_grid")
-                    .num_columns(3)
-                    .max_col_width((avail_width / 2.0).min(200.0))
-                    .spacing(vec2(6.0, 6.0))
-                    .show(ui, |ui| {
-                        if widgets::suggestion(ui, "Tell me a fun fact", "about the Roman empire")
+                ui.add_enabled_ui(can_send, |ui| {
+                    egui::Grid::new("suggestions_grid")
+                        .num_columns(3)
+                        .max_col_width((avail_width / 2.0).min(200.0))
+                        .spacing(vec2(6.0, 6.0))
+                        .show(ui, |ui| {
+                            if widgets::suggestion(
+                                ui,
+                                "Tell me a fun fact",
+                                "about the Roman empire",
+                            )
                             .clicked()
-                        {
-                            self.send_text(ollama, "Tell me a fun fact about the Roman empire");
-                        }
-                        if widgets::suggestion(
-                            ui,
-                            "Show me a code snippet",
-                            "of a web server in Rust",
-                        )
-                        .clicked()
-                        {
-                            self.send_text(
-                                ollama,
-                                "Show me a code snippet of a web server in Rust",
-                            );
-                        }
-                        widgets::dummy(ui);
-                        ui.end_row();
-
-                        if widgets::suggestion(ui, "Tell me a joke", "about crabs").clicked() {
-                            self.send_text(ollama, "Tell me a joke about crabs");
-                        }
-                        if widgets::suggestion(ui, "Give me ideas", "for a birthday present")
+                            {
+                                self.send_text(ollama, "Tell me a fun fact about the Roman empire");
+                            }
+                            if widgets::suggestion(
+                                ui,
+                                "Show me a code snippet",
+                                "of a web server in Rust",
+                            )
                             .clicked()
-                        {
-                            self.send_text(ollama, "Give me ideas for a birthday present");
-                        }
-                        widgets::dummy(ui);
-                        ui.end_row();
-                    });
+                            {
+                                self.send_text(
+                                    ollama,
+                                    "Show me a code snippet of a web server in Rust",
+                                );
+                            }
+                            widgets::dummy(ui);
+                            ui.end_row();
+
+                            if widgets::suggestion(ui, "Tell me a joke", "about crabs").clicked() {
+                                self.send_text(ollama, "Tell me a joke about crabs");
+                            }
+                            if widgets::suggestion(ui, "Give me ideas", "for a birthday present")
+                                .clicked()
+                            {
+                                self.send_text(ollama, "Give me ideas for a birthday present");
+                            }
+                            widgets::dummy(ui);
+                            ui.end_row();
+                        });
+                });
             });
         });
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn show(
         &mut self,
         ctx: &egui::Context,
@@ -1003,27 +4764,77 @@ impl Chat {
         #[cfg(feature = "tts")] tts: SharedTts,
         #[cfg(feature = "tts")] stopped_speaking: bool,
         commonmark_cache: &mut CommonMarkCache,
+        can_send: bool,
+        models: &[LocalModel],
+        prompts: &mut Vec<SavedPrompt>,
+        max_height_fraction: f32,
     ) -> ChatAction {
         let avail = ctx.available_rect();
-        let max_height = avail.height() * 0.4 + 24.0;
+        let max_height = max_chatbox_panel_height(avail.height(), max_height_fraction);
         let chatbox_panel_height = self.chatbox_height + 24.0;
-        let actual_chatbox_panel_height = chatbox_panel_height.min(max_height);
+        let actual_chatbox_panel_height =
+            chatbox_panel_height.clamp(MIN_CHATBOX_PANEL_HEIGHT, max_height);
+        debug_assert!(
+            actual_chatbox_panel_height <= avail.height().max(MIN_CHATBOX_PANEL_HEIGHT),
+            "chatbox panel taller than the window"
+        );
         let is_generating = self.flower_active();
         let mut action = ChatAction::None;
 
-        egui::TopBottomPanel::bottom("chatbox_panel")
+        // chats shown in other windows at the same time need their own ids
+        egui::TopBottomPanel::bottom(egui::Id::new(("chatbox_panel", self.id())))
             .exact_height(actual_chatbox_panel_height)
             .show(ctx, |ui| {
                 egui::ScrollArea::vertical().show(ui, |ui| {
-                    action = self.show_chatbox(
-                        ui,
-                        chatbox_panel_height >= max_height,
-                        is_generating,
-                        ollama,
-                    );
+                    let max_width = self.max_width;
+                    action = centered_column(ui, max_width, |ui| {
+                        self.show_chatbox(
+                            ui,
+                            chatbox_panel_height >= max_height,
+                            is_generating,
+                            can_send,
+                            ollama,
+                            models,
+                            prompts,
+                        )
+                    });
                 });
             });
 
+        let preview_modal = Modal::new(ctx, "request_preview_modal");
+        if self.request_preview.is_some() && !preview_modal.is_open() {
+            preview_modal.open();
+        }
+        preview_modal.show(|ui| {
+            self.show_request_preview(ui, &preview_modal, ollama, can_send);
+        });
+
+        let clear_modal = Modal::new(ctx, "clear_history_modal");
+        if std::mem::take(&mut self.confirm_clear) {
+            clear_modal.open();
+        }
+        clear_modal.show(|ui| {
+            clear_modal.title(ui, "Clear History");
+            clear_modal.frame(ui, |ui| {
+                clear_modal.body_and_icon(
+                    ui,
+                    "Do you really want to remove all messages in this chat? \
+                    You cannot undo this action later.",
+                    Icon::Warning,
+                );
+                clear_modal.buttons(ui, |ui| {
+                    if clear_modal.button(ui, "No").clicked() {
+                        clear_modal.close();
+                    }
+                    if clear_modal.caution_button(ui, "Yes").clicked() {
+                        clear_modal.close();
+                        self.messages.clear();
+                        self.virtual_list = VirtualList::new();
+                    }
+                });
+            });
+        });
+
         #[cfg(feature = "tts")]
         let mut new_speaker: Option<usize> = None;
 
@@ -1035,8 +4846,22 @@ impl Chat {
                 bottom: 3.0,
             }))
             .show(ctx, |ui| {
-                if self.messages.is_empty() {
-                    self.show_suggestions(ui, ollama);
+                if let Some(toast) = self.poll_translations() {
+                    action = ChatAction::Toast(toast);
+                }
+                if let Some(e) = self.summary_error.write().take() {
+                    action = ChatAction::Toast(Toast::warning(format!(
+                        "Older messages couldn't be summarized and were left out: {}",
+                        e.title()
+                    )));
+                }
+                if let Some(toast) = self.image_cache.take_failed_toast() {
+                    action = ChatAction::Toast(toast);
+                }
+                if self.comparison.is_some() {
+                    self.show_comparison(ui, commonmark_cache);
+                } else if self.messages.is_empty() {
+                    self.show_suggestions(ui, ollama, can_send);
                 } else {
                     #[allow(unused_variables)]
                     if let Some(new) = self.show_chat_scrollarea(
@@ -1045,6 +4870,7 @@ impl Chat {
                         commonmark_cache,
                         #[cfg(feature = "tts")]
                         tts,
+                        models,
                     ) {
                         #[cfg(feature = "tts")]
                         {
@@ -1052,16 +4878,18 @@ impl Chat {
                         }
                     }
 
-                    // stop generating button
+                    // stop generating button, with the scroll button above it, at the
+                    // right edge of the messages
+                    let mut pos = pos2(
+                        column_rect(ui.max_rect(), self.max_width).max.x - 32.0,
+                        (avail.height() - 32.0 - actual_chatbox_panel_height).max(32.0),
+                    );
                     if is_generating {
-                        self.stop_generating_button(
-                            ui,
-                            16.0,
-                            pos2(
-                                ui.cursor().max.x - 32.0,
-                                avail.height() - 32.0 - actual_chatbox_panel_height,
-                            ),
-                        );
+                        self.stop_generating_button(ui, 16.0, pos);
+                        pos.y -= 40.0;
+                    }
+                    if !self.scroll.at_bottom {
+                        self.scroll_to_bottom_button(ui, 16.0, pos);
                     }
                 }
             });
@@ -1088,3 +4916,293 @@ impl Chat {
         action
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::{Event, ImeEvent, Modifiers};
+
+    fn preedit(text: &str) -> Event {
+        Event::Ime(ImeEvent::Preedit(text.to_owned()))
+    }
+
+    fn commit(text: &str) -> Event {
+        Event::Ime(ImeEvent::Commit(text.to_owned()))
+    }
+
+    fn enter() -> Event {
+        Event::Key {
+            key: Key::Enter,
+            physical_key: None,
+            pressed: true,
+            repeat: false,
+            modifiers: Modifiers::NONE,
+        }
+    }
+
+    #[test]
+    fn enter_during_composition_belongs_to_ime() {
+        assert_eq!(ime_state(false, &[preedit("か"), enter()]), (true, true));
+        // the composition goes on in the next frames
+        assert_eq!(ime_state(true, &[enter()]), (true, true));
+    }
+
+    #[test]
+    fn enter_committing_composition_belongs_to_ime() {
+        assert_eq!(ime_state(true, &[commit("か"), enter()]), (false, true));
+        assert_eq!(
+            ime_state(true, &[Event::Ime(ImeEvent::Disabled)]),
+            (false, true)
+        );
+    }
+
+    #[test]
+    fn enter_after_composition_sends() {
+        let (composing, _) = ime_state(false, &[preedit("´"), commit("é")]);
+        assert_eq!(ime_state(composing, &[enter()]), (false, false));
+    }
+
+    #[test]
+    fn cancelled_composition_ends_it() {
+        assert_eq!(ime_state(true, &[preedit("")]), (false, false));
+    }
+
+    #[test]
+    fn enter_without_ime_sends() {
+        assert_eq!(ime_state(false, &[enter()]), (false, false));
+        assert_eq!(ime_state(false, &[]), (false, false));
+    }
+
+    #[test]
+    fn text_file_is_cut_at_a_char_boundary() {
+        let path = std::env::temp_dir().join(format!("ellama-text-{}.txt", fastrand::u64(..)));
+        std::fs::write(&path, "ab\u{e9}cd").unwrap();
+        let cut = TextFile::read(&path, 3);
+        let whole = TextFile::read(&path, 6);
+        std::fs::remove_file(&path).unwrap();
+
+        let cut = cut.unwrap();
+        assert_eq!(cut.text, "ab");
+        assert!(cut.truncated);
+        assert_eq!(cut.size, 6);
+        let whole = whole.unwrap();
+        assert_eq!(whole.text, "ab\u{e9}cd");
+        assert!(!whole.truncated);
+    }
+
+    #[test]
+    fn split_parts_fit_in_max_len() {
+        assert_eq!(split_message("short text", 100), ["short text"]);
+        assert_eq!(split_message("aaaa\nbbbb\ncccc", 9), ["aaaa\nbbbb", "cccc"]);
+        let text = "one two three four five six seven eight nine ten";
+        let parts = split_message(text, 12);
+        assert!(parts.iter().all(|p| p.len() <= 12), "{parts:?}");
+        assert_eq!(parts.join(" "), text);
+    }
+
+    #[test]
+    fn split_keeps_code_blocks_whole() {
+        let block = "```rust\nlet a = 1;\n\nlet b = 2;\n```";
+        let text = format!("intro\n{block}\nafter");
+        assert_eq!(split_message(&text, 10), ["intro", block, "after"]);
+
+        // a longer fence isn't closed by a shorter one
+        let block = "````\n```\ninner\n```\n````";
+        assert_eq!(
+            split_message(&format!("{block}\nafter"), 5),
+            [block, "after"]
+        );
+    }
+
+    #[test]
+    fn split_doesnt_cut_words() {
+        let word = "x".repeat(20);
+        assert_eq!(
+            split_message(&format!("a {word} b"), 5),
+            ["a", word.as_str(), "b"]
+        );
+    }
+
+    #[test]
+    fn split_counts_bytes_of_multibyte_chars() {
+        // each word takes up 5 or 6 bytes
+        assert_eq!(
+            split_message("h\u{e9}llo w\u{f6}rld \u{fc}n\u{ef}", 7),
+            ["h\u{e9}llo", "w\u{f6}rld", "\u{fc}n\u{ef}"]
+        );
+        assert_eq!(
+            split_message("\u{1f999}\u{1f999} \u{1f999}", 9),
+            ["\u{1f999}\u{1f999}", "\u{1f999}"]
+        );
+    }
+
+    #[cfg(feature = "tts")]
+    #[test]
+    fn finds_the_last_sentence_end() {
+        assert_eq!(last_sentence_end("Hello. World"), Some(6));
+        assert_eq!(last_sentence_end("Hi! there? yes"), Some(10));
+        assert_eq!(last_sentence_end("line\nnext"), Some(5));
+        assert_eq!(
+            last_sentence_end("\u{dc}n\u{ef}c\u{f6}d\u{e9}. x"),
+            Some(12)
+        );
+        // the sentence may go on
+        assert_eq!(last_sentence_end("e.g. 1.5 or"), Some(4));
+        assert_eq!(last_sentence_end("the end."), None);
+        assert_eq!(last_sentence_end("no end"), None);
+    }
+
+    /// Lines read from a response received in `chunks`
+    fn json_lines(chunks: &[&str]) -> Vec<String> {
+        let mut buf = Vec::new();
+        let mut lines = Vec::new();
+        let mut read = |buf: &mut Vec<u8>, ended| {
+            while let Some(line) = take_json_line(buf, ended) {
+                lines.push(String::from_utf8(line).unwrap().trim_end().to_owned());
+            }
+        };
+        for chunk in chunks {
+            buf.extend_from_slice(chunk.as_bytes());
+            read(&mut buf, false);
+        }
+        read(&mut buf, true);
+        lines
+    }
+
+    #[test]
+    fn joins_lines_split_across_chunks() {
+        assert_eq!(
+            json_lines(&[r#"{"a":"#, r#"1}"#, "\n", r#"{"b""#, ":2}\n"]),
+            [r#"{"a":1}"#, r#"{"b":2}"#]
+        );
+
+        // a multibyte char split between chunks
+        let line = "{\"content\":\"\u{e9}\"}\n";
+        assert!(!line.is_char_boundary(13));
+        let (start, end) = line.as_bytes().split_at(13);
+        let mut buf = start.to_vec();
+        assert_eq!(take_json_line(&mut buf, false), None);
+        buf.extend_from_slice(end);
+        assert_eq!(take_json_line(&mut buf, false).unwrap(), line.as_bytes());
+    }
+
+    #[test]
+    fn splits_several_lines_in_one_chunk() {
+        assert_eq!(
+            json_lines(&["{\"a\":1}\n\n{\"b\":2}\r\n{\"c\":3}\n"]),
+            [r#"{"a":1}"#, r#"{"b":2}"#, r#"{"c":3}"#]
+        );
+    }
+
+    #[test]
+    fn reads_last_line_without_newline() {
+        assert_eq!(
+            json_lines(&["{\"a\":1}\n{\"done\":true}"]),
+            [r#"{"a":1}"#, r#"{"done":true}"#]
+        );
+        assert!(json_lines(&["\n \n", " "]).is_empty());
+        let mut buf = b"{\"a\"".to_vec();
+        assert_eq!(take_json_line(&mut buf, false), None);
+        assert_eq!(buf, b"{\"a\"");
+    }
+
+    #[test]
+    fn reads_errors_and_done_reasons_from_chunks() {
+        let chunk: ChatChunk =
+            serde_json::from_str(r#"{"error":"model \"llama9\" not found, try pulling it first"}"#)
+                .unwrap();
+        assert_eq!(
+            chunk.error.as_deref(),
+            Some("model \"llama9\" not found, try pulling it first")
+        );
+        assert!(!chunk.done);
+
+        let chunk: ChatChunk = serde_json::from_str(
+            r#"{"message":{"role":"assistant","content":""},"done":true,"done_reason":"length","eval_count":128}"#,
+        )
+        .unwrap();
+        assert!(chunk.done);
+        assert_eq!(
+            parse_done_reason(chunk.done_reason.as_deref(), chunk.eval_count, Some(128)),
+            DoneReason::Length
+        );
+        let chunk: GenerateChunk =
+            serde_json::from_str(r#"{"response":"","done":true,"done_reason":"stop"}"#).unwrap();
+        assert_eq!(
+            parse_done_reason(chunk.done_reason.as_deref(), chunk.eval_count, None),
+            DoneReason::Stop
+        );
+    }
+
+    #[test]
+    fn guesses_done_reason_without_one() {
+        assert_eq!(parse_done_reason(None, 64, Some(64)), DoneReason::Length);
+        assert_eq!(parse_done_reason(None, 10, Some(64)), DoneReason::Stop);
+        assert_eq!(parse_done_reason(None, 64, Some(-1)), DoneReason::Stop);
+        assert_eq!(parse_done_reason(None, 64, None), DoneReason::Stop);
+        // the reason Ollama sent wins over the guess
+        assert_eq!(
+            parse_done_reason(Some("stop"), 64, Some(64)),
+            DoneReason::Stop
+        );
+    }
+
+    fn exported_messages() -> Vec<Message> {
+        let time = "2024-09-01T12:00:00Z".parse().unwrap();
+        let mut prompt = Message::user("Hi".to_owned(), "llama3".to_owned(), Vec::new());
+        prompt.time = time;
+        let mut response = Message::assistant("Hello!".to_owned(), "llama3".to_owned());
+        response.time = time;
+        vec![prompt, response]
+    }
+
+    #[test]
+    fn plaintext_export_follows_timestamps_setting() {
+        let messages = exported_messages();
+        assert_eq!(
+            messages_to_plaintext(&messages, true),
+            "2024-09-01T12:00:00+00:00 - User (llama3): Hi\n\
+            2024-09-01T12:00:00+00:00 - Assistant (llama3): Hello!\n"
+        );
+        assert_eq!(
+            messages_to_plaintext(&messages, false),
+            "User (llama3): Hi\nAssistant (llama3): Hello!\n"
+        );
+    }
+
+    #[test]
+    fn markdown_export_follows_timestamps_setting() {
+        let messages = exported_messages();
+        assert!(messages_to_markdown(&messages, true).contains("2024-09-01T12:00:00+00:00"));
+        assert!(!messages_to_markdown(&messages, false).contains("2024-09-01"));
+    }
+
+    /// Chat with `queued` queued and `draft` typed after it
+    fn chat_with_queued(queued: &str, draft: &str) -> Chat {
+        let mut chat = Chat::default();
+        chat.chatbox = queued.to_owned();
+        chat.queue_message();
+        chat.chatbox = draft.to_owned();
+        chat
+    }
+
+    #[test]
+    fn queued_invalid_command_goes_back_to_the_chatbox() {
+        let mut chat = chat_with_queued("/nonsense", "draft");
+        let action = chat.send_queued_message(&Ollama::default(), &[]);
+        assert!(matches!(action, ChatAction::None));
+        assert!(chat.queued_message.is_none());
+        assert_eq!(chat.chatbox, "/nonsense\ndraft");
+        assert!(chat.command_error.is_some());
+        assert!(chat.messages.is_empty());
+    }
+
+    #[test]
+    fn queued_command_keeps_the_draft_and_returns_its_action() {
+        let mut chat = chat_with_queued("/temp 0.5", "draft");
+        let action = chat.send_queued_message(&Ollama::default(), &[]);
+        assert!(matches!(action, ChatAction::Toast(_)));
+        assert!(chat.queued_message.is_none());
+        assert_eq!(chat.chatbox, "draft");
+    }
+}
@@ -0,0 +1,162 @@
+/// Commands that can be typed into the chatbox, with their arguments and descriptions
+pub const COMMANDS: &[(&str, &str, &str)] = &[
+    ("/model", "<name>", "Switch the model used in this chat"),
+    (
+        "/system",
+        "<text>",
+        "Set the system prompt, leave empty to remove it",
+    ),
+    ("/clear", "", "Clear the chat history"),
+    ("/export", "", "Export the chat to a file"),
+    ("/temp", "<number>", "Set the temperature, e.g. /temp 1.2"),
+    (
+        "/compare",
+        "<model> <model>…",
+        "Send the next prompts to several models side by side",
+    ),
+];
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Model(String),
+    System(String),
+    Clear,
+    Export,
+    Temperature(f32),
+    Compare(Vec<String>),
+}
+
+/// Parse the chatbox contents as a command. Returns `None` if they aren't one, messages
+/// starting with `//` are sent as messages starting with `/`.
+pub fn parse(text: &str) -> Option<Result<Command, String>> {
+    let rest = text.trim_end().strip_prefix('/')?;
+    if rest.starts_with('/') {
+        return None;
+    }
+    let (name, arg) = rest
+        .split_once(char::is_whitespace)
+        .map(|(name, arg)| (name, arg.trim()))
+        .unwrap_or((rest, ""));
+
+    Some(match name {
+        "model" if arg.is_empty() => Err("Usage: /model <name>".to_owned()),
+        "model" => Ok(Command::Model(arg.to_owned())),
+        "system" => Ok(Command::System(arg.to_owned())),
+        "clear" => Ok(Command::Clear),
+        "export" => Ok(Command::Export),
+        "temp" => arg
+            .parse::<f32>()
+            .ok()
+            .filter(|t| t.is_finite() && *t >= 0.0)
+            .map(Command::Temperature)
+            .ok_or_else(|| "Usage: /temp <number>, e.g. /temp 0.8".to_owned()),
+        "compare" => {
+            let models: Vec<_> = arg.split_whitespace().map(str::to_owned).collect();
+            if models.len() < 2 {
+                Err("Usage: /compare <model> <model>…, with at least two models".to_owned())
+            } else {
+                Ok(Command::Compare(models))
+            }
+        }
+        _ => Err(format!(
+            "Unknown command `/{name}`, start the message with `//` to send it as is"
+        )),
+    })
+}
+
+/// Remove the escaping `/` from messages starting with `//`
+pub fn unescape(text: &str) -> &str {
+    match text.strip_prefix('/') {
+        Some(rest) if rest.starts_with('/') => rest,
+        _ => text,
+    }
+}
+
+/// Escape a message so it isn't parsed as a command when it's sent again
+pub fn escape(text: &str) -> String {
+    if text.starts_with('/') {
+        format!("/{text}")
+    } else {
+        text.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            parse("/model llama3:8b").unwrap(),
+            Ok(Command::Model("llama3:8b".to_owned()))
+        );
+        assert_eq!(
+            parse("/system  Be brief.  \n").unwrap(),
+            Ok(Command::System("Be brief.".to_owned()))
+        );
+        assert_eq!(
+            parse("/system").unwrap(),
+            Ok(Command::System(String::new()))
+        );
+        assert_eq!(parse("/clear").unwrap(), Ok(Command::Clear));
+        assert_eq!(parse("/export").unwrap(), Ok(Command::Export));
+        assert_eq!(parse("/temp 0.8").unwrap(), Ok(Command::Temperature(0.8)));
+        assert_eq!(
+            parse("/compare a b  c").unwrap(),
+            Ok(Command::Compare(vec!["a".into(), "b".into(), "c".into()]))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        let err = parse("/nonsense arg").unwrap().unwrap_err();
+        assert!(err.contains("/nonsense"), "{err}");
+        assert!(parse("/").unwrap().is_err());
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        for text in [
+            "/model",
+            "/model   ",
+            "/temp",
+            "/temp hot",
+            "/temp -1",
+            "/temp NaN",
+            "/temp inf",
+            "/compare",
+            "/compare one",
+        ] {
+            assert!(parse(text).unwrap().is_err(), "{text} should be rejected");
+        }
+    }
+
+    #[test]
+    fn messages_arent_commands() {
+        assert!(parse("hello /model x").is_none());
+        assert!(parse(" /clear").is_none());
+        assert!(parse("//clear").is_none());
+        assert!(parse("").is_none());
+    }
+
+    #[test]
+    fn unescapes_double_slash() {
+        assert_eq!(unescape("//clear"), "/clear");
+        assert_eq!(unescape("///x"), "//x");
+        assert_eq!(unescape("/clear"), "/clear");
+        assert_eq!(unescape("a // b"), "a // b");
+    }
+
+    #[test]
+    fn escape_round_trips() {
+        for text in ["/clear", "//x", "plain", "", "a /b"] {
+            let escaped = escape(text);
+            assert!(
+                parse(&escaped).is_none(),
+                "{escaped} would be run as a command"
+            );
+            assert_eq!(unescape(&escaped), text);
+        }
+    }
+}
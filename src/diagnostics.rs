@@ -0,0 +1,158 @@
+use crate::{chat::Chat, widgets::Settings};
+use std::{collections::VecDeque, fmt::Write as _, sync::Mutex};
+
+/// Number of log lines kept for the diagnostic info
+const MAX_LOG_LINES: usize = 50;
+
+/// Versions of the GUI libraries, read from `Cargo.lock` by the build script
+const EGUI_VERSION: &str = match option_env!("ELLAMA_EGUI_VERSION") {
+    Some(version) => version,
+    None => "unknown",
+};
+const EFRAME_VERSION: &str = match option_env!("ELLAMA_EFRAME_VERSION") {
+    Some(version) => version,
+    None => "unknown",
+};
+
+/// Last log lines of the app, oldest first
+static LOG_LINES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Prints logs like `env_logger` does and keeps the last lines of the app's own logs for
+/// the diagnostic info, even when `RUST_LOG` doesn't print them
+struct Logger {
+    inner: env_logger::Logger,
+}
+
+impl Logger {
+    fn is_captured(metadata: &log::Metadata<'_>) -> bool {
+        metadata.level() <= log::Level::Info && metadata.target().starts_with("ellama")
+    }
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        Self::is_captured(metadata) || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if Self::is_captured(record.metadata()) {
+            let line = format!(
+                "{} {} {}: {}",
+                chrono::Local::now().format("%H:%M:%S%.3f"),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+            let mut lines = LOG_LINES.lock().unwrap_or_else(|e| e.into_inner());
+            if lines.len() >= MAX_LOG_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line);
+        }
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Set up logging, configured with `RUST_LOG` like `env_logger::init`
+pub fn init_logger() {
+    let inner = env_logger::Builder::from_default_env().build();
+    let max_level = inner.filter().max(log::LevelFilter::Info);
+    if log::set_boxed_logger(Box::new(Logger { inner })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Names of the top level settings that differ from the defaults
+fn changed_settings(settings: &Settings) -> Vec<String> {
+    let (Ok(serde_json::Value::Object(current)), Ok(serde_json::Value::Object(defaults))) = (
+        serde_json::to_value(settings),
+        serde_json::to_value(Settings::default()),
+    ) else {
+        return Vec::new();
+    };
+    current
+        .into_iter()
+        .filter(|(name, value)| defaults.get(name) != Some(value))
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Text for bug reports. Only the names of changed settings are included, the endpoint is
+/// reduced to its host and credentials are removed from the log lines.
+pub fn report(
+    settings: &Settings,
+    chats: &[Chat],
+    server_version: Option<&str>,
+    zoom: f32,
+) -> String {
+    let url = settings.endpoint_url();
+    let mut log_lines: Vec<String> = LOG_LINES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .cloned()
+        .collect();
+    if !url.username().is_empty() || url.password().is_some() {
+        let credentials = match url.password() {
+            Some(password) => format!("{}:{password}@", url.username()),
+            None => format!("{}@", url.username()),
+        };
+        for line in &mut log_lines {
+            *line = line.replace(&credentials, "");
+        }
+    }
+
+    let mut text = String::new();
+    writeln!(text, "Ellama {}", env!("CARGO_PKG_VERSION")).ok();
+    writeln!(
+        text,
+        "OS: {} ({})",
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    )
+    .ok();
+    writeln!(text, "egui {EGUI_VERSION}, eframe {EFRAME_VERSION}").ok();
+    writeln!(
+        text,
+        "Ollama: {}",
+        server_version.unwrap_or("unknown, the server couldn't be reached")
+    )
+    .ok();
+    writeln!(text, "Endpoint: {}", url.origin().ascii_serialization()).ok();
+    writeln!(
+        text,
+        "Theme: {:?}, zoom: {:.0}%",
+        settings.theme,
+        zoom * 100.0
+    )
+    .ok();
+    let changed = changed_settings(settings);
+    writeln!(
+        text,
+        "Changed settings: {}",
+        if changed.is_empty() {
+            "none".to_owned()
+        } else {
+            changed.join(", ")
+        }
+    )
+    .ok();
+    writeln!(
+        text,
+        "Chats: {}, messages: {}",
+        chats.len(),
+        chats.iter().map(|c| c.messages.len()).sum::<usize>()
+    )
+    .ok();
+    writeln!(text, "\nLast {} log lines:", log_lines.len()).ok();
+    for line in log_lines {
+        writeln!(text, "{line}").ok();
+    }
+    text
+}
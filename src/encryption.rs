@@ -0,0 +1,143 @@
+use anyhow::{anyhow, Result};
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use base64_stream::{FromBase64Reader, ToBase64Reader};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Nonce,
+};
+use std::io::Read;
+use subtle::ConstantTimeEq;
+
+const SALT_LEN: usize = 16;
+
+/// Key derived from the user's passphrase. This is never serialized, the user
+/// has to enter the passphrase on each startup.
+pub struct EncryptionKey {
+    key: [u8; 32],
+    salt: Vec<u8>,
+}
+
+/// Encrypted data, as stored on disk
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct EncryptedData {
+    salt: String,
+    nonce: String,
+    data: String,
+}
+
+fn to_base64(bytes: &[u8]) -> Result<String> {
+    let mut s = String::new();
+    ToBase64Reader::new(bytes).read_to_string(&mut s)?;
+    Ok(s)
+}
+
+fn from_base64(s: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    FromBase64Reader::new(s.as_bytes()).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+impl EncryptionKey {
+    /// Derive a key from the passphrase with a new random salt
+    pub fn new(passphrase: &str) -> Result<Self> {
+        let mut salt = vec![0; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive(passphrase, salt)
+    }
+
+    fn derive(passphrase: &str, salt: Vec<u8>) -> Result<Self> {
+        let mut key = [0; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| anyhow!("failed to derive key: {e}"))?;
+        Ok(Self { key, salt })
+    }
+
+    /// Check whether the key was derived from a passphrase. The check can be moved to another
+    /// thread, deriving the key is slow on purpose.
+    pub fn matcher(&self) -> impl FnOnce(&str) -> bool + Send + 'static {
+        let (key, salt) = (self.key, self.salt.clone());
+        move |passphrase| {
+            Self::derive(passphrase, salt)
+                .is_ok_and(|derived| derived.key[..].ct_eq(&key[..]).into())
+        }
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<EncryptedData> {
+        let cipher =
+            ChaCha20Poly1305::new_from_slice(&self.key).map_err(|e| anyhow!("invalid key: {e}"))?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let data = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|e| anyhow!("failed to encrypt: {e}"))?;
+        Ok(EncryptedData {
+            salt: to_base64(&self.salt)?,
+            nonce: to_base64(&nonce)?,
+            data: to_base64(&data)?,
+        })
+    }
+}
+
+/// Hash a PIN to be stored, in the PHC string format
+pub fn hash_pin(pin: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(pin.as_bytes(), &salt)
+        .map_err(|e| anyhow!("failed to hash PIN: {e}"))?
+        .to_string())
+}
+
+/// Whether the PIN matches a hash made with [`hash_pin`]
+pub fn verify_pin(pin: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(pin.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+impl EncryptedData {
+    /// Derive the key from the passphrase and decrypt the data, fails if the passphrase is wrong
+    pub fn decrypt(&self, passphrase: &str) -> Result<(EncryptionKey, Vec<u8>)> {
+        let key = EncryptionKey::derive(passphrase, from_base64(&self.salt)?)?;
+        let cipher =
+            ChaCha20Poly1305::new_from_slice(&key.key).map_err(|e| anyhow!("invalid key: {e}"))?;
+        let nonce = from_base64(&self.nonce)?;
+        if nonce.len() != 12 {
+            return Err(anyhow!("invalid nonce length"));
+        }
+        let plaintext = cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                from_base64(&self.data)?.as_slice(),
+            )
+            .map_err(|_| anyhow!("wrong passphrase"))?;
+        Ok((key, plaintext))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matcher_only_accepts_the_passphrase() {
+        let key = EncryptionKey::new("correct horse").unwrap();
+        assert!(key.matcher()("correct horse"));
+        assert!(!key.matcher()("correct horse "));
+        assert!(!key.matcher()(""));
+    }
+
+    #[test]
+    fn decrypts_with_the_passphrase() {
+        let key = EncryptionKey::new("correct horse").unwrap();
+        let data = key.encrypt(b"chats").unwrap();
+        let (decrypted_key, plaintext) = data.decrypt("correct horse").unwrap();
+        assert_eq!(plaintext, b"chats");
+        assert!(decrypted_key.matcher()("correct horse"));
+        assert!(data.decrypt("battery staple").is_err());
+    }
+}
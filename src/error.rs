@@ -0,0 +1,211 @@
+use std::borrow::Cow;
+
+/// Errors from Ollama requests and background tasks, grouped by what the user can do about them
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum AppError {
+    /// The Ollama server couldn't be reached
+    Connection(String),
+    /// The requested model isn't installed
+    ModelNotFound(String),
+    /// Ollama couldn't decode an attached image
+    Image(String),
+    /// There isn't enough memory to load the model
+    OutOfMemory(String),
+    /// A background task panicked
+    Panicked(String),
+    Other(String),
+}
+
+impl AppError {
+    /// Guess the kind of error from its message, ollama-rs only gives us a string
+    pub fn classify(message: impl Into<String>) -> Self {
+        let message = message.into();
+        let lower = message.to_lowercase();
+        let has = |patterns: &[&str]| patterns.iter().any(|p| lower.contains(p));
+
+        if has(&[
+            "error sending request",
+            "connection refused",
+            "connection reset",
+            "tcp connect",
+            "dns error",
+            "failed to lookup address",
+            "timed out",
+        ]) {
+            Self::Connection(message)
+        } else if lower.contains("model") && has(&["not found", "pull"]) {
+            Self::ModelNotFound(message)
+        } else if has(&[
+            "out of memory",
+            "requires more system memory",
+            "insufficient memory",
+            "cudamalloc failed",
+        ]) {
+            Self::OutOfMemory(message)
+        } else if has(&["image", "base64"])
+            && has(&["decode", "unknown format", "invalid", "illegal"])
+        {
+            Self::Image(message)
+        } else {
+            Self::Other(message)
+        }
+    }
+
+    /// Classify an error returned by a request, looking through to the reqwest error if there is one
+    pub fn from_error(e: &(dyn std::error::Error + 'static)) -> Self {
+        match e.downcast_ref::<reqwest::Error>() {
+            Some(e) if e.is_connect() || e.is_timeout() => Self::Connection(e.to_string()),
+            _ => Self::classify(e.to_string()),
+        }
+    }
+
+    /// The original error message
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Connection(s)
+            | Self::ModelNotFound(s)
+            | Self::Image(s)
+            | Self::OutOfMemory(s)
+            | Self::Panicked(s)
+            | Self::Other(s) => s,
+        }
+    }
+
+    /// The `error` field if the message contains an Ollama JSON error body like
+    /// `{"error":"..."}`, otherwise the whole message
+    pub fn reason(&self) -> Cow<'_, str> {
+        let message = self.message();
+        message
+            .find('{')
+            .zip(message.rfind('}'))
+            .and_then(|(start, end)| message.get(start..=end))
+            .and_then(|json| serde_json::from_str::<ErrorBody>(json).ok())
+            .map_or(Cow::Borrowed(message), |body| Cow::Owned(body.error))
+    }
+
+    /// Name of the missing model, Ollama quotes it like `model 'llama3' not found`
+    pub fn missing_model(&self) -> Option<String> {
+        let Self::ModelNotFound(_) = self else {
+            return None;
+        };
+        let reason = self.reason();
+        let rest = &reason[reason.find("model")? + "model".len()..];
+        let rest = rest.trim_start().strip_prefix(['\'', '"'])?;
+        let end = rest.find(['\'', '"'])?;
+        Some(rest[..end].to_owned()).filter(|name| !name.is_empty())
+    }
+
+    /// Short title for dialogs and toasts
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Connection(_) => "Can't reach Ollama",
+            Self::ModelNotFound(_) => "Model not found",
+            Self::Image(_) => "Couldn't read an image",
+            Self::OutOfMemory(_) => "Not enough memory",
+            Self::Panicked(_) => "Something went wrong",
+            Self::Other(_) => "Request failed",
+        }
+    }
+
+    /// What the user can try to fix the error
+    pub fn suggestion(&self) -> Option<&'static str> {
+        match self {
+            Self::Connection(_) => {
+                Some("Make sure Ollama is running and the endpoint in Settings is correct.")
+            }
+            Self::ModelNotFound(_) => {
+                Some("Pull the model in Settings or pick another one in the chat settings.")
+            }
+            Self::Image(_) => Some("Remove the attached image or convert it to PNG or JPEG."),
+            Self::OutOfMemory(_) => Some(
+                "Close other apps using the GPU, lower the context size in the inference \
+                settings or pick a smaller model.",
+            ),
+            Self::Panicked(_) => Some("This is a bug, please report it."),
+            Self::Other(_) => None,
+        }
+    }
+
+    /// Error message followed by the suggestion, for dialog bodies
+    pub fn body(&self) -> String {
+        match self.suggestion() {
+            Some(suggestion) => format!("{}\n\n{suggestion}", self.reason()),
+            None => self.reason().into_owned(),
+        }
+    }
+}
+
+/// Body of Ollama's error responses
+#[derive(serde::Deserialize)]
+struct ErrorBody {
+    error: String,
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::from_error(&e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_connection_refused() {
+        let e = AppError::classify(
+            "error sending request for url (http://127.0.0.1:11434/api/chat): \
+            tcp connect error: Connection refused (os error 111)",
+        );
+        assert!(matches!(e, AppError::Connection(_)));
+        assert!(e.suggestion().is_some());
+    }
+
+    #[test]
+    fn classify_model_not_found() {
+        let e = AppError::classify(
+            r#"404 Not Found: {"error":"model 'llama3' not found, try pulling it first"}"#,
+        );
+        assert!(matches!(e, AppError::ModelNotFound(_)));
+        assert_eq!(e.reason(), "model 'llama3' not found, try pulling it first");
+        assert_eq!(e.missing_model().as_deref(), Some("llama3"));
+    }
+
+    #[test]
+    fn classify_generic_error() {
+        let e = AppError::classify("500 Internal Server Error: something broke");
+        assert_eq!(
+            e,
+            AppError::Other("500 Internal Server Error: something broke".into())
+        );
+        assert_eq!(e.title(), "Request failed");
+        assert_eq!(e.missing_model(), None);
+        assert_eq!(e.body(), "500 Internal Server Error: something broke");
+    }
+
+    #[test]
+    fn from_error_classifies_other_errors_by_message() {
+        let io = std::io::Error::other("out of memory");
+        assert!(matches!(
+            AppError::from_error(&io),
+            AppError::OutOfMemory(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn from_error_detects_refused_connections() {
+        // nothing listens on port 1
+        let e = reqwest::Client::new()
+            .get("http://127.0.0.1:1")
+            .send()
+            .await
+            .expect_err("the connection should be refused");
+        assert!(matches!(AppError::from(e), AppError::Connection(_)));
+    }
+}
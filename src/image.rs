@@ -1,7 +1,8 @@
 use anyhow::Result;
 use base64_stream::ToBase64Reader;
 use eframe::egui::{self, vec2, Color32, Rect, RichText, Stroke};
-use image::ImageFormat;
+use egui::{Align2, Id, Key, Order, Sense};
+use image::{imageops::FilterType, DynamicImage, ImageDecoder as _, ImageFormat};
 use ollama_rs::generation::images::Image;
 use std::{
     fs::File,
@@ -9,17 +10,52 @@ use std::{
     path::{Path, PathBuf},
 };
 
-pub fn convert_image(path: &Path) -> Result<Image> {
-    let f = BufReader::new(File::open(path)?);
-
+/// Read an image for sending it to Ollama. Images with a side longer than `max_dimension`
+/// are downscaled to it.
+pub fn convert_image(path: &Path, max_dimension: Option<u32>) -> Result<Image> {
     // ollama only supports png and jpeg, we have to convert to png
     // whenever needed
     let format = ImageFormat::from_path(path)?;
-    if !matches!(format, ImageFormat::Png | ImageFormat::Jpeg) {
-        log::debug!("got {format:?} image, converting to png");
-        let img = image::load(f, format)?;
+    let oversized = match max_dimension {
+        Some(max) => {
+            let (width, height) = image::image_dimensions(path)?;
+            width.max(height) > max
+        }
+        None => false,
+    };
+    if oversized || !matches!(format, ImageFormat::Png | ImageFormat::Jpeg) {
+        let mut decoder = image::ImageReader::open(path)?.into_decoder()?;
+        let orientation = decoder.orientation()?;
+        let mut img = DynamicImage::from_decoder(decoder)?;
+        // the orientation is lost when encoding, rotate the pixels instead
+        img.apply_orientation(orientation);
+
+        if let Some(max) = max_dimension.filter(|_| oversized) {
+            let (width, height) = (img.width(), img.height());
+            let scale = f64::from(max) / f64::from(width.max(height));
+            let new_width = ((f64::from(width) * scale).round() as u32).max(1);
+            let new_height = ((f64::from(height) * scale).round() as u32).max(1);
+            log::info!(
+                "downscaling `{}` from {width}x{height} to {new_width}x{new_height}",
+                path.display()
+            );
+            img = DynamicImage::ImageRgba8(image::imageops::resize(
+                &img,
+                new_width,
+                new_height,
+                FilterType::Lanczos3,
+            ));
+        }
+
         let mut buf = Vec::new();
-        img.write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)?;
+        if format == ImageFormat::Jpeg {
+            // jpeg has no alpha channel
+            DynamicImage::ImageRgb8(img.to_rgb8())
+                .write_to(&mut Cursor::new(&mut buf), ImageFormat::Jpeg)?;
+        } else {
+            log::debug!("got {format:?} image, converting to png");
+            img.write_to(&mut Cursor::new(&mut buf), ImageFormat::Png)?;
+        }
         let mut reader = ToBase64Reader::new(buf.as_slice());
         let mut base64 = String::new();
         reader.read_to_string(&mut base64)?;
@@ -28,6 +64,7 @@ pub fn convert_image(path: &Path) -> Result<Image> {
     }
 
     // otherwise, ollama can handle it
+    let f = BufReader::new(File::open(path)?);
     let mut reader = ToBase64Reader::new(f);
     let mut base64 = String::new();
     reader.read_to_string(&mut base64)?;
@@ -35,12 +72,197 @@ pub fn convert_image(path: &Path) -> Result<Image> {
     Ok(Image::from_base64(&base64))
 }
 
-pub fn show_images(ui: &mut egui::Ui, images: &mut Vec<PathBuf>, mutate: bool) {
+/// Ask where to save a copy of the image. This copies the original file, even if it had
+/// to be converted before sending it
+fn save_image(path: PathBuf) {
+    tokio::spawn(async move {
+        let mut dialog = rfd::AsyncFileDialog::new();
+        if let Some(file_name) = path.file_name() {
+            dialog = dialog.set_file_name(file_name.to_string_lossy());
+        }
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            dialog = dialog.add_filter("Image", &[ext]);
+        }
+        let Some(file) = dialog.save_file().await else {
+            log::info!("no file selected");
+            return;
+        };
+        match std::fs::copy(&path, file.path()) {
+            Ok(_) => log::info!(
+                "saved image `{}` to `{}`",
+                path.display(),
+                file.path().display()
+            ),
+            Err(e) => log::error!("failed to save image `{}`: {e}", path.display()),
+        }
+    });
+}
+
+/// Image opened in the viewer, stored in egui's memory
+#[derive(Clone)]
+struct ImageViewer {
+    path: PathBuf,
+    zoom: f32,
+    /// Dimensions, size and format, read once when the viewer is opened
+    caption: String,
+}
+
+/// Caption like "1920×1080 · 2.1 MB · PNG", parts that can't be read are left out
+fn image_caption(path: &Path) -> String {
+    let mut parts = Vec::new();
+    match image::image_dimensions(path) {
+        Ok((width, height)) => parts.push(format!("{width}×{height}")),
+        Err(e) => log::warn!("failed to read dimensions of `{}`: {e}", path.display()),
+    }
+    if let Ok(metadata) = std::fs::metadata(path) {
+        parts.push(bytesize::ByteSize(metadata.len()).to_string());
+    }
+    if let Ok(format) = ImageFormat::from_path(path) {
+        parts.push(format!("{format:?}").to_uppercase());
+    }
+    parts.join(" · ")
+}
+
+/// Each window has its own viewer, so an image opens in the window it was clicked in
+#[inline]
+fn image_viewer_id(ctx: &egui::Context) -> Id {
+    Id::new("image_viewer").with(ctx.viewport_id())
+}
+
+fn open_image_viewer(ctx: &egui::Context, path: PathBuf) {
+    let caption = image_caption(&path);
+    let id = image_viewer_id(ctx);
+    ctx.data_mut(|d| {
+        d.insert_temp(
+            id,
+            ImageViewer {
+                path,
+                zoom: 1.0,
+                caption,
+            },
+        );
+    });
+}
+
+/// Whether an image is shown in the image viewer
+pub fn is_viewer_open(ctx: &egui::Context) -> bool {
+    let id = image_viewer_id(ctx);
+    ctx.data(|d| d.get_temp::<ImageViewer>(id).is_some())
+}
+
+/// Show the image that was clicked in [`show_images`] on top of everything else. Escape or
+/// clicking outside of the image closes it.
+pub fn show_image_viewer(ctx: &egui::Context) {
+    const MIN_ZOOM: f32 = 0.1;
+    const MAX_ZOOM: f32 = 8.0;
+
+    let id = image_viewer_id(ctx);
+    let Some(mut viewer) = ctx.data(|d| d.get_temp::<ImageViewer>(id)) else {
+        return;
+    };
+    let mut open = !ctx.input(|i| i.key_pressed(Key::Escape));
+
+    // dim the background, clicking it closes the viewer
+    let screen_rect = ctx.screen_rect();
+    egui::Area::new(Id::new("image_viewer_backdrop"))
+        .order(Order::Foreground)
+        .fixed_pos(screen_rect.min)
+        .show(ctx, |ui| {
+            ui.painter()
+                .rect_filled(screen_rect, 0.0, Color32::from_black_alpha(192));
+            if ui.allocate_rect(screen_rect, Sense::click()).clicked() {
+                open = false;
+            }
+        });
+
+    egui::Area::new(Id::new("image_viewer_window"))
+        .order(Order::Tooltip)
+        .anchor(Align2::CENTER_CENTER, vec2(0.0, 0.0))
+        .show(ctx, |ui| {
+            egui::Frame::window(ui.style()).show(ui, |ui| {
+                let file_name = viewer
+                    .path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy();
+                ui.horizontal(|ui| {
+                    ui.add(egui::Label::new(RichText::new(file_name).strong()).truncate());
+                    if ui.small_button("➖").on_hover_text("Zoom out").clicked() {
+                        viewer.zoom /= 1.25;
+                    }
+                    ui.label(format!("{:.0}%", viewer.zoom * 100.0));
+                    if ui.small_button("➕").on_hover_text("Zoom in").clicked() {
+                        viewer.zoom *= 1.25;
+                    }
+                    if ui
+                        .small_button("1:1")
+                        .on_hover_text("Original size")
+                        .clicked()
+                    {
+                        viewer.zoom = 1.0;
+                    }
+                    if ui.small_button("❌").on_hover_text("Close (Esc)").clicked() {
+                        open = false;
+                    }
+                });
+                if !viewer.caption.is_empty() {
+                    ui.label(RichText::new(&viewer.caption).small().weak());
+                }
+                ui.separator();
+
+                let max_size = screen_rect.size() * 0.85;
+                egui::ScrollArea::both()
+                    .max_width(max_size.x)
+                    .max_height(max_size.y)
+                    .show(ui, |ui| {
+                        let resp = ui.add(
+                            egui::Image::new(format!("file://{}", viewer.path.display()))
+                                .fit_to_original_size(viewer.zoom),
+                        );
+                        // ctrl + scroll or pinch to zoom
+                        if resp.hovered() {
+                            viewer.zoom *= ui.input(|i| i.zoom_delta());
+                        }
+                    });
+            });
+        });
+
+    viewer.zoom = viewer.zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+    ctx.data_mut(|d| {
+        if open {
+            d.insert_temp(id, viewer);
+        } else {
+            d.remove::<ImageViewer>(id);
+        }
+    });
+}
+
+/// Open the folder containing `path` in the file manager
+fn open_file_location(ctx: &egui::Context, path: &Path) {
+    let Some(dir) = path.parent() else {
+        return;
+    };
+    match url::Url::from_directory_path(dir) {
+        Ok(url) => ctx.open_url(egui::OpenUrl::new_tab(url)),
+        Err(()) => log::error!("invalid image directory: {dir:?}"),
+    }
+}
+
+/// Show image thumbnails, with an ❌ and a "Clear all" button to remove them if `mutate` is
+/// set. Clicking one opens it in the viewer and the context menu can toggle `send_original`
+/// if it's given. Returns the removed images with their indices, in ascending order.
+pub fn show_images(
+    ui: &mut egui::Ui,
+    images: &mut Vec<PathBuf>,
+    mutate: bool,
+    mut send_original: Option<&mut bool>,
+) -> Vec<(usize, PathBuf)> {
     const MAX_IMAGE_HEIGHT: f32 = 128.0;
     let pointer_pos = ui.input(|i| i.pointer.interact_pos());
-    let mut showing_x = false;
+    let mut removed = Vec::new();
 
-    images.retain_mut(|image_path| {
+    // removed after the loop so the other thumbnails don't shift under the cursor
+    for (i, image_path) in images.iter().enumerate() {
         let path_string = image_path.display().to_string();
         let resp = ui
             .group(|ui| {
@@ -49,8 +271,7 @@ pub fn show_images(ui: &mut egui::Ui, images: &mut Vec<PathBuf>, mutate: bool) {
                         egui::Image::new(format!("file://{path_string}"))
                             .max_height(MAX_IMAGE_HEIGHT)
                             .fit_to_original_size(1.0),
-                    )
-                    .on_hover_text(path_string);
+                    );
 
                     let file_name = image_path.file_name().unwrap_or_default().to_string_lossy();
                     ui.add(egui::Label::new(RichText::new(file_name).small()).truncate());
@@ -58,21 +279,53 @@ pub fn show_images(ui: &mut egui::Ui, images: &mut Vec<PathBuf>, mutate: bool) {
             })
             .response;
 
-        if !mutate || showing_x {
-            return true;
+        let interact = ui
+            .interact(resp.rect, resp.id.with("context_menu"), Sense::click())
+            .on_hover_text(&path_string)
+            .on_hover_cursor(egui::CursorIcon::ZoomIn);
+        let top = resp.rect.right_top();
+        let x_rect = Rect::from_center_size(top, vec2(16.0, 16.0));
+        let on_x = |pos: egui::Pos2| mutate && x_rect.contains(pos);
+        if interact.clicked() && !pointer_pos.is_some_and(on_x) {
+            open_image_viewer(ui.ctx(), image_path.clone());
         }
+        interact.context_menu(|ui| {
+            if mutate && ui.button("Remove").clicked() {
+                removed.push(i);
+                ui.close_menu();
+            }
+            if ui.button("Save image…").clicked() {
+                save_image(image_path.clone());
+                ui.close_menu();
+            }
+            if ui.button("Open file location").clicked() {
+                open_file_location(ui.ctx(), image_path);
+                ui.close_menu();
+            }
+            if ui.button("Copy path").clicked() {
+                ui.ctx().copy_text(path_string.clone());
+                ui.close_menu();
+            }
+            if let Some(send_original) = send_original.as_deref_mut() {
+                ui.checkbox(send_original, "Send original size")
+                    .on_hover_text("Don't downscale the attached images for the next message");
+            }
+        });
 
-        if let Some(pos) = pointer_pos {
-            if resp.rect.expand(8.0).contains(pos) {
-                showing_x = true;
+        if !mutate {
+            continue;
+        }
 
+        if let Some(pos) = pointer_pos {
+            let contains_pointer = x_rect.contains(pos);
+            if resp.rect.contains(pos) || contains_pointer {
                 // render an ❌ in a red circle
-                let top = resp.rect.right_top();
-                let x_rect = Rect::from_center_size(top, vec2(16.0, 16.0));
-                let contains_pointer = x_rect.contains(pos);
-
-                ui.painter()
-                    .circle_filled(top, 10.0, ui.visuals().window_fill);
+                let ring = if crate::style::is_high_contrast(ui.ctx()) {
+                    ui.visuals().strong_text_color()
+                } else {
+                    ui.visuals().window_fill
+                };
+                ui.painter().circle_filled(top, 10.0, ring);
                 ui.painter().circle_filled(
                     top,
                     8.0,
@@ -92,11 +345,24 @@ pub fn show_images(ui: &mut egui::Ui, images: &mut Vec<PathBuf>, mutate: bool) {
                 );
 
                 if contains_pointer && ui.input(|i| i.pointer.primary_clicked()) {
-                    return false;
+                    removed.push(i);
                 }
             }
         }
+    }
 
-        true
-    });
+    if mutate && images.len() > 1 && ui.button("Clear all").clicked() {
+        removed = (0..images.len()).collect();
+    }
+
+    removed.sort_unstable();
+    removed.dedup();
+    // back to front so the indices stay valid
+    let mut removed: Vec<_> = removed
+        .into_iter()
+        .rev()
+        .map(|i| (i, images.remove(i)))
+        .collect();
+    removed.reverse();
+    removed
 }
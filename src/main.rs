@@ -6,10 +6,19 @@ use ollama_rs::Ollama;
 use sessions::Sessions;
 
 mod chat;
+mod commands;
+mod diagnostics;
 mod easymark;
+mod encryption;
+mod error;
 mod image;
+mod migrations;
 mod sessions;
+mod shortcuts;
+mod stats;
 mod style;
+#[cfg(feature = "tray")]
+mod tray;
 mod widgets;
 
 const TITLE: &str = "Ellama";
@@ -18,6 +27,17 @@ const IMAGE_FORMATS: &[&str] = &[
     "tiff", "webp",
 ];
 
+/// Audio files that can be transcribed into the chatbox
+const AUDIO_FORMATS: &[&str] = &["wav", "mp3", "ogg", "flac", "m4a", "opus", "webm"];
+
+/// Files that can be attached to a message as text
+const TEXT_FORMATS: &[&str] = &[
+    "txt", "md", "rs", "toml", "json", "yaml", "yml", "xml", "html", "css", "js", "ts", "jsx",
+    "tsx", "py", "c", "h", "cpp", "hpp", "cc", "cs", "java", "kt", "go", "rb", "php", "lua", "sh",
+    "bash", "zsh", "ps1", "bat", "sql", "swift", "zig", "hs", "ml", "ex", "exs", "dart", "csv",
+    "ini", "cfg", "conf", "log", "diff", "patch",
+];
+
 fn load_icon() -> egui::IconData {
     let (icon_rgba, icon_width, icon_height) = {
         let icon = include_bytes!("../assets/icon.png");
@@ -38,7 +58,8 @@ fn load_icon() -> egui::IconData {
 
 #[tokio::main]
 async fn main() {
-    env_logger::init();
+    let started_at = std::time::Instant::now();
+    diagnostics::init_logger();
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_icon(load_icon()),
         ..Default::default()
@@ -46,30 +67,36 @@ async fn main() {
     eframe::run_native(
         TITLE,
         native_options,
-        Box::new(|cc| Ok(Box::new(Ellama::new(cc)))),
+        Box::new(move |cc| Ok(Box::new(Ellama::new(cc, started_at)))),
     )
     .expect("failed to run app");
 }
 
-#[derive(serde::Deserialize, serde::Serialize)]
+/// Startup work is deferred until the first frame is shown, so the window appears immediately
+#[derive(Default)]
+enum Startup {
+    /// Waiting for the first frame, holds the time the app was started at
+    Pending(std::time::Instant),
+    /// The first frame was shown, do the deferred work on this frame
+    FirstFrameShown,
+    #[default]
+    Done,
+}
+
+#[derive(Default, serde::Deserialize, serde::Serialize)]
 struct Ellama {
+    /// Version of the format the state was saved in, 0 for states saved before it was stored
+    #[serde(default)]
+    state_version: u32,
     sessions: Sessions,
     #[serde(skip)]
     ollama: Ollama,
-}
-
-impl Default for Ellama {
-    fn default() -> Self {
-        let ollama = Ollama::default();
-        Self {
-            sessions: Sessions::new(ollama.clone()),
-            ollama,
-        }
-    }
+    #[serde(skip)]
+    startup: Startup,
 }
 
 impl Ellama {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>, started_at: std::time::Instant) -> Self {
         // change visuals
         style::set_style(&cc.egui_ctx);
         egui_extras::install_image_loaders(&cc.egui_ctx);
@@ -81,10 +108,12 @@ impl Ellama {
         );
 
         if let Some(storage) = cc.storage {
-            if let Some(mut app_state) = eframe::get_value::<Self>(storage, eframe::APP_KEY) {
+            if let Some(mut app_state) = migrations::load(storage) {
                 log::debug!("app state successfully restored from storage");
+                app_state.sessions.assign_chat_ids();
+                app_state.sessions.mark_interrupted();
                 app_state.ollama = app_state.sessions.settings.make_ollama();
-                app_state.sessions.list_models(app_state.ollama.clone());
+                app_state.startup = Startup::Pending(started_at);
                 return app_state;
             }
         }
@@ -93,17 +122,47 @@ impl Ellama {
 
         // default app
 
-        Self::default()
+        Self {
+            state_version: migrations::STATE_VERSION,
+            startup: Startup::Pending(started_at),
+            ..Default::default()
+        }
     }
 }
 
 impl eframe::App for Ellama {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        self.sessions.show(ctx, &self.ollama);
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        if matches!(self.startup, Startup::FirstFrameShown) {
+            log::debug!("running deferred startup work");
+            self.sessions.start(ctx, self.ollama.clone());
+            self.startup = Startup::Done;
+        }
+
+        let system_dark = frame.info().system_theme.map(|t| t == eframe::Theme::Dark);
+        self.sessions.show(ctx, &self.ollama, system_dark);
+
+        if let Startup::Pending(started_at) = self.startup {
+            log::info!("startup to first frame took {:?}", started_at.elapsed());
+            self.startup = Startup::FirstFrameShown;
+            ctx.request_repaint();
+        }
     }
 
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
         log::debug!("saving app state");
-        eframe::set_value(storage, eframe::APP_KEY, self);
+        // the popped out chat is lent to its window between frames
+        self.sessions.reclaim_popped_out();
+        let chats = self.sessions.encrypt_chats();
+        match serde_json::to_string(self) {
+            Ok(state) => {
+                storage.set_string(migrations::STATE_KEY, state);
+                // drop the state saved as RON by older versions
+                storage.set_string(eframe::APP_KEY, String::new());
+            }
+            Err(e) => log::error!("failed to serialize app state: {e}"),
+        }
+        self.sessions.restore_chats(chats);
+        self.sessions.on_saved();
+        self.sessions.lend_popped_out(&self.ollama);
     }
 }
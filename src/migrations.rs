@@ -0,0 +1,435 @@
+use serde_json::Value;
+use std::path::Path;
+
+/// Version of the saved app state. Bump it and add a migration to [`MIGRATIONS`] when a
+/// change to the format can't be handled by serde defaults.
+pub const STATE_VERSION: u32 = 2;
+
+/// Storage key of the app state. It's saved as JSON, so older states can be migrated before
+/// they are deserialized.
+pub const STATE_KEY: &str = "app_state";
+
+/// File the state is copied to before it's migrated, next to the storage file
+const BACKUP_FILE: &str = "app_backup.json";
+
+type Migration = fn(&mut Value);
+
+/// `MIGRATIONS[i]` upgrades the state from version `i` to `i + 1`
+const MIGRATIONS: &[Migration] = &[
+    // states saved before the version was stored, the format didn't change otherwise
+    |_| (),
+    keep_saved_model_selections,
+];
+const _: () = assert!(MIGRATIONS.len() == STATE_VERSION as usize);
+
+/// Read the app state from `storage` and migrate it to [`STATE_VERSION`]. The state is
+/// copied to [`BACKUP_FILE`] before it's migrated or if it can't be read.
+pub fn load(storage: &dyn eframe::Storage) -> Option<crate::Ellama> {
+    let Some(raw) = storage.get_string(STATE_KEY) else {
+        return load_legacy(storage);
+    };
+    match load_state(&raw) {
+        Ok((state, migrated)) => {
+            if migrated {
+                save_backup(&raw);
+            }
+            Some(state)
+        }
+        Err(e) => {
+            log::error!("failed to read app state: {e}");
+            save_backup(&raw);
+            None
+        }
+    }
+}
+
+/// States saved before they were stored as JSON were saved as RON by eframe. RON can't be
+/// read into a [`Value`] without losing enum variants, so these are read as they are.
+fn load_legacy(storage: &dyn eframe::Storage) -> Option<crate::Ellama> {
+    let raw = storage
+        .get_string(eframe::APP_KEY)
+        .filter(|raw| !raw.is_empty())?;
+    match load_legacy_state(&raw) {
+        Ok(state) => {
+            log::info!("read app state saved as RON, it will be saved as JSON");
+            save_backup(&raw);
+            Some(state)
+        }
+        Err(e) => {
+            log::error!("failed to read app state: {e}");
+            save_backup(&raw);
+            None
+        }
+    }
+}
+
+fn load_legacy_state(raw: &str) -> ron::error::SpannedResult<crate::Ellama> {
+    let mut state: crate::Ellama = ron::from_str(raw)?;
+    // these are older than the source of the model selection
+    state.sessions.mark_user_selections();
+    state.state_version = STATE_VERSION;
+    Ok(state)
+}
+
+/// Deserialize a state saved as JSON after migrating it. Returns whether it was migrated.
+fn load_state(raw: &str) -> anyhow::Result<(crate::Ellama, bool)> {
+    let mut value: Value = serde_json::from_str(raw)?;
+    let version = value
+        .get("state_version")
+        .and_then(Value::as_u64)
+        .map_or(0, |v| v as u32);
+    let migrated = migrate(&mut value, version, MIGRATIONS);
+    let mut state: crate::Ellama = serde_json::from_value(value)?;
+    state.state_version = STATE_VERSION;
+    Ok((state, migrated))
+}
+
+/// Upgrade a state saved with `version` one version at a time. Returns whether it was
+/// migrated.
+fn migrate(state: &mut Value, version: u32, migrations: &[Migration]) -> bool {
+    let latest = migrations.len() as u32;
+    if version > latest {
+        log::warn!(
+            "app state was saved by a newer version (state version {version}, \
+            this version supports up to {latest}), some data may be lost"
+        );
+        return false;
+    }
+    for (from, migration) in migrations.iter().enumerate().skip(version as usize) {
+        log::info!("migrating app state from version {from} to {}", from + 1);
+        migration(state);
+    }
+    version < latest
+}
+
+/// Model selections saved before it was stored who selected them were kept by the user,
+/// don't let them be replaced by the best model when the model is missing
+fn keep_saved_model_selections(state: &mut Value) {
+    let Some(sessions) = state.get_mut("sessions") else {
+        return;
+    };
+    if let Some(picker) = sessions.pointer_mut("/settings/model_picker") {
+        mark_user_selection(picker);
+    }
+    if let Some(chats) = sessions.get_mut("chats").and_then(Value::as_array_mut) {
+        for picker in chats.iter_mut().filter_map(|c| c.get_mut("model_picker")) {
+            mark_user_selection(picker);
+        }
+    }
+}
+
+fn mark_user_selection(picker: &mut Value) {
+    let Some(picker) = picker.as_object_mut() else {
+        return;
+    };
+    let has_selection = picker
+        .get("selected")
+        .and_then(|s| s.get("name"))
+        .and_then(Value::as_str)
+        .is_some_and(|name| !name.is_empty());
+    if has_selection && !picker.contains_key("selection_source") {
+        picker.insert("selection_source".to_owned(), "User".into());
+    }
+}
+
+fn save_backup(raw: &str) {
+    let Some(dir) = eframe::storage_dir(crate::TITLE) else {
+        log::warn!("no storage directory, not saving a backup of the app state");
+        return;
+    };
+    write_backup(&dir, raw);
+}
+
+fn write_backup(dir: &Path, raw: &str) {
+    let path = dir.join(BACKUP_FILE);
+    match std::fs::create_dir_all(dir).and_then(|()| std::fs::write(&path, raw)) {
+        Ok(()) => log::info!("saved a backup of the app state to `{}`", path.display()),
+        Err(e) => log::error!("failed to save a backup of the app state: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// State saved as JSON with version 1, with a chat about an image and an empty chat
+    const STATE_V1: &str = r#"{
+        "state_version": 1,
+        "sessions": {
+            "tab": "Chats",
+            "chats": [
+                {
+                    "chatbox": "and a dog?",
+                    "messages": [
+                        {
+                            "model_name": "llava:7b",
+                            "content": "What's in this picture?",
+                            "role": "User",
+                            "is_generating": false,
+                            "time": "2024-09-01T12:00:00Z",
+                            "is_error": false,
+                            "error": null,
+                            "images": ["/home/user/cat.png"],
+                            "original_images": false,
+                            "text_files": [
+                                { "name": "notes.txt", "text": "fed at 8", "size": 8, "truncated": false }
+                            ],
+                            "is_prepending": false,
+                            "done_reason": null,
+                            "is_json": false,
+                            "generation_secs": null,
+                            "rating": null,
+                            "translation": null
+                        },
+                        {
+                            "model_name": "llava:7b",
+                            "content": "A cat sitting on a windowsill.",
+                            "role": "Assistant",
+                            "is_generating": false,
+                            "time": "2024-09-01T12:00:04Z",
+                            "is_error": false,
+                            "error": null,
+                            "images": [],
+                            "original_images": false,
+                            "text_files": [],
+                            "is_prepending": false,
+                            "done_reason": "Stop",
+                            "is_json": false,
+                            "generation_secs": 3.5,
+                            "rating": "Up",
+                            "translation": null
+                        }
+                    ],
+                    "summary": "What's in this picture?",
+                    "stop_generating": false,
+                    "model_picker": {
+                        "selected": {
+                            "name": "llava:7b",
+                            "modified_ago": "2 days ago",
+                            "modified_at": "2024-08-30T10:00:00Z",
+                            "size": 4733363377
+                        },
+                        "selection_source": "User",
+                        "info": null,
+                        "details": null,
+                        "settings": { "temperature": 0.7 },
+                        "template": null
+                    },
+                    "images": ["/home/user/dog.png"],
+                    "text_files": [],
+                    "prepend_buf": "",
+                    "system_prompt": "Describe images briefly.",
+                    "context_strategy": "KeepAll",
+                    "raw_mode": false
+                },
+                {
+                    "messages": [],
+                    "summary": "",
+                    "model_picker": {
+                        "selected": {
+                            "name": "llama3:8b",
+                            "modified_ago": "",
+                            "modified_at": "2024-08-28T09:00:00Z",
+                            "size": 4661224676
+                        },
+                        "selection_source": "Auto",
+                        "info": null,
+                        "settings": {},
+                        "template": null
+                    },
+                    "images": []
+                }
+            ],
+            "selected_chat": 0,
+            "chat_export_format": "Markdown",
+            "settings": {
+                "endpoint": "http://127.0.0.1:11434",
+                "endpoint_error": "",
+                "model_picker": {
+                    "selected": {
+                        "name": "llama3:8b",
+                        "modified_ago": "",
+                        "modified_at": "2024-08-28T09:00:00Z",
+                        "size": 4661224676
+                    },
+                    "selection_source": "Auto",
+                    "info": null,
+                    "settings": {},
+                    "template": null
+                },
+                "inherit_chat_picker": true,
+                "max_image_dimension": 1024
+            },
+            "sidebar_width": 240.0
+        }
+    }"#;
+
+    /// State saved as RON by 0.3.1, before it was stored as JSON
+    const STATE_RON: &str = r#"(sessions:(tab:Chats,chats:[(chatbox:"",messages:[(model_name:"llava:7b",content:"What's in this picture?",role:User,time:"2024-09-01T12:00:00Z",is_error:false,images:["/home/user/cat.png"],is_prepending:false),(model_name:"llava:7b",content:"A cat sitting on a windowsill.",role:Assistant,time:"2024-09-01T12:00:04Z",is_error:false,images:[],is_prepending:false)],summary:"What's in this picture?",stop_generating:false,model_picker:(selected:(name:"llava:7b",modified_ago:"2 days ago",modified_at:"2024-08-30T10:00:00Z",size:4733363377),info:None,settings:(temperature:Some(0.7)),template:None),images:["/home/user/dog.png"],prepend_buf:"")],selected_chat:0,edited_chat:None,chat_export_format:Plaintext,settings_open:false,settings:(endpoint:"http://127.0.0.1:11434",endpoint_error:"",model_picker:(selected:(name:"llama3:8b",modified_ago:"",modified_at:"2024-08-28T09:00:00Z",size:4661224676),info:None,settings:(temperature:None),template:None),inherit_chat_picker:true)))"#;
+
+    /// The chats of a loaded state as they would be saved
+    fn saved_chats(state: &crate::Ellama) -> Value {
+        let mut saved = serde_json::to_value(state).unwrap();
+        saved["sessions"]["chats"].take()
+    }
+
+    #[test]
+    fn migrates_chats_and_settings() {
+        let (state, migrated) = load_state(STATE_V1).unwrap();
+        assert!(migrated);
+        assert_eq!(state.state_version, STATE_VERSION);
+
+        let chats = saved_chats(&state);
+        assert_eq!(chats.as_array().unwrap().len(), 2);
+        let chat = &chats[0];
+        assert_eq!(chat["summary"], "What's in this picture?");
+        assert_eq!(chat["chatbox"], "and a dog?");
+        assert_eq!(chat["system_prompt"], "Describe images briefly.");
+        assert_eq!(chat["images"], serde_json::json!(["/home/user/dog.png"]));
+        assert_eq!(chat["model_picker"]["selected"]["name"], "llava:7b");
+        assert_eq!(chat["model_picker"]["selection_source"], "User");
+
+        let messages = chat["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["role"], "User");
+        assert_eq!(messages[0]["content"], "What's in this picture?");
+        assert_eq!(messages[0]["time"], "2024-09-01T12:00:00Z");
+        assert_eq!(
+            messages[0]["images"],
+            serde_json::json!(["/home/user/cat.png"])
+        );
+        assert_eq!(messages[0]["text_files"][0]["name"], "notes.txt");
+        assert_eq!(messages[1]["role"], "Assistant");
+        assert_eq!(messages[1]["content"], "A cat sitting on a windowsill.");
+        assert_eq!(messages[1]["done_reason"], "Stop");
+        assert_eq!(messages[1]["rating"], "Up");
+        assert_eq!(messages[1]["generation_secs"], 3.5);
+
+        // saved selection sources are kept
+        assert_eq!(chats[1]["model_picker"]["selection_source"], "Auto");
+        let settings = &state.sessions.settings;
+        assert_eq!(settings.endpoint, "http://127.0.0.1:11434");
+        assert_eq!(settings.model_picker.selected_model(), "llama3:8b");
+        assert_eq!(
+            settings.model_picker.selection_source,
+            crate::widgets::SelectionSource::Auto
+        );
+        assert_eq!(settings.max_image_dimension, Some(1024));
+    }
+
+    #[test]
+    fn migrates_unversioned_state() {
+        let mut state: Value = serde_json::from_str(STATE_V1).unwrap();
+        state.as_object_mut().unwrap().remove("state_version");
+        let (state, migrated) = load_state(&state.to_string()).unwrap();
+        assert!(migrated);
+        assert_eq!(state.state_version, STATE_VERSION);
+        assert_eq!(saved_chats(&state)[0]["messages"][1]["rating"], "Up");
+    }
+
+    #[test]
+    fn reads_state_saved_as_ron() {
+        let state = load_legacy_state(STATE_RON).unwrap();
+        assert_eq!(state.state_version, STATE_VERSION);
+
+        let chats = saved_chats(&state);
+        assert_eq!(chats.as_array().unwrap().len(), 1);
+        let chat = &chats[0];
+        assert_eq!(chat["summary"], "What's in this picture?");
+        assert_eq!(chat["images"], serde_json::json!(["/home/user/dog.png"]));
+        let messages = chat["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["role"], "User");
+        assert_eq!(
+            messages[0]["images"],
+            serde_json::json!(["/home/user/cat.png"])
+        );
+        assert_eq!(messages[1]["role"], "Assistant");
+        assert_eq!(messages[1]["content"], "A cat sitting on a windowsill.");
+
+        // the saved selections were kept by the user
+        assert_eq!(chat["model_picker"]["selection_source"], "User");
+        let picker = &state.sessions.settings.model_picker;
+        assert_eq!(picker.selected_model(), "llama3:8b");
+        assert_eq!(
+            picker.selection_source,
+            crate::widgets::SelectionSource::User
+        );
+    }
+
+    #[test]
+    fn round_trips_current_state() {
+        let (state, _) = load_state(STATE_V1).unwrap();
+        let saved = serde_json::to_string(&state).unwrap();
+        let (reloaded, migrated) = load_state(&saved).unwrap();
+        assert!(!migrated);
+        assert_eq!(
+            serde_json::to_value(&reloaded).unwrap(),
+            serde_json::from_str::<Value>(&saved).unwrap()
+        );
+    }
+
+    #[test]
+    fn runs_migrations_in_order_from_saved_version() {
+        let migrations: &[Migration] = &[
+            |state| state["steps"] = Value::from("0"),
+            |state| state["steps"] = Value::from(format!("{}1", state["steps"].as_str().unwrap())),
+            |state| state["steps"] = Value::from(format!("{}2", state["steps"].as_str().unwrap())),
+        ];
+        let mut state = serde_json::json!({});
+        assert!(migrate(&mut state, 0, migrations));
+        assert_eq!(state["steps"], "012");
+
+        let mut state = serde_json::json!({ "steps": "x" });
+        assert!(migrate(&mut state, 2, migrations));
+        assert_eq!(state["steps"], "x2");
+    }
+
+    #[test]
+    fn leaves_newer_state_alone() {
+        let mut state = serde_json::json!({ "state_version": STATE_VERSION + 1 });
+        let before = state.clone();
+        assert!(!migrate(&mut state, STATE_VERSION + 1, MIGRATIONS));
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn keeps_model_selections_saved_without_source() {
+        let picker = |name: &str| serde_json::json!({ "selected": { "name": name } });
+        let mut state = serde_json::json!({
+            "state_version": 1,
+            "sessions": {
+                "settings": { "model_picker": picker("llama3:8b") },
+                "chats": [
+                    { "model_picker": picker("") },
+                    { "model_picker": {
+                        "selected": { "name": "phi3" },
+                        "selection_source": "Auto",
+                    } },
+                ],
+            },
+        });
+        assert!(migrate(&mut state, 1, MIGRATIONS));
+        let sessions = &state["sessions"];
+        assert_eq!(
+            sessions["settings"]["model_picker"]["selection_source"],
+            "User"
+        );
+        assert!(sessions["chats"][0]["model_picker"]
+            .get("selection_source")
+            .is_none());
+        assert_eq!(
+            sessions["chats"][1]["model_picker"]["selection_source"],
+            "Auto"
+        );
+    }
+
+    #[test]
+    fn writes_backup_to_its_own_file() {
+        let dir = std::env::temp_dir().join(format!("ellama-backup-{}", fastrand::u64(..)));
+        write_backup(&dir, STATE_V1);
+        let backup = std::fs::read_to_string(dir.join(BACKUP_FILE)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(backup, STATE_V1);
+    }
+}
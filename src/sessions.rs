@@ -1,8 +1,14 @@
 use crate::{
-    chat::{Chat, ChatAction, ChatExportFormat},
-    widgets::{ModelPicker, RequestInfoType, Settings},
+    chat::{Chat, ChatAction, ChatExportFormat, ContextStrategy, Finished, Message, TextFile},
+    encryption::{EncryptedData, EncryptionKey},
+    error::AppError,
+    shortcuts,
+    widgets::{
+        self, ModelDetails, ModelPicker, RequestInfoType, RunningModel, SavedPrompt, SelectedModel,
+        SelectionSource, Settings, TranscriptionBackend,
+    },
 };
-use eframe::egui::{self, vec2, Color32, Frame, Layout, Rounding, Stroke};
+use eframe::egui::{self, vec2, Color32, Frame, Layout, RichText, Rounding, Stroke};
 use egui_commonmark::CommonMarkCache;
 use egui_modal::{Icon, Modal};
 use egui_notify::{Toast, Toasts};
@@ -10,14 +16,21 @@ use egui_twemoji::EmojiLabel;
 use egui_virtual_list::VirtualList;
 use flowync::{CompactFlower, CompactHandle};
 use ollama_rs::{
+    generation::{completion::request::GenerationRequest, parameters::KeepAlive},
     models::{LocalModel, ModelInfo},
     Ollama,
 };
 #[cfg(feature = "tts")]
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 #[cfg(feature = "tts")]
 use std::sync::Arc;
-use std::{cell::RefCell, collections::HashMap, path::PathBuf, rc::Rc, time::Instant};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant, SystemTime},
+};
 #[cfg(feature = "tts")]
 use tts::Tts;
 
@@ -25,6 +38,7 @@ use tts::Tts;
 enum SessionTab {
     #[default]
     Chats,
+    Stats,
 }
 
 #[cfg(feature = "tts")]
@@ -33,10 +47,72 @@ pub type SharedTts = Option<Arc<RwLock<Tts>>>;
 enum OllamaResponse {
     Ignore,
     Models(Vec<LocalModel>),
-    ModelInfo { name: String, info: ModelInfo },
+    ModelInfo {
+        name: String,
+        info: ModelInfo,
+        details: Option<ModelDetails>,
+    },
     Toast(Toast),
-    Images { id: usize, files: Vec<PathBuf> },
+    Images {
+        id: usize,
+        files: Vec<PathBuf>,
+    },
+    ImageFolder {
+        id: usize,
+        files: Vec<PathBuf>,
+        skipped: usize,
+    },
+    Files {
+        id: usize,
+        files: Vec<PathBuf>,
+    },
+    /// Attached text files that were read, or why they couldn't be read
+    TextFiles {
+        id: usize,
+        files: Vec<Result<TextFile, String>>,
+    },
     Settings(Box<Settings>),
+    RunningModels(Vec<RunningModel>),
+    /// App state loaded from a backup, it is deserialized on the UI thread
+    RestoredState(Box<serde_json::Value>),
+    /// Version of the Ollama server for the diagnostic info, `None` if it couldn't be reached
+    ServerVersion(Option<String>),
+}
+
+/// Info of a model that was requested before, so it isn't requested again after a restart
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CachedModelInfo {
+    /// `modified_at` of the model the info is for, it's outdated once the model changes
+    modified_at: String,
+    info: ModelInfo,
+    details: Option<ModelDetails>,
+}
+
+/// Background request loading the model of the selected chat before a message is sent
+struct Warmup {
+    model: String,
+    task: tokio::task::JoinHandle<()>,
+}
+
+/// Drag and drop payload of the chat list, the index of the dragged chat
+struct DraggedChat(usize);
+
+/// A picked model whose license wasn't accepted yet
+struct LicenseCheck {
+    model: String,
+    /// Chat whose model picker picked the model, `None` for the global picker
+    chat_id: Option<usize>,
+    /// Selection to go back to if the license is declined
+    previous: SelectedModel,
+    /// License text, once the model info was loaded
+    license: Option<String>,
+}
+
+/// Export waiting for the user to confirm writing encrypted chats as plain text
+#[derive(Clone, Copy)]
+enum PendingExport {
+    Chat(usize),
+    AllChats,
 }
 
 #[derive(Default, PartialEq, Eq)]
@@ -48,11 +124,225 @@ enum OllamaFlowerActivity {
     ListModels,
     /// Get model info
     ModelInfo,
+    /// List models loaded into memory
+    RunningModels,
+    /// Pull a model, then list models
+    PullModel,
 }
 
 // <progress, response, error>
-type OllamaFlower = CompactFlower<(), OllamaResponse, String>;
-type OllamaFlowerHandle = CompactHandle<(), OllamaResponse, String>;
+type OllamaFlower = CompactFlower<(), OllamaResponse, AppError>;
+type OllamaFlowerHandle = CompactHandle<(), OllamaResponse, AppError>;
+
+// <(chat id, file name, transcript), (), error>
+type TranscriptionFlower = CompactFlower<(usize, String, Result<String, String>), (), String>;
+
+/// Chat shown in its own window. The window is a deferred viewport that is painted on its
+/// own, so the chat is lent to it after every frame of the main window and taken back
+/// before the next one.
+struct PopOut {
+    /// The chat and the index it was removed from
+    chat: Option<(usize, Chat)>,
+    ollama: Ollama,
+    #[cfg(feature = "tts")]
+    tts: SharedTts,
+    #[cfg(feature = "tts")]
+    stopped_talking: bool,
+    commonmark_cache: CommonMarkCache,
+    models: Vec<LocalModel>,
+    prompts: Vec<SavedPrompt>,
+    can_send: bool,
+    max_height_fraction: f32,
+    /// Whether the app is locked, the chat is hidden until it's unlocked
+    locked: bool,
+    /// Whether the chats are encrypted, exporting them as plain text needs to be confirmed
+    encrypted: bool,
+    /// Open the modal asking to confirm the export
+    ask_export: bool,
+    /// Actions the window can't handle itself, handled by the main window
+    actions: Vec<ChatAction>,
+    /// An export of the chat was confirmed in the window
+    export_confirmed: bool,
+    dropped_files: Vec<PathBuf>,
+    /// Toasts raised by the chat, shown in its window
+    toasts: Toasts,
+    /// The window was closed or can't be opened
+    closed: bool,
+    unsupported: bool,
+}
+
+impl PopOut {
+    fn new(ollama: Ollama) -> Self {
+        Self {
+            chat: None,
+            ollama,
+            #[cfg(feature = "tts")]
+            tts: None,
+            #[cfg(feature = "tts")]
+            stopped_talking: false,
+            commonmark_cache: CommonMarkCache::default(),
+            models: Vec::new(),
+            prompts: Vec::new(),
+            can_send: true,
+            max_height_fraction: 0.0,
+            locked: false,
+            encrypted: false,
+            ask_export: false,
+            actions: Vec::new(),
+            export_confirmed: false,
+            dropped_files: Vec::new(),
+            toasts: Toasts::default(),
+            closed: false,
+            unsupported: false,
+        }
+    }
+
+    #[inline]
+    fn viewport_id() -> egui::ViewportId {
+        egui::ViewportId::from_hash_of("popped_out_chat")
+    }
+
+    /// Paint a frame of the window, called by egui from the deferred viewport
+    fn show(&mut self, ctx: &egui::Context, class: egui::ViewportClass) {
+        if class == egui::ViewportClass::Embedded {
+            self.closed = true;
+            self.unsupported = true;
+            ctx.request_repaint_of(egui::ViewportId::ROOT);
+            return;
+        }
+        if ctx.input(|i| i.viewport().close_requested()) {
+            self.closed = true;
+        }
+
+        if self.locked {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                widgets::centerer(ui, |ui| {
+                    ui.heading("🔒 Ellama is locked");
+                    ui.label("Unlock it in the main window");
+                });
+            });
+        } else if let Some((_, chat)) = &mut self.chat {
+            let dropped_files: Vec<PathBuf> = ctx.input(|i| {
+                i.raw
+                    .dropped_files
+                    .iter()
+                    .filter_map(|f| f.path.clone())
+                    .collect()
+            });
+            self.dropped_files.extend(dropped_files);
+
+            let action = chat.show(
+                ctx,
+                &self.ollama,
+                #[cfg(feature = "tts")]
+                self.tts.clone(),
+                #[cfg(feature = "tts")]
+                std::mem::take(&mut self.stopped_talking),
+                &mut self.commonmark_cache,
+                self.can_send,
+                &self.models,
+                &mut self.prompts,
+                self.max_height_fraction,
+            );
+            match action {
+                ChatAction::None => (),
+                ChatAction::Toast(toast) => {
+                    self.toasts.add(toast);
+                }
+                ChatAction::Export if self.encrypted => self.ask_export = true,
+                action => self.actions.push(action),
+            }
+            preview_files_being_dropped(ctx);
+        }
+
+        let export_modal = Modal::new(ctx, "popped_out_export_modal");
+        if std::mem::take(&mut self.ask_export) {
+            export_modal.open();
+        }
+        export_modal.show(|ui| {
+            export_modal.title(ui, "Export Encrypted Chats");
+            export_modal.frame(ui, |ui| {
+                export_modal.body_and_icon(
+                    ui,
+                    "Your chats are encrypted, but exports are saved as plain text. \
+                    Anyone who can open the exported file can read them.",
+                    Icon::Warning,
+                );
+            });
+            export_modal.buttons(ui, |ui| {
+                export_modal.button(ui, "Cancel");
+                if export_modal.caution_button(ui, "Export Anyway").clicked() {
+                    self.export_confirmed = true;
+                }
+            });
+        });
+
+        crate::image::show_image_viewer(ctx);
+        self.toasts.show(ctx);
+
+        // the main window handles what the chat asked for
+        if self.closed
+            || self.export_confirmed
+            || !self.actions.is_empty()
+            || !self.dropped_files.is_empty()
+        {
+            ctx.request_repaint_of(egui::ViewportId::ROOT);
+        }
+    }
+}
+
+/// How often to ping the server while it's unreachable
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(10);
+/// The wall clock moving this much further than the monotonic clock between two frames means
+/// the computer was asleep
+const WAKE_GAP: Duration = Duration::from_secs(30);
+/// Minimum time between two refreshes after waking from sleep
+const WAKE_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+/// How long a removed chat can be brought back
+const UNDO_REMOVE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a chat has to stay selected before its model is warmed up
+const WARMUP_DELAY: Duration = Duration::from_secs(2);
+/// Minimum time between two warmups of the same model
+const WARMUP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// Ask before attaching more images than this from a folder
+const MAX_UNCONFIRMED_FOLDER_IMAGES: usize = 10;
+/// Shortest PIN that can be set for locking the app
+const MIN_PIN_LEN: usize = 4;
+/// Longest wait after failed attempts to unlock the app
+const MAX_UNLOCK_DELAY: Duration = Duration::from_secs(30);
+
+/// Tracks whether the Ollama server is reachable, updated by failed requests
+#[derive(Default)]
+struct Connectivity {
+    /// When the server was found unreachable, `None` if it's online
+    offline_since: Option<Instant>,
+    /// When to ping the server again while it's unreachable
+    next_ping: Option<Instant>,
+    /// The user chose to send messages even though the server is unreachable
+    send_anyway: bool,
+}
+
+impl Connectivity {
+    #[inline]
+    fn is_offline(&self) -> bool {
+        self.offline_since.is_some()
+    }
+
+    fn mark_offline(&mut self) {
+        if self.offline_since.is_none() {
+            log::warn!("ollama server is unreachable");
+            self.offline_since = Some(Instant::now());
+        }
+        self.next_ping = Some(Instant::now() + RECONNECT_INTERVAL);
+    }
+
+    /// Returns whether the server was offline before
+    fn mark_online(&mut self) -> bool {
+        self.next_ping = None;
+        self.send_anyway = false;
+        self.offline_since.take().is_some()
+    }
+}
 
 #[derive(Default, serde::Serialize, serde::Deserialize)]
 struct SelectedModel {
@@ -81,36 +371,168 @@ impl From<LocalModel> for SelectedModel {
 #[serde(default)]
 pub struct Sessions {
     tab: SessionTab,
+    #[serde(serialize_with = "serialize_persistent_chats")]
     chats: Vec<Chat>,
     selected_chat: usize,
     #[serde(skip)]
     chat_marked_for_deletion: usize,
+    /// Chat whose duplicate button was clicked, copied after the chat list is drawn
+    #[serde(skip)]
+    chat_to_duplicate: Option<usize>,
+    /// Id of the last chat that was created. Ids aren't saved, the loaded chats get new
+    /// ones in [`Self::assign_chat_ids`].
+    #[serde(skip)]
+    last_chat_id: usize,
+    /// Id of the chat shown in its own window
+    #[serde(skip)]
+    popped_out: Option<usize>,
+    /// State shared with the window of the popped out chat
+    #[serde(skip)]
+    popout: Option<Arc<Mutex<PopOut>>>,
+    /// Appearance the style was last set up for, with the visuals it resulted in
+    #[serde(skip)]
+    applied_appearance: Option<(crate::style::Appearance, egui::Visuals)>,
+    /// Chat dropped in the chat list and the index it was dropped before
+    #[serde(skip)]
+    chat_to_move: Option<(usize, usize)>,
+    /// The last removed chat, its index and when it was removed. It's dropped for good
+    /// when another chat is removed or the undo times out.
+    #[serde(skip)]
+    recently_removed: Option<(usize, Chat, Instant)>,
+    /// Id of the chat added because the removed chat was the last regular one, it's removed
+    /// again by the undo if it wasn't used
+    #[serde(skip)]
+    removal_placeholder: Option<usize>,
     #[cfg(feature = "tts")]
     #[serde(skip)]
     is_speaking: bool,
     #[cfg(feature = "tts")]
     #[serde(skip)]
     tts: SharedTts,
+    /// Names of the available TTS voices, queried when the settings are first shown
+    #[cfg(feature = "tts")]
+    #[serde(skip)]
+    tts_voices: Option<Vec<String>>,
     #[serde(skip)]
     commonmark_cache: CommonMarkCache,
     #[serde(skip)]
     flower: OllamaFlower,
     #[serde(skip)]
+    transcription_flower: TranscriptionFlower,
+    /// Last known model list, shown while it's refreshed after a restart
     models: Vec<LocalModel>,
+    /// `models` wasn't refreshed since the app started
+    #[serde(skip)]
+    models_stale: bool,
     #[serde(skip)]
     flower_activity: OllamaFlowerActivity,
     #[serde(skip)]
     last_request_time: Instant,
     #[serde(skip)]
     pending_model_infos: HashMap<String, ()>,
+    model_info_cache: HashMap<String, CachedModelInfo>,
     #[serde(skip)]
     virtual_list: Rc<RefCell<VirtualList>>,
     edited_chat: Option<usize>,
     chat_export_format: ChatExportFormat,
+    export_timestamps: bool,
     #[serde(skip)]
     toasts: Toasts,
     settings_open: bool,
     pub settings: Settings,
+    #[serde(skip)]
+    connectivity: Connectivity,
+    #[serde(skip)]
+    running_models: Option<Vec<RunningModel>>,
+    /// Chats encrypted with the user's passphrase, `chats` are saved empty when this is set
+    encrypted_chats: Option<EncryptedData>,
+    #[serde(skip)]
+    encryption_key: Option<EncryptionKey>,
+    #[serde(skip)]
+    passphrase_buf: String,
+    #[serde(skip)]
+    passphrase_confirm_buf: String,
+    #[serde(skip)]
+    passphrase_error: String,
+    /// The app was locked after being idle or with the lock shortcut, chats keep generating
+    /// in the background
+    #[serde(skip)]
+    app_locked: bool,
+    /// Check of the entered passphrase or PIN, running on another thread
+    #[serde(skip)]
+    unlock_check: Option<std::thread::JoinHandle<bool>>,
+    /// Decryption of the chats saved encrypted with the entered passphrase, running on
+    /// another thread
+    #[serde(skip)]
+    decryption: Option<std::thread::JoinHandle<anyhow::Result<(EncryptionKey, Vec<u8>)>>>,
+    /// Failed unlock attempts in a row, each one doubles the wait before the next attempt
+    #[serde(skip)]
+    failed_unlocks: u32,
+    #[serde(skip)]
+    unlock_retry_at: Option<Instant>,
+    /// The keyboard shortcuts overlay is shown
+    #[serde(skip)]
+    shortcuts_open: bool,
+    /// Time of the last input, to lock the app after being idle
+    #[serde(skip)]
+    last_input: Option<Instant>,
+    #[serde(skip)]
+    warmup: Option<Warmup>,
+    /// When each model was last warmed up, to warm them up at most every [`WARMUP_INTERVAL`]
+    #[serde(skip)]
+    warmed_models: HashMap<String, Instant>,
+    /// Chat id and model that may be warmed up, and since when they are selected
+    #[serde(skip)]
+    warmup_candidate: Option<(usize, String, Instant)>,
+    #[serde(skip)]
+    pin_buf: String,
+    #[serde(skip)]
+    pin_confirm_buf: String,
+    /// Whether the deferred startup work (TTS, model list) was done
+    #[serde(skip)]
+    started: bool,
+    /// Set once the user decided what to do with the scratchpad, so the window can close
+    #[serde(skip)]
+    allow_close: bool,
+    #[cfg(feature = "tray")]
+    #[serde(skip)]
+    tray: Option<crate::tray::Tray>,
+    /// Width of the chat list on the left, restored on startup
+    sidebar_width: f32,
+    /// Images found in a picked folder, waiting for the user to confirm attaching them
+    #[serde(skip)]
+    pending_folder_images: Option<(usize, Vec<PathBuf>)>,
+    #[serde(skip)]
+    open_folder_images_modal: bool,
+    #[serde(skip)]
+    pending_export: Option<PendingExport>,
+    /// Model that was picked and whose license has to be accepted
+    #[serde(skip)]
+    license_check: Option<LicenseCheck>,
+    /// Error shown in the error dialog, with the dialog title and the id of the chat whose
+    /// completion failed
+    #[serde(skip)]
+    error_dialog: Option<(String, AppError, Option<usize>)>,
+    /// Chat to send the failed prompt of again once the model being pulled is installed
+    #[serde(skip)]
+    retry_after_pull: Option<usize>,
+    /// Diagnostic info shown in its dialog
+    #[serde(skip)]
+    diagnostics: Option<String>,
+    /// Monotonic and wall clock time of the last frame
+    #[serde(skip)]
+    last_frame: Option<(Instant, SystemTime)>,
+    #[serde(skip)]
+    last_wake_refresh: Option<Instant>,
+    /// Number of chats with unread responses shown in the window title
+    #[serde(skip)]
+    title_unread: usize,
+    #[serde(skip)]
+    last_saved: Option<chrono::DateTime<chrono::Local>>,
+    /// Serialized size of the chats in bytes, with the chat and message counts it was
+    /// computed for
+    #[serde(skip)]
+    data_size: Option<((usize, usize), usize)>,
 }
 
 impl Default for Sessions {
@@ -121,29 +543,84 @@ impl Default for Sessions {
             chats: vec![Chat::default()],
             selected_chat: 0,
             chat_marked_for_deletion: 0,
+            chat_to_duplicate: None,
+            last_chat_id: 1,
+            popped_out: None,
+            popout: None,
+            applied_appearance: None,
+            chat_to_move: None,
+            recently_removed: None,
+            removal_placeholder: None,
             #[cfg(feature = "tts")]
             is_speaking: false,
             #[cfg(feature = "tts")]
-            tts: Tts::default()
-                .map_err(|e| log::error!("failed to initialize TTS: {e}"))
-                .map(|tts| Arc::new(RwLock::new(tts)))
-                .ok(),
+            tts: None,
+            #[cfg(feature = "tts")]
+            tts_voices: None,
             commonmark_cache: CommonMarkCache::default(),
             flower: OllamaFlower::new(1),
+            transcription_flower: TranscriptionFlower::new(1),
             models: Vec::new(),
+            models_stale: true,
             flower_activity: OllamaFlowerActivity::default(),
             last_request_time: now,
             pending_model_infos: HashMap::new(),
+            model_info_cache: HashMap::new(),
             virtual_list: Rc::new(RefCell::new(VirtualList::default())),
             edited_chat: None,
             chat_export_format: ChatExportFormat::default(),
+            export_timestamps: true,
             toasts: Toasts::default(),
             settings_open: false,
             settings: Settings::default(),
+            connectivity: Connectivity::default(),
+            running_models: None,
+            encrypted_chats: None,
+            encryption_key: None,
+            passphrase_buf: String::new(),
+            passphrase_confirm_buf: String::new(),
+            passphrase_error: String::new(),
+            app_locked: false,
+            unlock_check: None,
+            decryption: None,
+            failed_unlocks: 0,
+            unlock_retry_at: None,
+            shortcuts_open: false,
+            last_input: None,
+            warmup: None,
+            warmed_models: HashMap::new(),
+            warmup_candidate: None,
+            pin_buf: String::new(),
+            pin_confirm_buf: String::new(),
+            started: false,
+            allow_close: false,
+            #[cfg(feature = "tray")]
+            tray: None,
+            sidebar_width: 200.0,
+            pending_folder_images: None,
+            open_folder_images_modal: false,
+            pending_export: None,
+            license_check: None,
+            error_dialog: None,
+            retry_after_pull: None,
+            diagnostics: None,
+            last_frame: None,
+            last_wake_refresh: None,
+            title_unread: 0,
+            last_saved: None,
+            data_size: None,
         }
     }
 }
 
+/// Serialize all chats except scratchpads
+fn serialize_persistent_chats<S: serde::Serializer>(
+    chats: &[Chat],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(chats.iter().filter(|c| !c.ephemeral))
+}
+
 async fn list_local_models(ollama: Ollama, handle: &OllamaFlowerHandle) {
     log::debug!("requesting local models...");
     match ollama.list_local_models().await {
@@ -153,25 +630,218 @@ async fn list_local_models(ollama: Ollama, handle: &OllamaFlowerHandle) {
         }
         Err(e) => {
             log::error!("failed to list local models: {e}");
-            handle.error(e.to_string());
+            handle.error(AppError::from_error(&e));
         }
     }
 }
 
-async fn request_model_info(ollama: Ollama, model_name: String, handle: &OllamaFlowerHandle) {
+/// Request the parameter count and quantization of a model, `None` if the server doesn't
+/// report them
+async fn request_model_details(url: url::Url, model_name: &str) -> Option<ModelDetails> {
+    #[derive(serde::Deserialize)]
+    struct ShowResponse {
+        #[serde(default)]
+        details: ModelDetails,
+    }
+
+    let url = url.join("api/show").ok()?;
+    let response = reqwest::Client::new()
+        .post(url)
+        .json(&serde_json::json!({ "name": model_name }))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status());
+    match response {
+        Ok(response) => match response.json::<ShowResponse>().await {
+            Ok(show) => Some(show.details),
+            Err(e) => {
+                log::error!("failed to parse model `{model_name}` details: {e}");
+                None
+            }
+        },
+        Err(e) => {
+            log::error!("failed to request model `{model_name}` details: {e}");
+            None
+        }
+    }
+}
+
+async fn request_model_info(
+    ollama: Ollama,
+    url: url::Url,
+    model_name: String,
+    handle: &OllamaFlowerHandle,
+) {
     match ollama.show_model_info(model_name.clone()).await {
         Ok(info) => {
             log::debug!("model `{model_name}` info: {info:?}");
+            let details = request_model_details(url, &model_name).await;
             handle.success(OllamaResponse::ModelInfo {
                 name: model_name,
                 info,
+                details,
             });
         }
         Err(e) => {
             log::error!("failed to request model `{model_name}` info: {e}");
-            handle.error(e.to_string());
+            handle.error(AppError::from_error(&e));
+        }
+    }
+}
+
+async fn load_model(
+    ollama: Ollama,
+    model_name: String,
+    keep_alive: Option<KeepAlive>,
+    handle: &OllamaFlowerHandle,
+) {
+    log::debug!("loading model `{model_name}` into memory...");
+
+    // an empty prompt makes ollama load the model without generating anything
+    let mut request = GenerationRequest::new(model_name.clone(), String::new());
+    if let Some(keep_alive) = keep_alive {
+        request = request.keep_alive(keep_alive);
+    }
+    match ollama.generate(request).await {
+        Ok(_) => {
+            log::info!("model `{model_name}` is loaded");
+            handle.success(OllamaResponse::Toast(Toast::success(format!(
+                "Model `{model_name}` is loaded"
+            ))));
+        }
+        Err(e) => {
+            log::error!("failed to load model `{model_name}`: {e}");
+            handle.error(AppError::from_error(&e));
+        }
+    }
+}
+
+/// Load a model with an empty prompt if it isn't running. Failures are only logged, the
+/// warmup happens without the user asking for it
+async fn warm_up_model(
+    ollama: Ollama,
+    url: url::Url,
+    model_name: String,
+    keep_alive: Option<KeepAlive>,
+) {
+    #[derive(serde::Deserialize)]
+    struct RunningModels {
+        models: Vec<RunningModel>,
+    }
+
+    // the list in the settings may be outdated
+    let running = match url.join("api/ps") {
+        Ok(url) => match reqwest::get(url).await {
+            Ok(resp) => resp.json::<RunningModels>().await,
+            Err(e) => Err(e),
+        },
+        Err(e) => {
+            log::warn!("invalid endpoint url: {e}");
+            return;
+        }
+    };
+    match running {
+        Ok(running) if running.models.iter().any(|m| m.name == model_name) => {
+            log::debug!("model `{model_name}` is already loaded, no warmup needed");
+            return;
+        }
+        Ok(_) => (),
+        Err(e) => {
+            log::warn!("failed to list running models before warming up `{model_name}`: {e}");
+            return;
+        }
+    }
+
+    log::info!("warming up model `{model_name}`...");
+    let mut request = GenerationRequest::new(model_name.clone(), String::new());
+    if let Some(keep_alive) = keep_alive {
+        request = request.keep_alive(keep_alive);
+    }
+    match ollama.generate(request).await {
+        Ok(_) => log::info!("model `{model_name}` is warmed up"),
+        Err(e) => log::warn!("failed to warm up model `{model_name}`: {e}"),
+    }
+}
+
+async fn pull_model(ollama: Ollama, model_name: String, handle: &OllamaFlowerHandle) {
+    log::debug!("pulling model `{model_name}`...");
+    match ollama.pull_model(model_name.clone(), false).await {
+        Ok(status) => {
+            log::info!("pulled model `{model_name}`: {}", status.message);
+            list_local_models(ollama, handle).await;
+        }
+        Err(e) => {
+            log::error!("failed to pull model `{model_name}`: {e}");
+            handle.error(AppError::from_error(&e));
+        }
+    }
+}
+
+async fn list_running_models(url: url::Url, handle: &OllamaFlowerHandle) {
+    #[derive(serde::Deserialize)]
+    struct RunningModels {
+        models: Vec<RunningModel>,
+    }
+
+    log::debug!("requesting running models...");
+    let Ok(url) = url.join("api/ps") else {
+        handle.error(AppError::Other("invalid endpoint url".to_owned()));
+        return;
+    };
+    let models = match reqwest::get(url).await {
+        Ok(resp) => resp.json::<RunningModels>().await,
+        Err(e) => Err(e),
+    };
+    match models {
+        Ok(RunningModels { models }) => {
+            log::debug!("{} running models: {models:?}", models.len());
+            handle.success(OllamaResponse::RunningModels(models));
+        }
+        Err(e) => {
+            log::error!("failed to list running models: {e}");
+            handle.error(AppError::from_error(&e));
+        }
+    }
+}
+
+async fn request_server_version(url: url::Url, handle: &OllamaFlowerHandle) {
+    #[derive(serde::Deserialize)]
+    struct Version {
+        version: String,
+    }
+
+    log::debug!("requesting server version...");
+    let version = match url.join("api/version") {
+        Ok(url) => match reqwest::get(url).await {
+            Ok(resp) => resp.json::<Version>().await.map(|v| v.version),
+            Err(e) => Err(e),
+        }
+        .map_err(|e| log::warn!("failed to get the server version: {e}"))
+        .ok(),
+        Err(e) => {
+            log::warn!("invalid endpoint url: {e}");
+            None
         }
+    };
+    handle.success(OllamaResponse::ServerVersion(version));
+}
+
+async fn unload_model(
+    ollama: Ollama,
+    url: url::Url,
+    model_name: String,
+    handle: &OllamaFlowerHandle,
+) {
+    log::debug!("unloading model `{model_name}`...");
+    let request = GenerationRequest::new(model_name.clone(), String::new())
+        .keep_alive(KeepAlive::UnloadOnCompletion);
+    if let Err(e) = ollama.generate(request).await {
+        log::error!("failed to unload model `{model_name}`: {e}");
+        handle.error(AppError::from_error(&e));
+        return;
     }
+    log::info!("unloaded model `{model_name}`");
+    list_running_models(url, handle).await;
 }
 
 async fn pick_images(id: usize, handle: &OllamaFlowerHandle) {
@@ -192,59 +862,325 @@ async fn pick_images(id: usize, handle: &OllamaFlowerHandle) {
     });
 }
 
-async fn load_settings(handle: &OllamaFlowerHandle) {
-    let Some(file) = rfd::AsyncFileDialog::new()
-        .add_filter("JSON file", &["json"])
-        .pick_file()
-        .await
-    else {
-        handle.success(OllamaResponse::Toast(Toast::info("No file selected")));
-        return;
-    };
+/// Find the images in a folder sorted by file name, returns them and the number of
+/// other files that were skipped
+fn scan_image_folder(dir: &Path, recursive: bool) -> std::io::Result<(Vec<PathBuf>, usize)> {
+    let mut images = Vec::new();
+    let mut skipped = 0;
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                if recursive {
+                    dirs.push(path);
+                }
+                continue;
+            }
+            let ext = path
+                .extension()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default()
+                .to_lowercase();
+            if crate::IMAGE_FORMATS.contains(&ext.as_str()) {
+                images.push(path);
+            } else {
+                skipped += 1;
+            }
+        }
+    }
+    images.sort_by_cached_key(|p| p.file_name().map(|n| n.to_ascii_lowercase()));
+    Ok((images, skipped))
+}
 
-    log::info!("reading settings from `{}`", file.path().display());
-    let Ok(f) = std::fs::File::open(file.path()).map_err(|e| {
-        log::error!("failed to open file `{}`: {e}", file.path().display());
-        handle.success(OllamaResponse::Toast(Toast::error(e.to_string())));
-    }) else {
-        return;
+/// Show a desktop notification about a response that finished while the window was unfocused
+fn notify_finished(summary: &str, finished: Finished) {
+    const MAX_BODY_CHARS: usize = 100;
+    let summary = if summary.is_empty() {
+        "New Chat"
+    } else {
+        summary
     };
-
-    let settings = serde_json::from_reader(std::io::BufReader::new(f));
-    if let Ok(settings) = settings {
-        handle.success(OllamaResponse::Settings(settings));
-    } else if let Err(e) = settings {
-        log::error!("failed to load settings: {e}");
-        handle.success(OllamaResponse::Toast(Toast::error(e.to_string())));
+    let (title, body) = match finished {
+        Finished::Response(answer) => (summary.to_owned(), answer),
+        Finished::Failed(e) => (format!("{summary}: {}", e.title()), e.reason().into_owned()),
+    };
+    let full = body.trim();
+    let mut body: String = full.chars().take(MAX_BODY_CHARS).collect();
+    if body.len() < full.len() {
+        body.push('…');
     }
+
+    // showing a notification may block on D-Bus, don't stall the UI
+    std::thread::spawn(move || {
+        if let Err(e) = notify_rust::Notification::new()
+            .appname(crate::TITLE)
+            .summary(&title)
+            .body(&body)
+            .show()
+        {
+            log::error!("failed to show notification: {e}");
+        }
+    });
 }
 
-fn preview_files_being_dropped(ctx: &egui::Context) {
-    use egui::*;
-    use std::fmt::Write as _;
+/// Play a short chime about a response that finished while the window was unfocused, rising
+/// for responses and falling for errors
+#[cfg(feature = "sound")]
+fn play_finished_sound(failed: bool) {
+    fn play(failed: bool) -> anyhow::Result<()> {
+        use rodio::Source as _;
+        const NOTE: Duration = Duration::from_millis(120);
 
-    if !ctx.input(|i| i.raw.hovered_files.is_empty()) {
-        let text = ctx.input(|i| {
-            let mut text = "Dropping files:".to_owned();
-            for file in &i.raw.hovered_files {
-                if let Some(path) = &file.path {
-                    write!(text, "\n{}", path.display()).ok();
-                } else if !file.mime.is_empty() {
-                    write!(text, "\n{}", file.mime).ok();
-                } else {
-                    text += "\n???";
-                }
-            }
-            text
-        });
+        let (_stream, handle) = rodio::OutputStream::try_default()?;
+        let sink = rodio::Sink::try_new(&handle)?;
+        let notes = if failed {
+            [880.0, 587.0]
+        } else {
+            [660.0, 880.0]
+        };
+        for freq in notes {
+            sink.append(
+                rodio::source::SineWave::new(freq)
+                    .take_duration(NOTE)
+                    .amplify(0.2),
+            );
+        }
+        sink.sleep_until_end();
+        Ok(())
+    }
 
-        let painter =
-            ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("file_drop_target")));
+    // the output stream has to live until the sound is played
+    std::thread::spawn(move || {
+        if let Err(e) = play(failed) {
+            log::error!("failed to play sound: {e}");
+        }
+    });
+}
 
-        let screen_rect = ctx.screen_rect();
-        painter.rect_filled(screen_rect, 0.0, Color32::from_black_alpha(192));
-        painter.text(
-            screen_rect.center(),
+/// Make a chat summary usable as a file name
+fn sanitize_file_name(name: &str) -> String {
+    const MAX_LEN: usize = 64;
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || r#"<>:"/\|?*"#.contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .take(MAX_LEN)
+        .collect();
+    let name = name.trim().trim_end_matches('.');
+    if name.is_empty() {
+        "Chat".to_owned()
+    } else {
+        name.to_owned()
+    }
+}
+
+async fn pick_image_folder(id: usize, recursive: bool, handle: &OllamaFlowerHandle) {
+    let Some(dir) = rfd::AsyncFileDialog::new().pick_folder().await else {
+        handle.success(OllamaResponse::Ignore);
+        return;
+    };
+    let dir = dir.path().to_path_buf();
+
+    match tokio::task::spawn_blocking(move || scan_image_folder(&dir, recursive)).await {
+        Ok(Ok((files, skipped))) => {
+            log::info!(
+                "found {} image(s) in folder, skipped {skipped} file(s)",
+                files.len()
+            );
+            handle.success(OllamaResponse::ImageFolder { id, files, skipped });
+        }
+        Ok(Err(e)) => {
+            log::error!("failed to read folder: {e}");
+            handle.error(AppError::Other(format!("Failed to read folder: {e}")));
+        }
+        Err(e) => {
+            log::error!("failed to scan folder: {e}");
+            handle.error(AppError::from_error(&e));
+        }
+    }
+}
+
+async fn pick_files(id: usize, handle: &OllamaFlowerHandle) {
+    let Some(files) = rfd::AsyncFileDialog::new()
+        .add_filter("Text", crate::TEXT_FORMATS)
+        .add_filter("Audio", crate::AUDIO_FORMATS)
+        .add_filter("All files", &["*"])
+        .pick_files()
+        .await
+    else {
+        handle.success(OllamaResponse::Ignore);
+        return;
+    };
+
+    log::info!("selected {} file(s)", files.len());
+
+    handle.success(OllamaResponse::Files {
+        id,
+        files: files.iter().map(|f| f.path().to_path_buf()).collect(),
+    });
+}
+
+async fn transcribe(
+    backend: &TranscriptionBackend,
+    path: &std::path::Path,
+) -> anyhow::Result<String> {
+    use anyhow::Context as _;
+
+    log::debug!("transcribing `{}`...", path.display());
+    match backend {
+        TranscriptionBackend::None => anyhow::bail!("no transcription backend is configured"),
+        TranscriptionBackend::Command(template) => {
+            // split the arguments ourselves so the path is never interpreted by a shell
+            let path = path.to_string_lossy();
+            let mut args = template
+                .split_whitespace()
+                .map(|arg| arg.replace("{file}", &path));
+            let program = args.next().context("the transcription command is empty")?;
+            let output = tokio::process::Command::new(&program)
+                .args(args)
+                .output()
+                .await
+                .with_context(|| format!("failed to run `{program}`"))?;
+            if !output.status.success() {
+                anyhow::bail!(
+                    "`{program}` failed ({}): {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+        }
+        TranscriptionBackend::Server(url) => {
+            #[derive(serde::Deserialize)]
+            struct Transcript {
+                text: String,
+            }
+
+            let file_name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            let bytes = tokio::fs::read(path).await?;
+            let form = reqwest::multipart::Form::new()
+                .part(
+                    "file",
+                    reqwest::multipart::Part::bytes(bytes).file_name(file_name),
+                )
+                .text("response_format", "json");
+            let body = reqwest::Client::new()
+                .post(url)
+                .multipart(form)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+            // fall back to the raw body for servers that respond with plain text
+            let text = serde_json::from_str::<Transcript>(&body)
+                .map(|t| t.text)
+                .unwrap_or(body);
+            Ok(text.trim().to_owned())
+        }
+    }
+}
+
+async fn load_settings(handle: &OllamaFlowerHandle) {
+    let Some(file) = rfd::AsyncFileDialog::new()
+        .add_filter("JSON file", &["json"])
+        .pick_file()
+        .await
+    else {
+        handle.success(OllamaResponse::Toast(Toast::info("No file selected")));
+        return;
+    };
+
+    log::info!("reading settings from `{}`", file.path().display());
+    let Ok(f) = std::fs::File::open(file.path()).map_err(|e| {
+        log::error!("failed to open file `{}`: {e}", file.path().display());
+        handle.success(OllamaResponse::Toast(Toast::error(e.to_string())));
+    }) else {
+        return;
+    };
+
+    let settings = serde_json::from_reader(std::io::BufReader::new(f));
+    if let Ok(settings) = settings {
+        handle.success(OllamaResponse::Settings(settings));
+    } else if let Err(e) = settings {
+        log::error!("failed to load settings: {e}");
+        handle.success(OllamaResponse::Toast(Toast::error(e.to_string())));
+    }
+}
+
+/// Show a toast about the chat with `id` in the window the chat is shown in
+fn add_chat_toast(
+    toasts: &mut Toasts,
+    popout: Option<&Arc<Mutex<PopOut>>>,
+    popped_out: Option<usize>,
+    id: usize,
+    toast: Toast,
+) {
+    match popout.filter(|_| popped_out == Some(id)) {
+        Some(popout) => {
+            popout.lock().toasts.add(toast);
+        }
+        None => {
+            toasts.add(toast);
+        }
+    }
+}
+
+/// Copy the settings a chat needs to show itself into it
+fn apply_chat_settings(settings: &Settings, chat: &mut Chat) {
+    chat.auto_trim = settings.auto_trim;
+    chat.timestamp_format = settings.timestamp_format;
+    chat.send_key = settings.send_key;
+    chat.quote_template.clone_from(&settings.quote_template);
+    chat.image_prompt.clone_from(&settings.image_prompt);
+    chat.translation_language
+        .clone_from(&settings.translation_language);
+    chat.translation_model
+        .clone_from(&settings.translation_model);
+    chat.confirm_duplicates = settings.confirm_duplicate_sends;
+    chat.always_show_actions = settings.always_show_actions;
+    chat.max_width = settings.chat_max_width;
+    chat.group_exchanges = settings.group_exchanges;
+    chat.max_message_bytes = settings.max_message_kib * 1024;
+    chat.max_image_dimension = settings.max_image_dimension;
+}
+
+fn preview_files_being_dropped(ctx: &egui::Context) {
+    use egui::*;
+    use std::fmt::Write as _;
+
+    if !ctx.input(|i| i.raw.hovered_files.is_empty()) {
+        let text = ctx.input(|i| {
+            let mut text = "Dropping files:".to_owned();
+            for file in &i.raw.hovered_files {
+                if let Some(path) = &file.path {
+                    write!(text, "\n{}", path.display()).ok();
+                } else if !file.mime.is_empty() {
+                    write!(text, "\n{}", file.mime).ok();
+                } else {
+                    text += "\n???";
+                }
+            }
+            text
+        });
+
+        let painter =
+            ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("file_drop_target")));
+
+        let screen_rect = ctx.screen_rect();
+        painter.rect_filled(screen_rect, 0.0, Color32::from_black_alpha(192));
+        painter.text(
+            screen_rect.center(),
             Align2::CENTER_CENTER,
             text,
             TextStyle::Heading.resolve(&ctx.style()),
@@ -254,10 +1190,28 @@ fn preview_files_being_dropped(ctx: &egui::Context) {
 }
 
 impl Sessions {
-    pub fn new(ollama: Ollama) -> Self {
-        let mut sessions = Self::default();
-        sessions.list_models(ollama);
-        sessions
+    /// Do the startup work that isn't needed to show the first frame
+    pub fn start(&mut self, ctx: &egui::Context, ollama: Ollama) {
+        #[cfg(feature = "tts")]
+        {
+            let now = Instant::now();
+            self.tts = Tts::default()
+                .map_err(|e| log::error!("failed to initialize TTS: {e}"))
+                .map(|tts| Arc::new(RwLock::new(tts)))
+                .ok();
+            log::debug!("initialized TTS in {:?}", now.elapsed());
+            self.apply_tts_settings();
+        }
+        #[cfg(feature = "tray")]
+        if self.settings.minimize_to_tray {
+            self.tray = crate::tray::Tray::new(ctx)
+                .map_err(|e| log::error!("failed to create tray icon: {e}"))
+                .ok();
+        }
+        #[cfg(not(feature = "tray"))]
+        let _ = ctx;
+        self.list_models(ollama);
+        self.started = true;
     }
 
     pub fn list_models(&mut self, ollama: Ollama) {
@@ -280,22 +1234,139 @@ impl Sessions {
                     handle.success(OllamaResponse::ModelInfo {
                         name: model_name.clone(),
                         info,
+                        details: chat.model_picker.details.clone(),
                     });
                     return;
                 }
             }
         }
+        if let Some(cached) = self.model_info_cache.get(&model_name).filter(|cached| {
+            self.models
+                .iter()
+                .any(|m| m.name == model_name && m.modified_at == cached.modified_at)
+        }) {
+            log::debug!("using cached info of model `{model_name}`");
+            handle.activate();
+            handle.success(OllamaResponse::ModelInfo {
+                name: model_name,
+                info: cached.info.clone(),
+                details: cached.details.clone(),
+            });
+            return;
+        }
 
         self.flower_activity = OllamaFlowerActivity::ModelInfo;
         self.last_request_time = Instant::now();
         self.pending_model_infos.insert(model_name.clone(), ());
+        let url = self.settings.endpoint_url();
+        tokio::spawn(async move {
+            handle.activate();
+            request_model_info(ollama, url, model_name, &handle).await;
+        });
+    }
+
+    fn list_running_models(&mut self) {
+        let handle = self.flower.handle();
+        let url = self.settings.endpoint_url();
+        self.flower_activity = OllamaFlowerActivity::RunningModels;
+        tokio::spawn(async move {
+            handle.activate();
+            list_running_models(url, &handle).await;
+        });
+    }
+
+    fn unload_model(&mut self, model_name: String, ollama: Ollama) {
+        let handle = self.flower.handle();
+        let url = self.settings.endpoint_url();
+        self.flower_activity = OllamaFlowerActivity::RunningModels;
+        tokio::spawn(async move {
+            handle.activate();
+            unload_model(ollama, url, model_name, &handle).await;
+        });
+    }
+
+    fn pull_model(&mut self, model_name: String, ollama: Ollama) {
+        let handle = self.flower.handle();
+        self.flower_activity = OllamaFlowerActivity::PullModel;
+        self.toasts.add(Toast::info(format!(
+            "Pulling `{model_name}`, this may take a while"
+        )));
+        tokio::spawn(async move {
+            handle.activate();
+            pull_model(ollama, model_name, &handle).await;
+        });
+    }
+
+    #[inline]
+    fn is_pulling_model(&self) -> bool {
+        self.flower.is_active() && self.flower_activity == OllamaFlowerActivity::PullModel
+    }
+
+    #[inline]
+    fn is_loading_running_models(&self) -> bool {
+        self.flower.is_active() && self.flower_activity == OllamaFlowerActivity::RunningModels
+    }
+
+    fn spawn_load_model(
+        flower: &OllamaFlower,
+        ollama: &Ollama,
+        model_name: &str,
+        keep_alive: Option<KeepAlive>,
+    ) {
+        let handle = flower.handle();
+        let ollama = ollama.clone();
+        let model_name = model_name.to_string();
         tokio::spawn(async move {
             handle.activate();
-            request_model_info(ollama, model_name, &handle).await;
+            load_model(ollama, model_name, keep_alive, &handle).await;
         });
     }
 
-    pub fn show(&mut self, ctx: &egui::Context, ollama: &Ollama) {
+    /// `system_dark` is whether the OS uses a dark theme, `None` if it's unknown
+    pub fn show(&mut self, ctx: &egui::Context, ollama: &Ollama, system_dark: Option<bool>) {
+        self.reclaim_popped_out();
+        self.show_main_window(ctx, ollama, system_dark);
+        self.lend_popped_out(ollama);
+        self.show_popped_out_window(ctx);
+    }
+
+    fn show_main_window(
+        &mut self,
+        ctx: &egui::Context,
+        ollama: &Ollama,
+        system_dark: Option<bool>,
+    ) {
+        let appearance = crate::style::Appearance {
+            min_font_size: self.settings.min_font_size,
+            high_contrast: self.settings.high_contrast,
+            dark_mode: self.settings.theme.is_dark(system_dark),
+            accent: self
+                .settings
+                .accent_color
+                .map(|[r, g, b]| Color32::from_rgb(r, g, b)),
+        };
+        // eframe also resets the visuals when the system theme changes
+        let up_to_date = self
+            .applied_appearance
+            .as_ref()
+            .is_some_and(|(applied, visuals)| {
+                *applied == appearance && ctx.style().visuals == *visuals
+            });
+        if !up_to_date {
+            crate::style::apply_appearance(ctx, appearance);
+            self.applied_appearance = Some((appearance, ctx.style().visuals.clone()));
+        }
+
+        if self.is_locked() {
+            self.poll_decryption(ctx);
+            self.show_unlock_screen(ctx);
+            return;
+        }
+
+        self.refresh_after_wake(ollama);
+        self.update_app_lock(ctx);
+        self.update_warmup(ctx, ollama);
+
         // check if tts stopped speaking
         #[cfg(feature = "tts")]
         let prev_is_speaking = self.is_speaking;
@@ -315,10 +1386,78 @@ impl Sessions {
         #[cfg(not(feature = "tts"))]
         let mut request_repaint = false;
 
-        let mut modal = Modal::new(ctx, "sessions_main_modal");
-        let mut chat_modal = Modal::new(ctx, "chat_main_modal").with_close_on_outside_click(true);
         let settings_modal =
             Modal::new(ctx, "global_settings_modal").with_close_on_outside_click(true);
+        let scratchpad_modal = Modal::new(ctx, "scratchpad_exit_modal");
+
+        self.handle_close_request(ctx, &scratchpad_modal);
+
+        // nothing is shown over the lock screen, the chats keep generating behind it
+        if self.app_locked {
+            self.poll_unlock(ctx);
+            self.show_app_lock_screen(ctx);
+            self.poll_tasks(ctx, ollama, request_repaint);
+            self.toasts.show(ctx);
+            return;
+        }
+
+        // a chat asked for from the tray while locked is opened after unlocking
+        #[cfg(feature = "tray")]
+        if self
+            .tray
+            .as_ref()
+            .is_some_and(crate::tray::Tray::take_new_chat)
+        {
+            self.add_default_chat();
+            self.selected_chat = self.chats.len() - 1;
+            self.edited_chat = None;
+            self.settings_open = false;
+        }
+        self.handle_shortcuts(ctx);
+
+        let folder_images_modal = Modal::new(ctx, "folder_images_modal");
+        if std::mem::take(&mut self.open_folder_images_modal) {
+            folder_images_modal.open();
+        }
+        folder_images_modal.show(|ui| {
+            self.show_folder_images_modal_inner(ui, &folder_images_modal);
+        });
+        let license_modal = Modal::new(ctx, "license_modal");
+        if self
+            .license_check
+            .as_ref()
+            .is_some_and(|c| c.license.is_some())
+            && !license_modal.is_open()
+        {
+            license_modal.open();
+        }
+        license_modal.show(|ui| {
+            self.show_license_modal_inner(ui, &license_modal);
+        });
+        let error_modal = Modal::new(ctx, "error_modal");
+        if self.error_dialog.is_some() && !error_modal.is_open() {
+            error_modal.open();
+        }
+        error_modal.show(|ui| {
+            self.show_error_modal_inner(ui, &error_modal, ollama);
+        });
+        let diagnostics_modal = Modal::new(ctx, "diagnostics_modal");
+        if self.diagnostics.is_some() && !diagnostics_modal.is_open() {
+            diagnostics_modal.open();
+        }
+        diagnostics_modal.show(|ui| {
+            self.show_diagnostics_modal_inner(ui, &diagnostics_modal);
+        });
+        let export_modal = Modal::new(ctx, "plaintext_export_modal");
+        if self.pending_export.is_some() && !export_modal.is_open() {
+            export_modal.open();
+        }
+        export_modal.show(|ui| {
+            self.show_plaintext_export_modal_inner(ui, &export_modal);
+        });
+        scratchpad_modal.show(|ui| {
+            self.show_scratchpad_exit_modal_inner(ui, &scratchpad_modal);
+        });
 
         // if self.edit_modal_open {
         //     let mut open = self.edit_modal_open;
@@ -334,34 +1473,24 @@ impl Sessions {
 
         // show dialogs created on the previous frame, if we move this into the end of the function
         // it won't be located in the center of the window but in the center of the centralpanel instead
-        chat_modal.show_dialog();
-        modal.show_dialog();
         self.settings.show_modal(&settings_modal);
 
+        if self.connectivity.is_offline() {
+            self.show_offline_banner(ctx, ollama);
+        }
+
         let avail_width = ctx.available_rect().width();
-        egui::SidePanel::left("sessions_panel")
+        let sidebar = egui::SidePanel::left("sessions_panel")
             .resizable(true)
+            .default_width(self.sidebar_width)
             .max_width(avail_width * 0.5)
             .show(ctx, |ui| {
                 self.show_left_panel(ui);
                 ui.allocate_space(ui.available_size());
             });
+        self.sidebar_width = sidebar.response.rect.width();
 
-        // poll all flowers
-        for chat in self.chats.iter_mut() {
-            if chat.flower_active() {
-                request_repaint = true;
-                chat.poll_flower(&mut chat_modal);
-            }
-        }
-        if self.flower.is_active() {
-            request_repaint = true;
-            self.poll_ollama_flower(&modal);
-        }
-
-        if request_repaint {
-            ctx.request_repaint();
-        }
+        self.poll_tasks(ctx, ollama, request_repaint);
 
         if self.settings_open {
             self.edited_chat = None;
@@ -369,14 +1498,26 @@ impl Sessions {
                 egui::ScrollArea::both().auto_shrink(false).show(ui, |ui| {
                     let mut request_info_for: Option<String> = None;
                     let mut list_models = false;
+                    let mut list_running_models = false;
+                    let mut unload_model: Option<String> = None;
+                    let mut pull_model: Option<String> = None;
+                    let mut export_all = false;
+                    let is_loading_running_models = self.is_loading_running_models();
+                    let is_loading_models = self.is_loading_models();
+                    self.show_stale_models_note(ui, is_loading_models);
 
                     self.settings.show(
                         ui,
-                        if self.is_loading_models() {
+                        if is_loading_models && self.models.is_empty() {
                             None
                         } else {
                             Some(&self.models)
                         },
+                        if is_loading_running_models {
+                            None
+                        } else {
+                            self.running_models.as_deref()
+                        },
                         &mut |typ| match typ {
                             RequestInfoType::ModelInfo(name) => {
                                 if !self.pending_model_infos.contains_key(name) {
@@ -393,14 +1534,63 @@ impl Sessions {
                                     load_settings(&handle).await;
                                 });
                             }
+                            RequestInfoType::LoadModel { name, keep_alive } => {
+                                Self::spawn_load_model(&self.flower, ollama, name, keep_alive);
+                            }
+                            RequestInfoType::RunningModels => {
+                                list_running_models = !is_loading_running_models;
+                            }
+                            RequestInfoType::UnloadModel(name) => {
+                                unload_model = Some(name.to_string());
+                            }
+                            RequestInfoType::PullModel(name) => {
+                                pull_model = Some(name.to_string());
+                            }
+                            RequestInfoType::ExportAllChats => {
+                                export_all = true;
+                            }
                         },
                         &settings_modal,
                     );
 
+                    if export_all {
+                        self.request_export(PendingExport::AllChats);
+                    }
+
+                    #[cfg(feature = "tts")]
+                    {
+                        ui.separator();
+                        self.show_tts_settings(ui);
+                    }
+
+                    ui.separator();
+                    self.show_encryption_settings(ui);
+
+                    ui.separator();
+                    self.show_lock_settings(ui);
+
+                    ui.separator();
+                    self.show_data_settings(ui);
+
+                    ui.separator();
+                    self.show_diagnostics_settings(ui);
+
+                    if let Some(name) = unload_model {
+                        self.unload_model(name, ollama.clone());
+                    } else if list_running_models
+                        || (self.running_models.is_none() && !is_loading_running_models)
+                    {
+                        self.list_running_models();
+                    }
+
                     if let Some(name) = request_info_for {
                         self.request_model_info(name, ollama.clone());
                     }
-                    if list_models {
+                    if let Some(name) = pull_model {
+                        if !self.is_pulling_model() {
+                            self.pull_model(name, ollama.clone());
+                        }
+                    } else if list_models {
                         self.list_models(ollama.clone());
                     }
                 });
@@ -421,44 +1611,268 @@ impl Sessions {
             preview_files_being_dropped(ctx);
         }
 
+        #[cfg(feature = "tts")]
+        if let Some(popout) = &self.popout {
+            popout.lock().stopped_talking |= prev_is_speaking && !self.is_speaking;
+        }
+
+        self.check_model_licenses(ollama);
+
+        crate::image::show_image_viewer(ctx);
+        shortcuts::show_help(ctx, &mut self.shortcuts_open, self.settings.send_key);
+
         // display toast queue
         self.toasts.show(ctx);
     }
 
+    /// Poll the chats and background tasks, repainting while any of them is running
+    fn poll_tasks(&mut self, ctx: &egui::Context, ollama: &Ollama, mut request_repaint: bool) {
+        // poll all flowers
+        let mut completion_failed = false;
+        let unfocused = !ctx.input(|i| i.viewport().focused.unwrap_or(true));
+        let notify = self.settings.notify_on_completion && unfocused;
+        #[cfg(feature = "sound")]
+        let chime = self.settings.sound_on_completion && unfocused;
+        let mut queued_actions = Vec::new();
+        for (idx, chat) in self.chats.iter_mut().enumerate() {
+            if chat.flower_active() {
+                request_repaint = true;
+                let finished = chat.poll_flower(
+                    #[cfg(feature = "tts")]
+                    self.tts.clone(),
+                    #[cfg(feature = "tts")]
+                    self.settings.tts.auto_read,
+                    #[cfg(feature = "tts")]
+                    self.settings.tts.read_while_generating,
+                );
+                if let Some(Finished::Failed(e)) = &finished {
+                    completion_failed = true;
+                    let title = format!("Failed to generate completion: {}", e.title());
+                    // the error dialog can't be shown in the window of a popped out chat
+                    let popout = self
+                        .popout
+                        .as_ref()
+                        .filter(|_| self.popped_out == Some(chat.id()));
+                    if let Some(popout) = popout {
+                        popout.lock().toasts.add(Toast::error(title));
+                    } else {
+                        self.error_dialog = Some((title, e.clone(), Some(chat.id())));
+                    }
+                }
+                #[cfg(feature = "sound")]
+                if let Some(finished) = finished.as_ref().filter(|_| chime) {
+                    play_finished_sound(matches!(finished, Finished::Failed(_)));
+                }
+                if let Some(finished) = finished.filter(|_| notify) {
+                    notify_finished(&chat.summary, finished);
+                }
+                chat.auto_trim = self.settings.auto_trim;
+                queued_actions.push((idx, chat.send_queued_message(ollama, &self.models)));
+            }
+        }
+        for (idx, action) in queued_actions {
+            self.handle_chat_action(idx, action);
+        }
+
+        // count chats that finished in the background in the title
+        if let Some(chat) = self.chats.get_mut(self.selected_chat) {
+            chat.has_unread = false;
+        }
+        if let Some(id) = self.popped_out {
+            if let Some(chat) = self.chats.iter_mut().find(|c| c.id() == id) {
+                chat.has_unread = false;
+            }
+        }
+        let unread = self.chats.iter().filter(|c| c.has_unread).count();
+        if unread != self.title_unread {
+            self.title_unread = unread;
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(if unread == 0 {
+                crate::TITLE.to_owned()
+            } else {
+                format!("({unread}) {}", crate::TITLE)
+            }));
+        }
+
+        // a failed completion may mean that the server went away, check it
+        if completion_failed && !self.connectivity.is_offline() && !self.flower.is_active() {
+            self.list_models(ollama.clone());
+        }
+        if self.flower.is_active() {
+            request_repaint = true;
+            self.poll_ollama_flower(ctx, ollama);
+        }
+        if self.transcription_flower.is_active() {
+            request_repaint = true;
+            self.poll_transcription_flower();
+        }
+
+        if request_repaint {
+            ctx.request_repaint();
+        }
+    }
+
     fn show_selected_chat(
         &mut self,
         ctx: &egui::Context,
         ollama: &Ollama,
         #[cfg(feature = "tts")] stopped_talking: bool,
     ) {
-        let Some(chat) = self.chats.get_mut(self.selected_chat) else {
+        let Some(id) = self.chats.get(self.selected_chat).map(|c| c.id()) else {
             self.selected_chat = 0;
             return;
         };
 
-        ctx.input(|i| {
-            for file in &i.raw.dropped_files {
-                if let Some(path) = &file.path {
-                    let filename = path.file_name().unwrap_or_default().to_string_lossy();
-                    let Some(ext) = path.extension().and_then(|s| s.to_str()) else {
-                        log::warn!("dropped file `{}` has no extension", path.display());
-                        self.toasts
-                            .add(Toast::info(format!("Skipping non-image `{filename}`")));
-                        continue;
-                    };
-                    if !crate::IMAGE_FORMATS.contains(&ext) {
-                        log::warn!(
-                            "dropped file `{}` has unsupported extension `{ext}`",
-                            path.display()
-                        );
-                        self.toasts
-                            .add(Toast::info(format!("Skipping non-image `{filename}`")));
-                        continue;
+        // the chat would be edited from both windows
+        if self.popped_out == Some(id) {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(ui.available_height() / 3.0);
+                    ui.label(RichText::new("This chat is open in another window").weak());
+                    if ui.button("Bring Back").clicked() {
+                        self.popped_out = None;
                     }
-                    chat.images.push(path.clone());
-                }
+                });
+            });
+            return;
+        }
+
+        self.show_chat(
+            ctx,
+            self.selected_chat,
+            ollama,
+            #[cfg(feature = "tts")]
+            stopped_talking,
+        );
+    }
+
+    /// Put the popped out chat back into the chat list and handle what it asked for while
+    /// it was shown in its own window
+    pub fn reclaim_popped_out(&mut self) {
+        let Some(popout) = self.popout.clone() else {
+            return;
+        };
+        let mut popout = popout.lock();
+        let Some((idx, chat)) = popout.chat.take() else {
+            return;
+        };
+        let idx = idx.min(self.chats.len());
+        let id = chat.id();
+        self.chats.insert(idx, chat);
+        self.settings.saved_prompts = std::mem::take(&mut popout.prompts);
+
+        let actions = std::mem::take(&mut popout.actions);
+        let export_confirmed = std::mem::take(&mut popout.export_confirmed);
+        let dropped_files = std::mem::take(&mut popout.dropped_files);
+        if popout.closed {
+            if popout.unsupported {
+                self.toasts
+                    .error("Opening chats in new windows isn't supported on this platform");
             }
+            self.popped_out = None;
+        }
+        drop(popout);
+
+        for action in actions {
+            self.handle_chat_action(idx, action);
+        }
+        if export_confirmed {
+            self.run_export(PendingExport::Chat(idx));
+        }
+        if !dropped_files.is_empty() {
+            self.attach_files(id, dropped_files);
+        }
+        if self.popped_out.is_none() {
+            self.popout = None;
+        }
+    }
+
+    /// Move the popped out chat to the state shared with its window until
+    /// [`Self::reclaim_popped_out`] is called
+    pub fn lend_popped_out(&mut self, ollama: &Ollama) {
+        let Some(id) = self.popped_out else {
+            self.popout = None;
+            return;
+        };
+        let Some(idx) = self.chats.iter().position(|c| c.id() == id) else {
+            self.popped_out = None;
+            self.popout = None;
+            return;
+        };
+        let mut chat = self.chats.remove(idx);
+        chat.has_unread = false;
+        apply_chat_settings(&self.settings, &mut chat);
+
+        let popout = self
+            .popout
+            .get_or_insert_with(|| Arc::new(Mutex::new(PopOut::new(ollama.clone()))));
+        let mut popout = popout.lock();
+        popout.chat = Some((idx, chat));
+        popout.ollama = ollama.clone();
+        #[cfg(feature = "tts")]
+        {
+            popout.tts = self.tts.clone();
+        }
+        popout.models.clone_from(&self.models);
+        popout.prompts = std::mem::take(&mut self.settings.saved_prompts);
+        popout.can_send = !self.connectivity.is_offline() || self.connectivity.send_anyway;
+        popout.max_height_fraction = self.settings.chatbox_max_height;
+        popout.locked = self.app_locked;
+        popout.encrypted = self.encryption_key.is_some();
+    }
+
+    /// Keep the window of the popped out chat open, it's painted by egui along with the
+    /// main window
+    fn show_popped_out_window(&self, ctx: &egui::Context) {
+        let Some(popout) = self.popout.clone() else {
+            return;
+        };
+        let (title, generating) = {
+            let popout = popout.lock();
+            let Some((_, chat)) = &popout.chat else {
+                return;
+            };
+            let title = if chat.summary.is_empty() {
+                "New Chat".to_owned()
+            } else {
+                chat.summary.clone()
+            };
+            (title, chat.flower_active())
+        };
+        let builder = egui::ViewportBuilder::default()
+            .with_title(format!("{title} - {}", crate::TITLE))
+            .with_inner_size(vec2(640.0, 720.0));
+
+        ctx.show_viewport_deferred(PopOut::viewport_id(), builder, move |ctx, class| {
+            popout.lock().show(ctx, class);
         });
+        // the response is polled by the main window, show it as it comes in
+        if generating {
+            ctx.request_repaint_of(PopOut::viewport_id());
+        }
+    }
+
+    /// Show a chat and its chatbox in the viewport of `ctx`
+    fn show_chat(
+        &mut self,
+        ctx: &egui::Context,
+        idx: usize,
+        ollama: &Ollama,
+        #[cfg(feature = "tts")] stopped_talking: bool,
+    ) {
+        let id = self.chats[idx].id();
+        let dropped_files: Vec<PathBuf> = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|f| f.path.clone())
+                .collect()
+        });
+        if !dropped_files.is_empty() {
+            self.attach_files(id, dropped_files);
+        }
+        let chat = &mut self.chats[idx];
+        chat.has_unread = false;
+        apply_chat_settings(&self.settings, chat);
 
         let action = chat.show(
             ctx,
@@ -468,8 +1882,17 @@ impl Sessions {
             #[cfg(feature = "tts")]
             stopped_talking,
             &mut self.commonmark_cache,
+            !self.connectivity.is_offline() || self.connectivity.send_anyway,
+            &self.models,
+            &mut self.settings.saved_prompts,
+            self.settings.chatbox_max_height,
         );
 
+        self.handle_chat_action(idx, action);
+    }
+
+    /// Handle an action of the chat at `idx`
+    fn handle_chat_action(&mut self, idx: usize, action: ChatAction) {
         match action {
             ChatAction::None => (),
             ChatAction::PickImages { id } => {
@@ -479,88 +1902,738 @@ impl Sessions {
                     pick_images(id, &handle).await;
                 });
             }
+            ChatAction::PickFiles { id } => {
+                let handle = self.flower.handle();
+                tokio::spawn(async move {
+                    handle.activate();
+                    pick_files(id, &handle).await;
+                });
+            }
+            ChatAction::PickImageFolder { id, recursive } => {
+                let handle = self.flower.handle();
+                tokio::spawn(async move {
+                    handle.activate();
+                    pick_image_folder(id, recursive, &handle).await;
+                });
+            }
+            ChatAction::Export => self.request_export(PendingExport::Chat(idx)),
+            ChatAction::Toast(toast) => {
+                self.toasts.add(toast);
+            }
         }
     }
 
-    fn show_remove_chat_modal_inner(&mut self, ui: &mut egui::Ui, modal: &Modal) {
-        modal.title(ui, "Remove Chat");
+    /// Show a toast about the chat with `id` in the window the chat is shown in
+    fn add_chat_toast(&mut self, id: usize, toast: Toast) {
+        add_chat_toast(
+            &mut self.toasts,
+            self.popout.as_ref(),
+            self.popped_out,
+            id,
+            toast,
+        );
+    }
+
+    /// Attach dropped or picked files to a chat: images are attached as images, text files
+    /// are read in the background and audio files are transcribed into the chatbox
+    fn attach_files(&mut self, id: usize, files: Vec<PathBuf>) {
+        let mut text_files = Vec::new();
+        let mut audio_files = Vec::new();
+        for path in files {
+            let filename = path.file_name().unwrap_or_default().to_string_lossy();
+            let ext = path
+                .extension()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default()
+                .to_lowercase();
+            let Some(chat) = self.chats.iter_mut().find(|c| c.id() == id) else {
+                return;
+            };
+            if crate::IMAGE_FORMATS.contains(&ext.as_str()) {
+                chat.images.push(path.clone());
+            } else if crate::TEXT_FORMATS.contains(&ext.as_str()) {
+                text_files.push(path.clone());
+            } else if crate::AUDIO_FORMATS.contains(&ext.as_str()) {
+                audio_files.push(path.clone());
+            } else {
+                log::warn!("file `{}` has unsupported extension", path.display());
+                add_chat_toast(
+                    &mut self.toasts,
+                    self.popout.as_ref(),
+                    self.popped_out,
+                    id,
+                    Toast::info(format!("Skipping unsupported file `{filename}`")),
+                );
+            }
+        }
+        if !text_files.is_empty() {
+            let handle = self.flower.handle();
+            let max_bytes = self.settings.max_text_file_kib * 1024;
+            tokio::spawn(async move {
+                handle.activate();
+                let files = text_files
+                    .iter()
+                    .map(|path| {
+                        TextFile::read(path, max_bytes).map_err(|e| {
+                            log::error!("failed to attach file: {e}");
+                            e.to_string()
+                        })
+                    })
+                    .collect();
+                handle.success(OllamaResponse::TextFiles { id, files });
+            });
+        }
+        if !audio_files.is_empty() {
+            self.transcribe_files(id, audio_files);
+        }
+    }
+
+    fn transcribe_files(&mut self, id: usize, files: Vec<PathBuf>) {
+        if self.settings.transcription == TranscriptionBackend::None {
+            self.add_chat_toast(
+                id,
+                Toast::error(
+                    "Audio files can't be attached without a transcription backend, \
+                    set one up in Settings",
+                ),
+            );
+            return;
+        }
+        if self.transcription_flower.is_active() {
+            self.add_chat_toast(
+                id,
+                Toast::info("Wait for the current transcription to finish"),
+            );
+            return;
+        }
+
+        self.add_chat_toast(
+            id,
+            Toast::info(format!("Transcribing {} audio file(s)…", files.len())),
+        );
+        let handle = self.transcription_flower.handle();
+        let backend = self.settings.transcription.clone();
+        tokio::spawn(async move {
+            handle.activate();
+            for path in files {
+                let file_name = path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned();
+                let transcript = transcribe(&backend, &path).await.map_err(|e| {
+                    log::error!("failed to transcribe `{}`: {e}", path.display());
+                    e.to_string()
+                });
+                handle.send((id, file_name, transcript));
+            }
+            handle.success(());
+        });
+    }
+
+    fn poll_transcription_flower(&mut self) {
+        self.transcription_flower
+            .extract(|(id, file_name, transcript)| match transcript {
+                Ok(transcript) => {
+                    if let Some(chat) = self.chats.iter_mut().find(|c| c.id() == id) {
+                        chat.insert_transcript(&file_name, &transcript);
+                    }
+                    add_chat_toast(
+                        &mut self.toasts,
+                        self.popout.as_ref(),
+                        self.popped_out,
+                        id,
+                        Toast::success(format!("Transcribed `{file_name}`")),
+                    );
+                }
+                Err(e) => {
+                    add_chat_toast(
+                        &mut self.toasts,
+                        self.popout.as_ref(),
+                        self.popped_out,
+                        id,
+                        Toast::error(format!("Failed to transcribe `{file_name}`: {e}")),
+                    );
+                }
+            })
+            .finalize(|result| {
+                if let Err(flowync::error::Compact::Panicked(e)) = result {
+                    log::error!("transcription task panicked: {e}");
+                    self.toasts
+                        .add(Toast::error(format!("Transcription task panicked: {e}")));
+                }
+            });
+    }
+
+    /// Ask where to save the chat and export it with the selected format
+    /// Exports are written as plain text, ask first if the chats are encrypted
+    fn request_export(&mut self, export: PendingExport) {
+        if self.encryption_key.is_some() {
+            self.pending_export = Some(export);
+        } else {
+            self.run_export(export);
+        }
+    }
+
+    fn run_export(&mut self, export: PendingExport) {
+        match export {
+            PendingExport::Chat(idx) => self.export_chat(idx),
+            PendingExport::AllChats => self.export_all_chats(),
+        }
+    }
+
+    fn show_plaintext_export_modal_inner(&mut self, ui: &mut egui::Ui, modal: &Modal) {
+        let Some(export) = self.pending_export else {
+            modal.close();
+            return;
+        };
+        modal.title(ui, "Export Encrypted Chats");
         modal.frame(ui, |ui| {
             modal.body_and_icon(
                 ui,
-                "Do you really want to remove this chat? \
-                You cannot undo this action later.\n\
-                Hold Shift to surpass this warning.",
+                "Your chats are encrypted, but exports are saved as plain text. \
+                Anyone who can open the exported file can read them.",
                 Icon::Warning,
             );
-            modal.buttons(ui, |ui| {
-                if modal.button(ui, "No").clicked() {
-                    modal.close();
+        });
+        modal.buttons(ui, |ui| {
+            if modal.button(ui, "Cancel").clicked() {
+                self.pending_export = None;
+            }
+            if modal.caution_button(ui, "Export Anyway").clicked() {
+                self.pending_export = None;
+                self.run_export(export);
+            }
+        });
+    }
+
+    fn export_chat(&self, chat_idx: usize) {
+        let Some(chat) = self.chats.get(chat_idx) else {
+            return;
+        };
+        let format = self.chat_export_format;
+        let task = rfd::AsyncFileDialog::new()
+            .add_filter(format!("{format:?} file"), format.extensions())
+            .save_file();
+        let messages = chat.messages.clone();
+        let timestamps = self.export_timestamps;
+        let handle = self.flower.handle();
+        tokio::spawn(async move {
+            let toast = crate::chat::export_messages(messages, format, timestamps, task)
+                .await
+                .map_err(|e| {
+                    log::error!("failed to export messages: {e}");
+                    e
+                });
+
+            handle.activate();
+            if let Ok(toast) = toast {
+                handle.success(OllamaResponse::Toast(toast))
+            } else if let Err(e) = toast {
+                handle.success(OllamaResponse::Toast(Toast::error(e.to_string())))
+            };
+        });
+    }
+
+    /// Export every chat with messages into a picked folder, one file per chat
+    fn export_all_chats(&mut self) {
+        let format = self.chat_export_format;
+        let timestamps = self.export_timestamps;
+        let chats: Vec<(String, Vec<Message>)> = self
+            .chats
+            .iter()
+            .filter(|c| !c.messages.is_empty())
+            .map(|c| (c.summary.clone(), c.messages.clone()))
+            .collect();
+        if chats.is_empty() {
+            self.toasts.add(Toast::info("No chats to export"));
+            return;
+        }
+        let handle = self.flower.handle();
+        tokio::spawn(async move {
+            handle.activate();
+            let Some(dir) = rfd::AsyncFileDialog::new().pick_folder().await else {
+                log::info!("export cancelled");
+                handle.success(OllamaResponse::Toast(Toast::info("Export cancelled")));
+                return;
+            };
+            let dir = dir.path().to_path_buf();
+            let total = chats.len();
+            log::info!("exporting {total} chats to {dir:?} (format: {format:?})...");
+
+            let result = tokio::task::spawn_blocking(move || {
+                let mut exported = 0;
+                for (i, (summary, messages)) in chats.iter().enumerate() {
+                    let path = dir.join(format!(
+                        "{} {}.{}",
+                        i + 1,
+                        sanitize_file_name(summary),
+                        format.extensions()[0]
+                    ));
+                    match crate::chat::write_messages(&path, messages, format, timestamps) {
+                        Ok(()) => exported += 1,
+                        Err(e) => log::error!("failed to export chat to {path:?}: {e}"),
+                    }
                 }
-                let summary = self
-                    .chats
-                    .get(self.chat_marked_for_deletion)
-                    .map(|c| {
-                        if c.summary.is_empty() {
-                            "New Chat"
-                        } else {
-                            c.summary.as_str()
-                        }
-                    })
-                    .unwrap_or("New Chat");
-                if modal
-                    .caution_button(ui, "Yes")
-                    .on_hover_text(format!("Remove chat \"{summary}\"",))
-                    .clicked()
-                {
-                    modal.close();
-                    self.remove_chat(self.chat_marked_for_deletion);
+                exported
+            })
+            .await;
+
+            let toast = match result {
+                Ok(exported) if exported == total => {
+                    Toast::success(format!("Exported {exported}/{total} chats"))
                 }
-            });
+                Ok(exported) => Toast::warning(format!(
+                    "Exported {exported}/{total} chats, see the log for errors"
+                )),
+                Err(e) => Toast::error(e.to_string()),
+            };
+            handle.success(OllamaResponse::Toast(toast));
         });
     }
 
-    fn show_chat_edit_panel(&mut self, ui: &mut egui::Ui, chat_idx: usize, ollama: &Ollama) {
-        ui.horizontal(|ui| {
-            let Some(chat) = self.chats.get(chat_idx) else {
-                return;
+    /// Ask what to do with the scratchpad before exiting if it has any messages
+    fn handle_close_request(&mut self, ctx: &egui::Context, modal: &Modal) {
+        if self.allow_close || !ctx.input(|i| i.viewport().close_requested()) {
+            return;
+        }
+        // the scratchpad isn't lost while the app keeps running in the tray
+        #[cfg(feature = "tray")]
+        if let Some(tray) = self
+            .tray
+            .as_ref()
+            .filter(|t| self.settings.minimize_to_tray && t.is_ready() && !t.quit_requested())
+        {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            tray.hide(ctx);
+            return;
+        }
+        let Some(idx) = self
+            .chats
+            .iter()
+            .position(|c| c.ephemeral && !c.messages.is_empty())
+        else {
+            return;
+        };
+        ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+        self.selected_chat = idx;
+        self.edited_chat = None;
+        self.settings_open = false;
+        modal.open();
+    }
+
+    /// Cancel the model warmup, if there is one
+    fn cancel_warmup(&mut self) {
+        if let Some(warmup) = self.warmup.take() {
+            log::info!("cancelling warmup of `{}`", warmup.model);
+            warmup.task.abort();
+        }
+    }
+
+    /// Warm up the model of the selected chat once it stays selected for [`WARMUP_DELAY`],
+    /// if prewarming is enabled. Warmups are canceled when a response for another model
+    /// starts generating
+    fn update_warmup(&mut self, ctx: &egui::Context, ollama: &Ollama) {
+        if self.warmup.as_ref().is_some_and(|w| w.task.is_finished()) {
+            self.warmup = None;
+        }
+        if !self.settings.prewarm_models || self.connectivity.is_offline() {
+            self.cancel_warmup();
+            self.warmup_candidate = None;
+            return;
+        }
+
+        // real requests go first, one for the same model waits for the warmup anyway
+        let generating: Vec<&str> = self
+            .chats
+            .iter()
+            .filter(|c| c.flower_active())
+            .map(|c| c.model_picker.selected_model())
+            .collect();
+        if !generating.is_empty() {
+            if self
+                .warmup
+                .as_ref()
+                .is_some_and(|w| !generating.contains(&w.model.as_str()))
+            {
+                self.cancel_warmup();
+            }
+            return;
+        }
+
+        let Some(chat) = self.chats.get(self.selected_chat) else {
+            return;
+        };
+        let model = chat.model_picker.selected_model();
+        let recently_warmed = self
+            .warmed_models
+            .get(model)
+            .is_some_and(|time| time.elapsed() < WARMUP_INTERVAL);
+        if model.is_empty() || recently_warmed {
+            return;
+        }
+        let selected_since = self
+            .warmup_candidate
+            .as_ref()
+            .filter(|(id, candidate, _)| *id == chat.id() && candidate == model)
+            .map(|(_, _, since)| *since);
+        let Some(since) = selected_since else {
+            self.warmup_candidate = Some((chat.id(), model.to_owned(), Instant::now()));
+            ctx.request_repaint_after(WARMUP_DELAY);
+            return;
+        };
+        if since.elapsed() < WARMUP_DELAY {
+            ctx.request_repaint_after(WARMUP_DELAY - since.elapsed());
+            return;
+        }
+
+        let model = model.to_owned();
+        let keep_alive = chat.model_picker.get_keep_alive();
+        self.cancel_warmup();
+        self.warmed_models.insert(model.clone(), Instant::now());
+        let task = tokio::spawn(warm_up_model(
+            ollama.clone(),
+            self.settings.endpoint_url(),
+            model.clone(),
+            keep_alive,
+        ));
+        self.warmup = Some(Warmup { model, task });
+    }
+
+    /// Refresh the model list and connectivity after the computer woke from sleep, the
+    /// connection and the model info are likely stale then
+    fn refresh_after_wake(&mut self, ollama: &Ollama) {
+        let now = (Instant::now(), SystemTime::now());
+        let Some((last_instant, last_time)) = self.last_frame.replace(now) else {
+            return;
+        };
+
+        // the monotonic clock doesn't advance while asleep, but the wall clock does. a long
+        // gap in both just means no frames were drawn for a while
+        let monotonic = now.0.duration_since(last_instant);
+        let wall = now.1.duration_since(last_time).unwrap_or_default();
+        if wall.saturating_sub(monotonic) < WAKE_GAP
+            || self
+                .last_wake_refresh
+                .is_some_and(|t| t.elapsed() < WAKE_REFRESH_INTERVAL)
+        {
+            return;
+        }
+
+        log::info!(
+            "woke from sleep after ~{}s, refreshing the model list",
+            wall.as_secs()
+        );
+        self.last_wake_refresh = Some(now.0);
+        self.running_models = None;
+        // the screen was most likely locked while asleep, lock the app too
+        if self.settings.auto_lock_minutes > 0 && self.can_lock_app() {
+            self.lock_app();
+        }
+        if !self.flower.is_active() {
+            self.list_models(ollama.clone());
+        }
+    }
+
+    /// Ask to accept the licenses of models the user picked, if enabled
+    fn check_model_licenses(&mut self, ollama: &Ollama) {
+        let mut picked = Vec::new();
+        if let Some(previous) = self.settings.model_picker.take_user_selection() {
+            picked.push((
+                None,
+                previous,
+                self.settings.model_picker.selected_model().to_owned(),
+            ));
+        }
+        for chat in self.chats.iter_mut() {
+            if let Some(previous) = chat.model_picker.take_user_selection() {
+                picked.push((
+                    Some(chat.id()),
+                    previous,
+                    chat.model_picker.selected_model().to_owned(),
+                ));
+            }
+        }
+        if !self.settings.license_prompt {
+            return;
+        }
+
+        for (chat_id, previous, model) in picked {
+            if model.is_empty() || self.settings.accepted_licenses.contains_key(&model) {
+                continue;
+            }
+            // picking another model in the same picker keeps what it had before the first one
+            let previous = match self.license_check.take() {
+                Some(check) if check.chat_id == chat_id => check.previous,
+                _ => previous,
             };
-            if chat.summary.is_empty() {
-                ui.heading("Editing Chat \"New Chat\"");
-            } else {
-                ui.heading(format!("Editing Chat \"{}\"", chat.summary));
+            log::info!("asking to accept the license of `{model}`");
+            if !self.pending_model_infos.contains_key(&model) {
+                self.request_model_info(model.clone(), ollama.clone());
             }
+            self.license_check = Some(LicenseCheck {
+                model,
+                chat_id,
+                previous,
+                license: None,
+            });
+        }
+    }
 
-            ui.with_layout(Layout::right_to_left(egui::Align::Min), |ui| {
-                if ui
-                    .add(
-                        egui::Button::new("❌")
-                            .fill(Color32::TRANSPARENT)
-                            .frame(false),
-                    )
-                    .on_hover_text("Close")
-                    .clicked()
-                {
-                    self.edited_chat = None;
-                }
+    /// Friendly description of an error, with the raw message under "Details" and a way to
+    /// pull the model if it's missing, retrying the completion that failed without it
+    fn show_error_modal_inner(&mut self, ui: &mut egui::Ui, modal: &Modal, ollama: &Ollama) {
+        let Some((title, error, chat_id)) = &self.error_dialog else {
+            modal.close();
+            return;
+        };
+        let missing_model = error.missing_model();
+        let chat_id = *chat_id;
+        modal.title(ui, title);
+        modal.frame(ui, |ui| {
+            ui.set_max_width(480.0);
+            ui.label(&*error.reason());
+            if let Some(suggestion) = error.suggestion() {
+                ui.add_space(4.0);
+                ui.weak(suggestion);
+            }
+            ui.add_space(4.0);
+            ui.collapsing("Details", |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(240.0)
+                    .show(ui, |ui| {
+                        ui.code_editor(&mut error.message());
+                    });
             });
         });
-
+        modal.buttons(ui, |ui| {
+            if modal.button(ui, "Close").clicked() {
+                self.error_dialog = None;
+            }
+            if let Some(name) = missing_model {
+                let pulling = self.is_pulling_model();
+                let button = ui.add_enabled_ui(!pulling, |ui| {
+                    if chat_id.is_some() {
+                        modal
+                            .suggested_button(ui, format!("Pull {name} and Retry"))
+                            .on_hover_text("Send the prompt again once the model is pulled")
+                    } else {
+                        modal.suggested_button(ui, format!("Pull {name}"))
+                    }
+                });
+                if button.inner.clicked() {
+                    self.pull_model(name, ollama.clone());
+                    self.retry_after_pull = chat_id;
+                    self.error_dialog = None;
+                }
+            }
+        });
+    }
+
+    fn show_license_modal_inner(&mut self, ui: &mut egui::Ui, modal: &Modal) {
+        let Some(check) = &self.license_check else {
+            modal.close();
+            return;
+        };
+        let license = check.license.clone().unwrap_or_default();
+        modal.title(ui, format!("License of {}", check.model));
+        modal.frame(ui, |ui| {
+            ui.label("Please read the license of this model before using it");
+            egui::ScrollArea::vertical()
+                .max_height(320.0)
+                .show(ui, |ui| {
+                    ui.code_editor(&mut license.as_str());
+                });
+            modal.buttons(ui, |ui| {
+                if modal
+                    .button(ui, "Cancel")
+                    .on_hover_text("Go back to the previously selected model")
+                    .clicked()
+                {
+                    modal.close();
+                    if let Some(check) = self.license_check.take() {
+                        log::info!("declined the license of `{}`", check.model);
+                        let picker = match check.chat_id {
+                            Some(id) => self
+                                .chats
+                                .iter_mut()
+                                .find(|c| c.id() == id)
+                                .map(|c| &mut c.model_picker),
+                            None => Some(&mut self.settings.model_picker),
+                        };
+                        if let Some(picker) = picker {
+                            picker.revert_selection(check.previous);
+                        }
+                    }
+                }
+                if modal.suggested_button(ui, "Accept").clicked() {
+                    modal.close();
+                    if let Some(check) = self.license_check.take() {
+                        log::info!("accepted the license of `{}`", check.model);
+                        self.settings
+                            .accepted_licenses
+                            .insert(check.model, check.license.unwrap_or_default());
+                    }
+                }
+            });
+        });
+    }
+
+    fn show_folder_images_modal_inner(&mut self, ui: &mut egui::Ui, modal: &Modal) {
+        let count = self
+            .pending_folder_images
+            .as_ref()
+            .map_or(0, |(_, files)| files.len());
+        modal.title(ui, "Attach Images");
+        modal.frame(ui, |ui| {
+            modal.body_and_icon(
+                ui,
+                format!("The folder contains {count} images. Do you want to attach all of them?"),
+                Icon::Info,
+            );
+            modal.buttons(ui, |ui| {
+                if modal.button(ui, "Cancel").clicked() {
+                    modal.close();
+                    self.pending_folder_images = None;
+                }
+                if modal
+                    .suggested_button(ui, format!("Attach {count} Images"))
+                    .clicked()
+                {
+                    modal.close();
+                    if let Some((id, files)) = self.pending_folder_images.take() {
+                        if let Some(chat) = self.chats.iter_mut().find(|c| c.id() == id) {
+                            chat.images.extend(files);
+                        }
+                    }
+                }
+            });
+        });
+    }
+
+    fn show_scratchpad_exit_modal_inner(&mut self, ui: &mut egui::Ui, modal: &Modal) {
+        modal.title(ui, "Discard Scratchpad?");
+        modal.frame(ui, |ui| {
+            modal.body_and_icon(
+                ui,
+                "The scratchpad is never saved and will be lost when you exit. \
+                Do you want to keep it as a regular chat?",
+                Icon::Warning,
+            );
+            modal.buttons(ui, |ui| {
+                if modal.button(ui, "Cancel").clicked() {
+                    modal.close();
+                }
+                if modal
+                    .caution_button(ui, "Discard")
+                    .on_hover_text("Exit without saving the scratchpad")
+                    .clicked()
+                {
+                    modal.close();
+                    self.allow_close = true;
+                    ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+                if modal
+                    .suggested_button(ui, "Keep as Chat")
+                    .on_hover_text("Convert the scratchpad to a regular chat and exit")
+                    .clicked()
+                {
+                    modal.close();
+                    for chat in self.chats.iter_mut() {
+                        chat.ephemeral = false;
+                    }
+                    self.allow_close = true;
+                    ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+            });
+        });
+    }
+
+    fn show_remove_chat_modal_inner(&mut self, ui: &mut egui::Ui, modal: &Modal) {
+        modal.title(ui, "Remove Chat");
+        modal.frame(ui, |ui| {
+            modal.body_and_icon(
+                ui,
+                "Do you really want to remove this chat? \
+                You can undo this for a few seconds after removing it.\n\
+                Hold Shift to surpass this warning.",
+                Icon::Warning,
+            );
+            modal.buttons(ui, |ui| {
+                if modal.button(ui, "No").clicked() {
+                    modal.close();
+                }
+                let summary = self
+                    .chats
+                    .get(self.chat_marked_for_deletion)
+                    .map(|c| {
+                        if c.summary.is_empty() {
+                            "New Chat"
+                        } else {
+                            c.summary.as_str()
+                        }
+                    })
+                    .unwrap_or("New Chat");
+                if modal
+                    .caution_button(ui, "Yes")
+                    .on_hover_text(format!("Remove chat \"{summary}\"",))
+                    .clicked()
+                {
+                    modal.close();
+                    self.remove_chat(self.chat_marked_for_deletion);
+                }
+            });
+        });
+    }
+
+    fn show_chat_edit_panel(&mut self, ui: &mut egui::Ui, chat_idx: usize, ollama: &Ollama) {
+        ui.horizontal(|ui| {
+            let Some(chat) = self.chats.get(chat_idx) else {
+                return;
+            };
+            if chat.ephemeral {
+                ui.heading("Editing Scratchpad");
+            } else if chat.summary.is_empty() {
+                ui.heading("Editing Chat \"New Chat\"");
+            } else {
+                ui.heading(format!("Editing Chat \"{}\"", chat.summary));
+            }
+
+            ui.with_layout(Layout::right_to_left(egui::Align::Min), |ui| {
+                if ui
+                    .add(
+                        egui::Button::new("❌")
+                            .fill(Color32::TRANSPARENT)
+                            .frame(false),
+                    )
+                    .on_hover_text("Close")
+                    .clicked()
+                {
+                    self.edited_chat = None;
+                }
+            });
+        });
+
         egui::CollapsingHeader::new("Model")
             .default_open(true)
             .show(ui, |ui| {
                 let mut request_info_for: Option<String> = None;
                 let is_loading_models = self.is_loading_models();
+                self.show_stale_models_note(ui, is_loading_models);
                 let Some(chat) = self.chats.get_mut(chat_idx) else {
                     return;
                 };
                 let mut list_models = false;
+                let mut pull_model: Option<String> = None;
                 chat.model_picker.show(
                     ui,
-                    if is_loading_models {
+                    if is_loading_models && self.models.is_empty() {
                         None
                     } else {
                         Some(&self.models)
                     },
+                    &mut self.settings.model_shortlist,
+                    &mut self.settings.model_presets,
                     &mut |typ| match typ {
                         RequestInfoType::ModelInfo(name) => {
                             if !self.pending_model_infos.contains_key(name) {
@@ -571,172 +2644,1545 @@ impl Sessions {
                             list_models = true;
                         }
                         RequestInfoType::LoadSettings => (), // can't be called from here
+                        RequestInfoType::LoadModel { name, keep_alive } => {
+                            Self::spawn_load_model(&self.flower, ollama, name, keep_alive);
+                        }
+                        RequestInfoType::PullModel(name) => {
+                            pull_model = Some(name.to_string());
+                        }
+                        // can't be called from here
+                        RequestInfoType::RunningModels
+                        | RequestInfoType::UnloadModel(_)
+                        | RequestInfoType::ExportAllChats => (),
                     },
                 );
-                if let Some(name) = request_info_for {
-                    if self.settings.inherit_chat_picker
-                        && (name != self.settings.model_picker.selected_model())
+                if let Some(name) = request_info_for {
+                    if self.settings.inherit_chat_picker
+                        && (name != self.settings.model_picker.selected_model())
+                    {
+                        self.settings
+                            .model_picker
+                            .set_selected(chat.model_picker.selected.clone());
+                        self.settings.model_picker.selection_source =
+                            chat.model_picker.selection_source;
+                    }
+
+                    self.request_model_info(name, ollama.clone());
+                }
+                if let Some(name) = pull_model {
+                    if !self.is_pulling_model() {
+                        self.pull_model(name, ollama.clone());
+                    }
+                } else if list_models {
+                    self.list_models(ollama.clone());
+                }
+            });
+        ui.collapsing("System Prompt", |ui| {
+            let Some(chat) = self.chats.get_mut(chat_idx) else {
+                return;
+            };
+            ui.label(
+                "Instructions sent to the model before the chat history, also set with /system",
+            );
+            ui.add(
+                egui::TextEdit::multiline(&mut chat.system_prompt)
+                    .hint_text("You are a helpful assistant.")
+                    .desired_width(f32::INFINITY),
+            );
+        });
+        ui.collapsing("Context", |ui| {
+            let Some(chat) = self.chats.get_mut(chat_idx) else {
+                return;
+            };
+            ui.label("Which messages are sent to the model when the chat gets long");
+            egui::ComboBox::from_id_source("context_strategy_combobox")
+                .selected_text(chat.context_strategy.name())
+                .show_ui(ui, |ui| {
+                    for strategy in [
+                        ContextStrategy::KeepAll,
+                        ContextStrategy::DropOldest,
+                        ContextStrategy::SlidingWindow(20),
+                    ] {
+                        let selected = std::mem::discriminant(&chat.context_strategy)
+                            == std::mem::discriminant(&strategy);
+                        if ui.selectable_label(selected, strategy.name()).clicked() && !selected {
+                            chat.context_strategy = strategy;
+                        }
+                    }
+                });
+            if let ContextStrategy::SlidingWindow(ref mut n) = chat.context_strategy {
+                ui.horizontal(|ui| {
+                    ui.label("Messages");
+                    ui.add(egui::DragValue::new(n).clamp_range(2..=1000));
+                });
+            }
+            ui.add_space(4.0);
+            chat.show_context_budget(ui);
+            ui.add_space(4.0);
+            ui.checkbox(&mut chat.raw_mode, "Raw mode").on_hover_text(
+                "Send the chatbox as is to the generate endpoint, without the chat history, \
+                system prompt or the model's template. Useful for base models and prompts \
+                that are already formatted.",
+            );
+        });
+        #[cfg(feature = "tts")]
+        ui.collapsing("Text-to-Speech", |ui| {
+            let Some(chat) = self.chats.get_mut(chat_idx) else {
+                return;
+            };
+            let global = if self.settings.tts.auto_read {
+                "Use global setting (on)"
+            } else {
+                "Use global setting (off)"
+            };
+            egui::ComboBox::from_label("Read responses aloud")
+                .selected_text(match chat.auto_read {
+                    None => global,
+                    Some(true) => "Always",
+                    Some(false) => "Never",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut chat.auto_read, None, global);
+                    ui.selectable_value(&mut chat.auto_read, Some(true), "Always");
+                    ui.selectable_value(&mut chat.auto_read, Some(false), "Never");
+                });
+        });
+        ui.collapsing("Export", |ui| {
+            ui.label("Export chat history to a file");
+            let format = self.chat_export_format;
+            egui::ComboBox::from_label("Export Format")
+                .selected_text(format.to_string())
+                .show_ui(ui, |ui| {
+                    for format in ChatExportFormat::ALL {
+                        ui.selectable_value(
+                            &mut self.chat_export_format,
+                            format,
+                            format.to_string(),
+                        );
+                    }
+                });
+            ui.checkbox(&mut self.export_timestamps, "Include timestamps");
+            ui.horizontal(|ui| {
+                if ui.button("Save As…").clicked() {
+                    self.request_export(PendingExport::Chat(chat_idx));
+                }
+                if ui
+                    .button("🗐 Copy conversation")
+                    .on_hover_text("Copy all messages to the clipboard as Markdown")
+                    .clicked()
+                {
+                    if let Some(chat) = self.chats.get(chat_idx) {
+                        ui.ctx().copy_text(crate::chat::messages_to_markdown(
+                            &chat.messages,
+                            self.export_timestamps,
+                        ));
+                        self.toasts.add(Toast::success(format!(
+                            "Copied {} messages",
+                            chat.messages.len()
+                        )));
+                    }
+                }
+            });
+        });
+    }
+
+    /// Handle the global shortcuts for creating, closing and switching chats
+    fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+        // don't act behind dialogs and menus
+        if ctx.memory(|m| m.any_popup_open()) || Modal::new(ctx, "remove_chat_modal").is_open() {
+            return;
+        }
+
+        // Escape closes the image viewer and cancels editing otherwise
+        let can_stop = self
+            .chats
+            .get(self.selected_chat)
+            .is_some_and(Chat::flower_active)
+            && !crate::image::is_viewer_open(ctx);
+        let (new, close, next, prev, stop, help) = ctx.input_mut(|i| {
+            // check the shift variant first, Ctrl+Tab would also match Ctrl+Shift+Tab
+            let prev = shortcuts::PREV_CHAT.iter().any(|s| i.consume_shortcut(s));
+            (
+                i.consume_shortcut(&shortcuts::NEW_CHAT),
+                i.consume_shortcut(&shortcuts::CLOSE_CHAT),
+                shortcuts::NEXT_CHAT.iter().any(|s| i.consume_shortcut(s)),
+                prev,
+                can_stop && i.consume_shortcut(&shortcuts::STOP),
+                i.consume_shortcut(&shortcuts::HELP),
+            )
+        });
+
+        if help {
+            self.shortcuts_open = !self.shortcuts_open;
+        }
+        if stop {
+            self.chats[self.selected_chat].stop_generating();
+        }
+
+        if new {
+            self.add_default_chat();
+            self.selected_chat = self.chats.len() - 1;
+            self.edited_chat = None;
+            self.settings_open = false;
+        }
+        if close && self.selected_chat < self.chats.len() {
+            if self.chats[self.selected_chat].messages.is_empty() {
+                self.remove_chat(self.selected_chat);
+            } else {
+                self.chat_marked_for_deletion = self.selected_chat;
+                self.edited_chat = None;
+                Modal::new(ctx, "remove_chat_modal").open();
+            }
+        }
+        let len = self.chats.len();
+        if next && len > 0 {
+            self.selected_chat = (self.selected_chat + 1) % len;
+            self.settings_open = false;
+        }
+        if prev && len > 0 {
+            self.selected_chat = (self.selected_chat + len - 1) % len;
+            self.settings_open = false;
+        }
+    }
+
+    fn show_left_panel(&mut self, ui: &mut egui::Ui) {
+        ui.add_space(ui.style().spacing.window_margin.top);
+        ui.horizontal(|ui| {
+            let ctx = ui.ctx().clone();
+            ui.selectable_value(&mut self.tab, SessionTab::Chats, "Chats")
+                .on_hover_text(format!(
+                    "Shortcuts:\n{}\n\n{}",
+                    shortcuts::list(&ctx, shortcuts::Area::Chats, self.settings.send_key),
+                    shortcuts::hint(&ctx, "All shortcuts", &shortcuts::HELP),
+                ));
+            ui.selectable_value(&mut self.tab, SessionTab::Stats, "Stats")
+                .on_hover_text("How the responses of each model were rated");
+            ui.with_layout(Layout::right_to_left(egui::Align::Max), |ui| {
+                ui.toggle_value(&mut self.settings_open, "⚙")
+                    .on_hover_text("Settings");
+            });
+        });
+
+        ui.add_space(8.0);
+
+        match self.tab {
+            SessionTab::Chats => {
+                let modal = Modal::new(ui.ctx(), "remove_chat_modal");
+                self.show_chats(ui, &modal);
+                modal.show(|ui| {
+                    self.show_remove_chat_modal_inner(ui, &modal);
+                });
+            }
+            SessionTab::Stats => crate::stats::show(ui, &self.chats),
+        }
+    }
+
+    #[inline]
+    pub fn model_picker(&self) -> &ModelPicker {
+        &self.settings.model_picker
+    }
+
+    fn poll_ollama_flower(&mut self, ctx: &egui::Context, ollama: &Ollama) {
+        let mut attach_files = None;
+        let mut restored_state = None;
+        let mut retry_chat = None;
+        let mut server_version = None;
+        self.flower.extract(|()| ()).finalize(|resp| {
+            let was_listing_models = self.flower_activity == OllamaFlowerActivity::ListModels;
+            let was_pulling_model = self.flower_activity == OllamaFlowerActivity::PullModel;
+            if self.flower_activity == OllamaFlowerActivity::RunningModels && resp.is_err() {
+                // don't request them again every frame
+                self.running_models = Some(Vec::new());
+            }
+            self.flower_activity = OllamaFlowerActivity::Idle;
+            match resp {
+                Ok(OllamaResponse::Ignore) => (),
+                Ok(OllamaResponse::Models(models)) => {
+                    if self.connectivity.mark_online() {
+                        log::info!("reconnected to the ollama server");
+                        self.toasts.add(Toast::success("Reconnected to Ollama"));
+                    }
+                    if was_pulling_model {
+                        self.toasts.add(Toast::success("Model pulled"));
+                        retry_chat = self.retry_after_pull.take();
+                    }
+                    // forget the info of models that were removed or changed
+                    self.model_info_cache.retain(|name, cached| {
+                        models
+                            .iter()
+                            .any(|m| &m.name == name && m.modified_at == cached.modified_at)
+                    });
+                    self.models = models;
+                    self.models_stale = false;
+                    self.settings.model_picker.select_best_model(&self.models);
+
+                    // for each chat with unselected models, select the best model
+                    for chat in self.chats.iter_mut() {
+                        if !chat.model_picker.has_selection() {
+                            chat.model_picker
+                                .set_selected(self.settings.model_picker.selected.clone());
+                            chat.model_picker.selection_source = SelectionSource::Auto;
+                        }
+                    }
+                }
+                Ok(OllamaResponse::ModelInfo {
+                    name,
+                    info,
+                    details,
+                }) => {
+                    self.pending_model_infos.remove(&name);
+                    if let Some(model) = self.models.iter().find(|m| m.name == name) {
+                        self.model_info_cache.insert(
+                            name.clone(),
+                            CachedModelInfo {
+                                modified_at: model.modified_at.clone(),
+                                info: info.clone(),
+                                details: details.clone(),
+                            },
+                        );
+                    }
+                    if let Some(check) = self
+                        .license_check
+                        .as_mut()
+                        .filter(|c| c.model == name && c.license.is_none())
+                    {
+                        if info.license.trim().is_empty() {
+                            // nothing to accept
+                            self.license_check = None;
+                        } else {
+                            check.license = Some(info.license.clone());
+                        }
+                    }
+                    self.settings
+                        .model_picker
+                        .on_new_model_info(&name, &info, details.as_ref());
+                    for chat in self.chats.iter_mut() {
+                        chat.model_picker
+                            .on_new_model_info(&name, &info, details.as_ref());
+                    }
+                }
+                Ok(OllamaResponse::Toast(toast)) => {
+                    self.toasts.add(toast);
+                }
+                Ok(OllamaResponse::Images { id, files }) => {
+                    if let Some(chat) = self.chats.iter_mut().find(|c| c.id() == id) {
+                        log::debug!("adding {} image(s)", files.len());
+                        chat.images.extend(files);
+                    }
+                }
+                Ok(OllamaResponse::ImageFolder { id, files, skipped }) => {
+                    if skipped > 0 {
+                        self.toasts.add(Toast::info(format!(
+                            "Skipped {skipped} file(s) that aren't supported images"
+                        )));
+                    }
+                    if files.is_empty() {
+                        self.toasts
+                            .add(Toast::warning("No images found in the folder"));
+                    } else if files.len() > MAX_UNCONFIRMED_FOLDER_IMAGES {
+                        self.pending_folder_images = Some((id, files));
+                        self.open_folder_images_modal = true;
+                    } else if let Some(chat) = self.chats.iter_mut().find(|c| c.id() == id) {
+                        chat.images.extend(files);
+                    }
+                }
+                Ok(OllamaResponse::Files { id, files }) => {
+                    log::debug!("attaching {} file(s)", files.len());
+                    attach_files = Some((id, files));
+                }
+                Ok(OllamaResponse::TextFiles { id, files }) => {
+                    let chat = self.chats.iter_mut().find(|c| c.id() == id);
+                    for file in files {
+                        match file {
+                            Ok(file) => {
+                                if file.truncated {
+                                    log::warn!("attached file `{}` was truncated", file.name);
+                                    add_chat_toast(
+                                        &mut self.toasts,
+                                        self.popout.as_ref(),
+                                        self.popped_out,
+                                        id,
+                                        Toast::warning(format!(
+                                            "`{}` is too large, only the first {} KiB are \
+                                            attached",
+                                            file.name, self.settings.max_text_file_kib
+                                        )),
+                                    );
+                                } else {
+                                    log::info!("attached file `{}`", file.name);
+                                }
+                                if let Some(chat) = chat.as_deref_mut() {
+                                    chat.text_files.push(file);
+                                }
+                            }
+                            Err(e) => {
+                                add_chat_toast(
+                                    &mut self.toasts,
+                                    self.popout.as_ref(),
+                                    self.popped_out,
+                                    id,
+                                    Toast::error(e),
+                                );
+                            }
+                        }
+                    }
+                }
+                Ok(OllamaResponse::Settings(settings)) => {
+                    self.settings = *settings;
+                    #[cfg(feature = "tts")]
+                    self.apply_tts_settings();
+                }
+                Ok(OllamaResponse::RunningModels(models)) => {
+                    self.running_models = Some(models);
+                }
+                Ok(OllamaResponse::RestoredState(state)) => {
+                    restored_state = Some(state);
+                }
+                Ok(OllamaResponse::ServerVersion(version)) => {
+                    server_version = Some(version);
+                }
+                Err(flowync::error::Compact::Suppose(_))
+                    if was_listing_models && self.connectivity.is_offline() =>
+                {
+                    // still unreachable, the banner already says so
+                    self.connectivity.mark_offline();
+                }
+                Err(e) => {
+                    let e = match e {
+                        flowync::error::Compact::Suppose(e) => e,
+                        flowync::error::Compact::Panicked(e) => {
+                            log::error!("task panicked: {e}");
+                            AppError::Panicked(format!("Task panicked: {e}"))
+                        }
+                    };
+                    if was_listing_models {
+                        self.connectivity.mark_offline();
+                    }
+                    if was_pulling_model {
+                        self.retry_after_pull = None;
+                    }
+                    self.error_dialog = Some((e.title().to_owned(), e, None));
+                }
+            };
+        });
+        if let Some((id, files)) = attach_files {
+            self.attach_files(id, files);
+        }
+        if let Some(state) = restored_state {
+            self.restore_state(*state, ollama);
+        }
+        if let Some(version) = server_version {
+            let report = crate::diagnostics::report(
+                &self.settings,
+                &self.chats,
+                version.as_deref(),
+                ctx.zoom_factor(),
+            );
+            ctx.copy_text(report.clone());
+            self.toasts
+                .add(Toast::success("Diagnostic info copied to the clipboard"));
+            self.diagnostics = Some(report);
+        }
+        if let Some(id) = retry_chat {
+            if let Some(chat) = self.chats.iter_mut().find(|c| c.id() == id) {
+                chat.retry_failed(ollama, &self.models);
+            }
+        }
+    }
+
+    /// Save the whole app state to a picked file. Chats are encrypted if encryption is enabled.
+    fn backup(&mut self) {
+        let chats = self.encrypt_chats();
+        let json = serde_json::to_string_pretty(self);
+        self.restore_chats(chats);
+        let json = match json {
+            Ok(json) => json,
+            Err(e) => {
+                log::error!("failed to serialize app state: {e}");
+                self.toasts.add(Toast::error(e.to_string()));
+                return;
+            }
+        };
+
+        let handle = self.flower.handle();
+        tokio::spawn(async move {
+            handle.activate();
+            let Some(file) = rfd::AsyncFileDialog::new()
+                .add_filter("Ellama backup", &["json"])
+                .set_file_name("backup.ellama.json")
+                .save_file()
+                .await
+            else {
+                log::info!("backup cancelled");
+                handle.success(OllamaResponse::Ignore);
+                return;
+            };
+            let toast = match tokio::fs::write(file.path(), json).await {
+                Ok(()) => {
+                    log::info!("saved backup to {:?}", file.path());
+                    Toast::success(format!("Saved backup to {}", file.file_name()))
+                }
+                Err(e) => {
+                    log::error!("failed to save backup: {e}");
+                    Toast::error(format!("Failed to save backup: {e}"))
+                }
+            };
+            handle.success(OllamaResponse::Toast(toast));
+        });
+    }
+
+    /// Load the app state from a picked backup file, replacing the current chats and settings
+    fn restore_backup(&self) {
+        let handle = self.flower.handle();
+        tokio::spawn(async move {
+            handle.activate();
+            let Some(file) = rfd::AsyncFileDialog::new()
+                .add_filter("Ellama backup", &["json"])
+                .pick_file()
+                .await
+            else {
+                log::info!("restore cancelled");
+                handle.success(OllamaResponse::Ignore);
+                return;
+            };
+            let state = tokio::fs::read(file.path())
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(serde_json::from_slice::<serde_json::Value>(&data)?));
+            match state {
+                Ok(state) => handle.success(OllamaResponse::RestoredState(Box::new(state))),
+                Err(e) => {
+                    log::error!("failed to read backup: {e}");
+                    handle.success(OllamaResponse::Toast(Toast::error(format!(
+                        "Failed to read backup: {e}"
+                    ))));
+                }
+            }
+        });
+    }
+
+    /// Mark the responses that were generating when the state was saved as interrupted
+    pub fn mark_interrupted(&mut self) {
+        for chat in &mut self.chats {
+            chat.mark_interrupted();
+        }
+    }
+
+    fn restore_state(&mut self, state: serde_json::Value, ollama: &Ollama) {
+        let state: Self = match serde_json::from_value(state) {
+            Ok(state) => state,
+            Err(e) => {
+                log::error!("failed to restore backup: {e}");
+                self.toasts
+                    .add(Toast::error(format!("Not a valid backup: {e}")));
+                return;
+            }
+        };
+        for chat in &self.chats {
+            chat.stop_generating();
+        }
+
+        self.tab = state.tab;
+        self.chats = state.chats;
+        self.selected_chat = state.selected_chat;
+        self.edited_chat = None;
+        self.chat_export_format = state.chat_export_format;
+        self.export_timestamps = state.export_timestamps;
+        self.settings = state.settings;
+        self.sidebar_width = state.sidebar_width;
+        // encrypted backups ask for the passphrase again
+        self.encrypted_chats = state.encrypted_chats;
+        self.encryption_key = None;
+        self.decryption = None;
+        if !self.is_locked() && self.chats.is_empty() {
+            self.add_default_chat();
+        }
+        self.selected_chat = self.selected_chat.min(self.chats.len().saturating_sub(1));
+
+        // rebuild what isn't serialized
+        self.assign_chat_ids();
+        self.mark_interrupted();
+        self.virtual_list = Rc::new(RefCell::new(VirtualList::new()));
+        self.commonmark_cache = CommonMarkCache::default();
+        self.pending_model_infos.clear();
+        self.data_size = None;
+        #[cfg(feature = "tts")]
+        self.apply_tts_settings();
+
+        log::info!("restored app state from backup");
+        self.toasts.add(Toast::success("Backup restored"));
+        self.list_models(ollama.clone());
+    }
+
+    fn show_offline_banner(&mut self, ctx: &egui::Context, ollama: &Ollama) {
+        let now = Instant::now();
+        let next_ping = self.connectivity.next_ping.unwrap_or(now);
+        if now >= next_ping && !self.flower.is_active() {
+            log::debug!("pinging ollama server...");
+            self.connectivity.next_ping = Some(now + RECONNECT_INTERVAL);
+            self.list_models(ollama.clone());
+        }
+
+        let is_loading = self.is_loading_models();
+        let host = self.settings.endpoint_host();
+        egui::TopBottomPanel::top("offline_banner").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let text = if is_loading {
+                    format!("⚠ Ollama unreachable at {host} — retrying…")
+                } else {
+                    format!(
+                        "⚠ Ollama unreachable at {host} — retrying in {}s",
+                        next_ping.saturating_duration_since(now).as_secs() + 1
+                    )
+                };
+                ui.label(RichText::new(text).color(ui.visuals().warn_fg_color));
+                if ui
+                    .add_enabled(!is_loading, egui::Button::new("Retry now"))
+                    .clicked()
+                {
+                    self.connectivity.next_ping = Some(now);
+                }
+                ui.checkbox(&mut self.connectivity.send_anyway, "Send anyway")
+                    .on_hover_text("Allow sending messages while the server is unreachable");
+            });
+        });
+
+        // update the countdown
+        ctx.request_repaint_after(Duration::from_secs(1));
+    }
+
+    /// Apply the voice, rate and pitch from the settings to the TTS instance
+    #[cfg(feature = "tts")]
+    fn apply_tts_settings(&self) {
+        let Some(tts) = &self.tts else {
+            return;
+        };
+        let mut tts = tts.write();
+        let features = tts.supported_features();
+        let settings = &self.settings.tts;
+
+        if features.voice {
+            let voice = settings.voice.as_ref().and_then(|name| {
+                tts.voices()
+                    .map_err(|e| log::error!("failed to list TTS voices: {e}"))
+                    .ok()?
+                    .into_iter()
+                    .find(|v| &v.name() == name)
+            });
+            if let Some(voice) = voice {
+                let _ = tts
+                    .set_voice(&voice)
+                    .map_err(|e| log::error!("failed to set TTS voice: {e}"));
+            } else if let Some(name) = &settings.voice {
+                log::warn!("TTS voice `{name}` is not available, using the default voice");
+            }
+        }
+        if features.rate {
+            let rate = settings.rate.unwrap_or_else(|| tts.normal_rate());
+            let _ = tts
+                .set_rate(rate)
+                .map_err(|e| log::error!("failed to set TTS rate: {e}"));
+        }
+        if features.pitch {
+            let pitch = settings.pitch.unwrap_or_else(|| tts.normal_pitch());
+            let _ = tts
+                .set_pitch(pitch)
+                .map_err(|e| log::error!("failed to set TTS pitch: {e}"));
+        }
+    }
+
+    #[cfg(feature = "tts")]
+    fn show_tts_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Text-to-Speech");
+        let Some(tts) = self.tts.clone() else {
+            ui.label("Text-to-speech is not available on this system");
+            return;
+        };
+        let tts = tts.read();
+        let features = tts.supported_features();
+        let voices = self.tts_voices.get_or_insert_with(|| {
+            if !features.voice {
+                return Vec::new();
+            }
+            tts.voices()
+                .map(|voices| voices.iter().map(|v| v.name()).collect())
+                .unwrap_or_else(|e| {
+                    log::error!("failed to list TTS voices: {e}");
+                    Vec::new()
+                })
+        });
+        let settings = &mut self.settings.tts;
+        let mut changed = false;
+
+        egui::Grid::new("tts_settings_grid")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Voice");
+                ui.add_enabled_ui(!voices.is_empty(), |ui| {
+                    egui::ComboBox::from_id_source("tts_voice_combobox")
+                        .selected_text(settings.voice.as_deref().unwrap_or("Default"))
+                        .show_ui(ui, |ui| {
+                            changed |= ui
+                                .selectable_value(&mut settings.voice, None, "Default")
+                                .changed();
+                            for voice in voices.iter() {
+                                changed |= ui
+                                    .selectable_value(
+                                        &mut settings.voice,
+                                        Some(voice.clone()),
+                                        voice,
+                                    )
+                                    .changed();
+                            }
+                        });
+                })
+                .response
+                .on_disabled_hover_text("Voice selection is not supported on this platform");
+                ui.end_row();
+
+                ui.label("Rate");
+                let mut rate = settings.rate.unwrap_or_else(|| tts.normal_rate());
+                if ui
+                    .add_enabled(
+                        features.rate,
+                        egui::Slider::new(&mut rate, tts.min_rate()..=tts.max_rate()),
+                    )
+                    .on_disabled_hover_text("Changing the rate is not supported on this platform")
+                    .changed()
+                {
+                    settings.rate = Some(rate);
+                    changed = true;
+                }
+                ui.end_row();
+
+                ui.label("Pitch");
+                let mut pitch = settings.pitch.unwrap_or_else(|| tts.normal_pitch());
+                if ui
+                    .add_enabled(
+                        features.pitch,
+                        egui::Slider::new(&mut pitch, tts.min_pitch()..=tts.max_pitch()),
+                    )
+                    .on_disabled_hover_text("Changing the pitch is not supported on this platform")
+                    .changed()
+                {
+                    settings.pitch = Some(pitch);
+                    changed = true;
+                }
+                ui.end_row();
+            });
+
+        ui.horizontal(|ui| {
+            ui.add(widgets::toggle(&mut settings.auto_read));
+            ui.label("Read responses aloud when they finish generating");
+        });
+        ui.add_enabled_ui(settings.auto_read, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(widgets::toggle(&mut settings.read_while_generating));
+                ui.label("Start reading while the response is being generated");
+            });
+        });
+
+        if ui.button("Reset").clicked() {
+            *settings = Default::default();
+            changed = true;
+        }
+
+        drop(tts);
+        if changed {
+            self.apply_tts_settings();
+        }
+    }
+
+    /// Whether the chats are encrypted and the passphrase wasn't entered yet
+    #[inline]
+    fn is_locked(&self) -> bool {
+        self.encrypted_chats.is_some() && self.encryption_key.is_none()
+    }
+
+    /// If encryption is enabled, encrypt the chats and take them out so they aren't saved in
+    /// plaintext. They must be put back with [`Self::restore_chats`] after saving.
+    pub fn encrypt_chats(&mut self) -> Option<Vec<Chat>> {
+        if self.is_locked() {
+            // still encrypted from the last run, keep them as they are
+            return None;
+        }
+        let Some(key) = &self.encryption_key else {
+            self.encrypted_chats = None;
+            return None;
+        };
+        let chats: Vec<&Chat> = self.chats.iter().filter(|c| !c.ephemeral).collect();
+        match serde_json::to_vec(&chats)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| key.encrypt(&json))
+        {
+            Ok(data) => self.encrypted_chats = Some(data),
+            // never save the chats in plaintext, keep the previously encrypted chats
+            Err(e) => log::error!("failed to encrypt chats: {e}"),
+        }
+        Some(std::mem::take(&mut self.chats))
+    }
+
+    pub fn restore_chats(&mut self, chats: Option<Vec<Chat>>) {
+        if let Some(chats) = chats {
+            self.chats = chats;
+        }
+    }
+
+    /// Decrypt the chats with the entered passphrase on another thread, deriving the key is
+    /// slow on purpose. See [`Self::poll_decryption`].
+    fn unlock(&mut self) {
+        if self.decryption.is_some() || self.unlock_delayed() {
+            return;
+        }
+        let Some(data) = self.encrypted_chats.clone() else {
+            return;
+        };
+        let passphrase = std::mem::take(&mut self.passphrase_buf);
+        self.decryption = Some(std::thread::spawn(move || data.decrypt(&passphrase)));
+    }
+
+    /// Put the decrypted chats in place once the passphrase was checked
+    fn poll_decryption(&mut self, ctx: &egui::Context) {
+        self.repaint_unlock_countdown(ctx);
+        if !self
+            .decryption
+            .as_ref()
+            .is_some_and(std::thread::JoinHandle::is_finished)
+        {
+            if self.decryption.is_some() {
+                ctx.request_repaint_after(Duration::from_millis(50));
+            }
+            return;
+        }
+        let Some(decryption) = self.decryption.take() else {
+            return;
+        };
+        let decrypted = decryption
+            .join()
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("decrypting the chats panicked")))
+            .and_then(|(key, json)| Ok((key, serde_json::from_slice::<Vec<Chat>>(&json)?)));
+        match decrypted {
+            Ok((key, chats)) => {
+                log::info!("decrypted {} chats", chats.len());
+                self.chats = chats;
+                self.assign_chat_ids();
+                self.mark_interrupted();
+                self.encryption_key = Some(key);
+                self.passphrase_error.clear();
+                self.failed_unlocks = 0;
+                self.unlock_retry_at = None;
+                if self.chats.is_empty() {
+                    self.add_default_chat();
+                }
+                self.selected_chat = self.selected_chat.min(self.chats.len() - 1);
+            }
+            Err(e) => {
+                log::warn!("failed to decrypt chats: {e}");
+                self.delay_unlock();
+                self.passphrase_error = e.to_string();
+            }
+        }
+    }
+
+    fn show_unlock_screen(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            widgets::centerer(ui, |ui| {
+                ui.heading("🔒 Chats are encrypted");
+                ui.label("Enter your passphrase to unlock them");
+                let resp = ui.add(
+                    egui::TextEdit::singleline(&mut self.passphrase_buf)
+                        .password(true)
+                        .hint_text("Passphrase"),
+                );
+                let submitted = resp.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                let decrypting = self.decryption.is_some();
+                let can_unlock = !decrypting && !self.unlock_delayed();
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(can_unlock, egui::Button::new("Unlock"))
+                        .clicked()
+                        || (submitted && can_unlock)
+                    {
+                        self.unlock();
+                    }
+                    if decrypting {
+                        ui.spinner();
+                    } else {
+                        self.show_unlock_retry(ui);
+                    }
+                });
+                if !self.passphrase_error.is_empty() {
+                    ui.label(
+                        RichText::new(&self.passphrase_error).color(ui.visuals().error_fg_color),
+                    );
+                }
+            });
+        });
+    }
+
+    /// Called after the app state was saved to disk
+    pub fn on_saved(&mut self) {
+        self.last_saved = Some(chrono::Local::now());
+    }
+
+    fn show_diagnostics_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Diagnostics");
+        ui.label(
+            "Versions, names of the changed settings, chat counts and recent logs for bug \
+            reports. Setting values and messages aren't included.",
+        );
+        if ui.button("Copy Diagnostic Info").clicked() {
+            let handle = self.flower.handle();
+            let url = self.settings.endpoint_url();
+            tokio::spawn(async move {
+                handle.activate();
+                request_server_version(url, &handle).await;
+            });
+        }
+    }
+
+    fn show_diagnostics_modal_inner(&mut self, ui: &mut egui::Ui, modal: &Modal) {
+        let Some(report) = &self.diagnostics else {
+            modal.close();
+            return;
+        };
+        modal.title(ui, "Diagnostic Info");
+        modal.frame(ui, |ui| {
+            ui.label("Copied to the clipboard, check it before pasting it into a bug report");
+            egui::ScrollArea::vertical()
+                .max_height(ui.ctx().screen_rect().height() * 0.6)
+                .show(ui, |ui| {
+                    ui.code_editor(&mut report.as_str());
+                });
+        });
+        modal.buttons(ui, |ui| {
+            if modal.button(ui, "Close").clicked() {
+                self.diagnostics = None;
+            }
+            if modal.button(ui, "Copy").clicked() {
+                if let Some(report) = &self.diagnostics {
+                    ui.ctx().copy_text(report.clone());
+                }
+            }
+        });
+    }
+
+    fn show_data_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Data");
+
+        let chats = self.chats.iter().filter(|c| !c.ephemeral).count();
+        let messages: usize = self
+            .chats
+            .iter()
+            .filter(|c| !c.ephemeral)
+            .map(|c| c.messages.len())
+            .sum();
+        let storage_dir = eframe::storage_dir(crate::TITLE);
+
+        egui::Grid::new("data_grid").num_columns(2).show(ui, |ui| {
+            ui.label("Chats");
+            ui.label(chats.to_string());
+            ui.end_row();
+
+            ui.label("Messages");
+            ui.label(messages.to_string());
+            ui.end_row();
+
+            ui.label("Size");
+            ui.horizontal(|ui| {
+                match self.data_size {
+                    Some((counts, size)) => {
+                        ui.label(format!("~{}", bytesize::ByteSize(size as u64)));
+                        if counts != (chats, messages) {
+                            ui.label(RichText::new("(outdated)").weak());
+                        }
+                    }
+                    None => {
+                        ui.label(RichText::new("Not computed").weak());
+                    }
+                }
+                if ui
+                    .small_button("Compute")
+                    .on_hover_text("Serialize the chats to measure how much space they take up")
+                    .clicked()
+                {
+                    let persistent: Vec<&Chat> =
+                        self.chats.iter().filter(|c| !c.ephemeral).collect();
+                    match ron::to_string(&persistent) {
+                        Ok(s) => self.data_size = Some(((chats, messages), s.len())),
+                        Err(e) => log::error!("failed to serialize chats: {e}"),
+                    }
+                }
+            });
+            ui.end_row();
+
+            ui.label("Location");
+            ui.horizontal(|ui| {
+                if let Some(dir) = &storage_dir {
+                    ui.add(egui::Label::new(dir.display().to_string()).truncate());
+                    if ui.small_button("Open folder").clicked() {
+                        match url::Url::from_directory_path(dir) {
+                            Ok(url) => ui.ctx().open_url(egui::OpenUrl::new_tab(url)),
+                            Err(()) => log::error!("invalid storage directory: {dir:?}"),
+                        }
+                    }
+                } else {
+                    ui.label(RichText::new("Unknown").weak());
+                }
+            });
+            ui.end_row();
+
+            ui.label("Last saved");
+            ui.label(
+                self.last_saved
+                    .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                    .unwrap_or_else(|| "Not saved yet".to_owned()),
+            );
+            ui.end_row();
+        });
+
+        let modal = Modal::new(ui.ctx(), "restore_backup_modal");
+        ui.label("Back up all chats and settings to a file, or restore them from one");
+        ui.horizontal(|ui| {
+            if ui.button("Backup…").clicked() {
+                self.backup();
+            }
+            if ui.button("Restore…").clicked() {
+                modal.open();
+            }
+        });
+        modal.show(|ui| {
+            modal.title(ui, "Restore Backup");
+            modal.frame(ui, |ui| {
+                modal.body_and_icon(
+                    ui,
+                    "Restoring a backup replaces all current chats and settings. \
+                    You cannot undo this action later.",
+                    Icon::Warning,
+                );
+                modal.buttons(ui, |ui| {
+                    if modal.button(ui, "Cancel").clicked() {
+                        modal.close();
+                    }
+                    if modal.caution_button(ui, "Restore…").clicked() {
+                        modal.close();
+                        self.restore_backup();
+                    }
+                });
+            });
+        });
+    }
+
+    fn show_encryption_settings(&mut self, ui: &mut egui::Ui) {
+        let modal = Modal::new(ui.ctx(), "disable_encryption_modal");
+
+        ui.heading("Encryption");
+        if self.encryption_key.is_some() {
+            ui.label(
+                "Chats are encrypted with your passphrase. \
+                It is never saved, you will be asked for it on startup.",
+            );
+            if ui.button("Disable Encryption").clicked() {
+                modal.open();
+            }
+        } else {
+            ui.label(
+                "Encrypt chats stored on disk with a passphrase. \
+                If you forget it, your chats cannot be recovered.",
+            );
+            egui::Grid::new("encryption_grid")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Passphrase");
+                    ui.add(egui::TextEdit::singleline(&mut self.passphrase_buf).password(true));
+                    ui.end_row();
+
+                    ui.label("Confirm");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.passphrase_confirm_buf).password(true),
+                    );
+                    ui.end_row();
+                });
+            if ui.button("Enable Encryption").clicked() {
+                if self.passphrase_buf.is_empty() {
+                    self.passphrase_error = "Passphrase cannot be empty".to_owned();
+                } else if self.passphrase_buf != self.passphrase_confirm_buf {
+                    self.passphrase_error = "Passphrases do not match".to_owned();
+                } else {
+                    match EncryptionKey::new(&self.passphrase_buf) {
+                        Ok(key) => {
+                            log::info!("enabled chat encryption");
+                            self.encryption_key = Some(key);
+                            self.passphrase_error.clear();
+                            self.toasts
+                                .add(Toast::success("Chats will be saved encrypted"));
+                        }
+                        Err(e) => self.passphrase_error = e.to_string(),
+                    }
+                    self.passphrase_buf.clear();
+                    self.passphrase_confirm_buf.clear();
+                }
+            }
+            if !self.passphrase_error.is_empty() {
+                ui.label(RichText::new(&self.passphrase_error).color(ui.visuals().error_fg_color));
+            }
+        }
+
+        modal.show(|ui| {
+            modal.title(ui, "Disable Encryption");
+            modal.frame(ui, |ui| {
+                modal.body_and_icon(
+                    ui,
+                    "Your chats will be stored unencrypted on disk. Are you sure?",
+                    Icon::Warning,
+                );
+            });
+            modal.buttons(ui, |ui| {
+                modal.button(ui, "No");
+                if modal.caution_button(ui, "Yes").clicked() {
+                    log::info!("disabled chat encryption");
+                    self.encryption_key = None;
+                    self.encrypted_chats = None;
+                    self.toasts
+                        .add(Toast::info("Chats will be saved unencrypted"));
+                }
+            });
+        });
+    }
+
+    /// Whether there is a passphrase or PIN to unlock the app with
+    #[inline]
+    fn can_lock_app(&self) -> bool {
+        self.encryption_key.is_some() || self.settings.lock_pin_hash.is_some()
+    }
+
+    fn lock_app(&mut self) {
+        log::info!("locking the app");
+        self.app_locked = true;
+        self.passphrase_buf.clear();
+        self.passphrase_error.clear();
+    }
+
+    /// Lock the app with the shortcut or after being idle for too long
+    fn update_app_lock(&mut self, ctx: &egui::Context) {
+        if self.app_locked {
+            return;
+        }
+        if ctx.input_mut(|i| i.consume_shortcut(&shortcuts::LOCK)) {
+            if self.can_lock_app() {
+                self.lock_app();
+                return;
+            }
+            self.toasts.add(Toast::info(
+                "Set a PIN or enable encryption in Settings to lock the app",
+            ));
+        }
+
+        let active = ctx.input(|i| !i.events.is_empty() || i.pointer.is_moving());
+        let last_input = *self.last_input.get_or_insert_with(Instant::now);
+        if active {
+            self.last_input = Some(Instant::now());
+        }
+        if self.settings.auto_lock_minutes == 0 || !self.can_lock_app() {
+            return;
+        }
+        let timeout = Duration::from_secs(self.settings.auto_lock_minutes as u64 * 60);
+        let idle = last_input.elapsed();
+        if !active && idle >= timeout {
+            self.lock_app();
+        } else {
+            ctx.request_repaint_after(timeout.saturating_sub(idle));
+        }
+    }
+
+    /// Whether unlocking has to wait after failed attempts
+    #[inline]
+    fn unlock_delayed(&self) -> bool {
+        self.unlock_retry_at.is_some_and(|at| Instant::now() < at)
+    }
+
+    /// Check the entered passphrase or PIN on another thread, see [`Self::poll_unlock`]
+    fn unlock_app(&mut self) {
+        if self.unlock_check.is_some() || self.unlock_delayed() {
+            return;
+        }
+        let input = std::mem::take(&mut self.passphrase_buf);
+        let check: Box<dyn FnOnce(&str) -> bool + Send> = if let Some(key) = &self.encryption_key {
+            Box::new(key.matcher())
+        } else if let Some(hash) = self.settings.lock_pin_hash.clone() {
+            Box::new(move |pin| crate::encryption::verify_pin(pin, &hash))
+        } else {
+            Box::new(|_| true)
+        };
+        self.unlock_check = Some(std::thread::spawn(move || check(&input)));
+    }
+
+    /// Keep the countdown until the next unlock attempt up to date
+    fn repaint_unlock_countdown(&self, ctx: &egui::Context) {
+        if let Some(at) = self.unlock_retry_at.filter(|_| self.unlock_delayed()) {
+            ctx.request_repaint_after(
+                at.duration_since(Instant::now())
+                    .min(Duration::from_secs(1)),
+            );
+        }
+    }
+
+    /// Double the wait before the next unlock attempt after a failed one
+    fn delay_unlock(&mut self) {
+        self.failed_unlocks += 1;
+        let delay = Duration::from_secs(1 << (self.failed_unlocks - 1).min(5));
+        log::warn!("failed to unlock the app {} time(s)", self.failed_unlocks);
+        self.unlock_retry_at = Some(Instant::now() + delay.min(MAX_UNLOCK_DELAY));
+    }
+
+    fn show_unlock_retry(&self, ui: &mut egui::Ui) {
+        if let Some(at) = self.unlock_retry_at.filter(|_| self.unlock_delayed()) {
+            let wait = at.duration_since(Instant::now()).as_secs() + 1;
+            ui.weak(format!("Try again in {wait} s"));
+        }
+    }
+
+    /// Unlock the app once the entered passphrase or PIN was checked
+    fn poll_unlock(&mut self, ctx: &egui::Context) {
+        self.repaint_unlock_countdown(ctx);
+        if !self
+            .unlock_check
+            .as_ref()
+            .is_some_and(std::thread::JoinHandle::is_finished)
+        {
+            if self.unlock_check.is_some() {
+                ctx.request_repaint_after(Duration::from_millis(50));
+            }
+            return;
+        }
+        let Some(check) = self.unlock_check.take() else {
+            return;
+        };
+        let unlocked = check.join().unwrap_or_else(|_| {
+            log::error!("checking the passphrase panicked");
+            false
+        });
+        if unlocked {
+            log::info!("unlocked the app");
+            self.app_locked = false;
+            self.last_input = Some(Instant::now());
+            self.passphrase_error.clear();
+            self.failed_unlocks = 0;
+            self.unlock_retry_at = None;
+        } else {
+            self.delay_unlock();
+            self.passphrase_error = if self.encryption_key.is_some() {
+                "Wrong passphrase".to_owned()
+            } else {
+                "Wrong PIN".to_owned()
+            };
+        }
+    }
+
+    fn show_app_lock_screen(&mut self, ctx: &egui::Context) {
+        let using_passphrase = self.encryption_key.is_some();
+        let generating = self.chats.iter().filter(|c| c.flower_active()).count();
+        egui::CentralPanel::default().show(ctx, |ui| {
+            widgets::centerer(ui, |ui| {
+                ui.heading("🔒 Ellama is locked");
+                ui.label(if using_passphrase {
+                    "Enter your passphrase to unlock it"
+                } else {
+                    "Enter your PIN to unlock it"
+                });
+                let resp = ui.add(
+                    egui::TextEdit::singleline(&mut self.passphrase_buf)
+                        .password(true)
+                        .hint_text(if using_passphrase {
+                            "Passphrase"
+                        } else {
+                            "PIN"
+                        }),
+                );
+                if ui.memory(|m| m.focused().is_none()) {
+                    resp.request_focus();
+                }
+                let submitted = resp.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                let checking = self.unlock_check.is_some();
+                let can_unlock = !checking && !self.unlock_delayed();
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(can_unlock, egui::Button::new("Unlock"))
+                        .clicked()
+                        || (submitted && can_unlock)
                     {
-                        self.settings.model_picker.selected = chat.model_picker.selected.clone();
+                        self.unlock_app();
                     }
-
-                    self.request_model_info(name, ollama.clone());
+                    if checking {
+                        ui.spinner();
+                    } else {
+                        self.show_unlock_retry(ui);
+                    }
+                });
+                if !self.passphrase_error.is_empty() {
+                    ui.label(
+                        RichText::new(&self.passphrase_error).color(ui.visuals().error_fg_color),
+                    );
                 }
-                if list_models {
-                    self.list_models(ollama.clone());
+                if generating > 0 {
+                    ui.label(
+                        RichText::new(format!(
+                            "{generating} chat{} still generating",
+                            if generating == 1 { " is" } else { "s are" }
+                        ))
+                        .weak(),
+                    );
                 }
             });
-        ui.collapsing("Export", |ui| {
-            ui.label("Export chat history to a file");
-            let format = self.chat_export_format;
-            egui::ComboBox::from_label("Export Format")
-                .selected_text(format.to_string())
-                .show_ui(ui, |ui| {
-                    for format in ChatExportFormat::ALL {
-                        ui.selectable_value(
-                            &mut self.chat_export_format,
-                            format,
-                            format.to_string(),
-                        );
-                    }
-                });
-            if ui.button("Save As…").clicked() {
-                let task = rfd::AsyncFileDialog::new()
-                    .add_filter(format!("{format:?} file"), format.extensions())
-                    .save_file();
-                let Some(chat) = self.chats.get_mut(chat_idx) else {
-                    return;
-                };
-                let messages = chat.messages.clone();
-                let handle = self.flower.handle();
-                tokio::spawn(async move {
-                    let toast = crate::chat::export_messages(messages, format, task)
-                        .await
-                        .map_err(|e| {
-                            log::error!("failed to export messages: {e}");
-                            e
-                        });
+        });
+    }
 
-                    handle.activate();
-                    if let Ok(toast) = toast {
-                        handle.success(OllamaResponse::Toast(toast))
-                    } else if let Err(e) = toast {
-                        handle.success(OllamaResponse::Toast(Toast::error(e.to_string())))
-                    };
+    fn show_lock_settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Lock");
+        ui.label(format!(
+            "Hide the chats until the app is unlocked with your passphrase or a PIN. \
+            Press {} to lock it now.",
+            ui.ctx().format_shortcut(&shortcuts::LOCK)
+        ));
+
+        if self.encryption_key.is_some() {
+            ui.label(RichText::new("Chats are encrypted, the passphrase unlocks the app").weak());
+        } else if self.settings.lock_pin_hash.is_some() {
+            if ui.button("Remove PIN").clicked() {
+                self.settings.lock_pin_hash = None;
+                self.toasts.add(Toast::info("Removed the PIN"));
+            }
+        } else {
+            egui::Grid::new("lock_pin_grid")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("PIN");
+                    ui.add(egui::TextEdit::singleline(&mut self.pin_buf).password(true));
+                    ui.end_row();
+
+                    ui.label("Confirm");
+                    ui.add(egui::TextEdit::singleline(&mut self.pin_confirm_buf).password(true));
+                    ui.end_row();
                 });
+            if ui.button("Set PIN").clicked() {
+                if self.pin_buf.len() < MIN_PIN_LEN
+                    || !self.pin_buf.chars().all(|c| c.is_ascii_digit())
+                {
+                    self.toasts.add(Toast::error(format!(
+                        "The PIN must be at least {MIN_PIN_LEN} digits"
+                    )));
+                } else if self.pin_buf != self.pin_confirm_buf {
+                    self.toasts.add(Toast::error("PINs do not match"));
+                } else {
+                    match crate::encryption::hash_pin(&self.pin_buf) {
+                        Ok(hash) => {
+                            self.settings.lock_pin_hash = Some(hash);
+                            self.toasts.add(Toast::success("The app can now be locked"));
+                        }
+                        Err(e) => {
+                            log::error!("failed to hash PIN: {e}");
+                            self.toasts.add(Toast::error(e.to_string()));
+                        }
+                    }
+                    self.pin_buf.clear();
+                    self.pin_confirm_buf.clear();
+                }
             }
+        }
+
+        ui.add_enabled_ui(self.can_lock_app(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Lock after");
+                ui.add(
+                    egui::DragValue::new(&mut self.settings.auto_lock_minutes)
+                        .clamp_range(0..=24 * 60)
+                        .suffix(" min"),
+                );
+                ui.label("without input");
+            })
+            .response
+            .on_hover_text(
+                "0 to never lock automatically. The app is also locked after waking from sleep.",
+            );
         });
     }
 
-    fn show_left_panel(&mut self, ui: &mut egui::Ui) {
-        ui.add_space(ui.style().spacing.window_margin.top);
+    /// Note that the model list is the one saved in the last session
+    fn show_stale_models_note(&self, ui: &mut egui::Ui, is_loading_models: bool) {
+        if !self.models_stale || self.models.is_empty() {
+            return;
+        }
         ui.horizontal(|ui| {
-            ui.selectable_value(&mut self.tab, SessionTab::Chats, "Chats");
-            ui.with_layout(Layout::right_to_left(egui::Align::Max), |ui| {
-                ui.toggle_value(&mut self.settings_open, "⚙")
-                    .on_hover_text("Settings");
-            });
+            if is_loading_models {
+                ui.add(egui::Spinner::new().size(12.0));
+                ui.weak("Model list from the last session, refreshing…");
+            } else {
+                ui.weak("Model list from the last session, Ollama couldn't be reached");
+            }
         });
+    }
 
-        ui.add_space(8.0);
+    #[inline]
+    fn is_loading_models(&self) -> bool {
+        !self.started
+            || (self.flower.is_active() && self.flower_activity == OllamaFlowerActivity::ListModels)
+    }
 
-        match self.tab {
-            SessionTab::Chats => {
-                let modal = Modal::new(ui.ctx(), "remove_chat_modal");
-                self.show_chats(ui, &modal);
-                modal.show(|ui| {
-                    self.show_remove_chat_modal_inner(ui, &modal);
-                });
+    /// Treat all selected models as picked by the user, for states saved before it was
+    /// stored who picked them
+    pub fn mark_user_selections(&mut self) {
+        let chat_pickers = self.chats.iter_mut().map(|c| &mut c.model_picker);
+        for picker in std::iter::once(&mut self.settings.model_picker).chain(chat_pickers) {
+            if picker.has_selection() {
+                picker.selection_source = SelectionSource::User;
             }
         }
     }
 
-    #[inline]
-    pub fn model_picker(&self) -> &ModelPicker {
-        &self.settings.model_picker
+    /// Id for a new chat. Ids aren't reused, so a chat that is brought back or still
+    /// open in its own window can't be mistaken for another one.
+    fn next_chat_id(&mut self) -> usize {
+        self.last_chat_id += 1;
+        self.last_chat_id
     }
 
-    fn poll_ollama_flower(&mut self, modal: &Modal) {
-        self.flower.extract(|()| ()).finalize(|resp| {
-            self.flower_activity = OllamaFlowerActivity::Idle;
-            match resp {
-                Ok(OllamaResponse::Ignore) => (),
-                Ok(OllamaResponse::Models(models)) => {
-                    self.models = models;
-                    if !self.settings.model_picker.has_selection() {
-                        self.settings.model_picker.select_best_model(&self.models);
-
-                        // for each chat with unselected models, select the best model
-                        for chat in self.chats.iter_mut() {
-                            if !chat.model_picker.has_selection() {
-                                chat.model_picker.selected =
-                                    self.settings.model_picker.selected.clone();
-                            }
-                        }
-                    }
-                }
-                Ok(OllamaResponse::ModelInfo { name, info }) => {
-                    self.pending_model_infos.remove(&name);
-                    self.settings.model_picker.on_new_model_info(&name, &info);
-                    for chat in self.chats.iter_mut() {
-                        chat.model_picker.on_new_model_info(&name, &info);
-                    }
-                }
-                Ok(OllamaResponse::Toast(toast)) => {
-                    self.toasts.add(toast);
-                }
-                Ok(OllamaResponse::Images { id, files }) => {
-                    if let Some(chat) = self.chats.iter_mut().find(|c| c.id() == id) {
-                        log::debug!("adding {} image(s)", files.len());
-                        chat.images.extend(files);
-                    }
-                }
-                Ok(OllamaResponse::Settings(settings)) => {
-                    self.settings = *settings;
-                }
-                Err(flowync::error::Compact::Suppose(e)) => {
-                    modal
-                        .dialog()
-                        .with_icon(Icon::Error)
-                        .with_title("Ollama request failed")
-                        .with_body(e)
-                        .open();
-                }
-                Err(flowync::error::Compact::Panicked(e)) => {
-                    log::error!("task panicked: {e}");
-                    modal
-                        .dialog()
-                        .with_icon(Icon::Error)
-                        .with_title("Ollama request task panicked")
-                        .with_body(format!("Task panicked: {e}"))
-                        .open();
-                }
-            };
-        });
+    /// Give every chat its own id. Ids aren't saved, so all loaded or decrypted chats
+    /// start out with the same one.
+    pub fn assign_chat_ids(&mut self) {
+        for chat in &mut self.chats {
+            self.last_chat_id += 1;
+            chat.reset_flower(self.last_chat_id);
+        }
     }
 
     #[inline]
-    fn is_loading_models(&self) -> bool {
-        self.flower.is_active() && self.flower_activity == OllamaFlowerActivity::ListModels
+    fn add_default_chat(&mut self) {
+        let id = self.next_chat_id();
+        self.chats.push(Chat::new(id, self.model_picker().clone()));
     }
 
-    #[inline]
-    fn add_default_chat(&mut self) {
-        // id 1 is already used, and we (probably) don't want to reuse ids for flowers
-        self.chats
-            .push(Chat::new(self.chats.len() + 2, self.model_picker().clone()));
+    /// Insert a copy of the chat right after it and select it
+    fn duplicate_chat(&mut self, idx: usize) {
+        let id = self.next_chat_id();
+        let chat = self.chats[idx].duplicate(id);
+        self.chats.insert(idx + 1, chat);
+        self.virtual_list.borrow_mut().items_inserted_at(idx + 1);
+        self.selected_chat = idx + 1;
+        if let Some(edited) = self.edited_chat.as_mut().filter(|e| **e > idx) {
+            *edited += 1;
+        }
+    }
+
+    /// Move a chat so it's before the chat at `to`, keeping the same chats selected
+    fn move_chat(&mut self, from: usize, to: usize) {
+        // `to` counts the moved chat, which is removed first
+        let to = if to > from { to - 1 } else { to };
+        if from == to || from >= self.chats.len() || to >= self.chats.len() {
+            return;
+        }
+        let chat = self.chats.remove(from);
+        self.chats.insert(to, chat);
+
+        let remap = |i: usize| {
+            if i == from {
+                to
+            } else if from < i && i <= to {
+                i - 1
+            } else if to <= i && i < from {
+                i + 1
+            } else {
+                i
+            }
+        };
+        self.selected_chat = remap(self.selected_chat);
+        self.edited_chat = self.edited_chat.map(remap);
+        self.chat_marked_for_deletion = remap(self.chat_marked_for_deletion);
+        self.virtual_list = Rc::new(RefCell::new(VirtualList::new()));
+    }
+
+    /// Select the scratchpad, creating it at the top of the list if there isn't one
+    fn open_scratchpad(&mut self) {
+        if let Some(idx) = self.chats.iter().position(|c| c.ephemeral) {
+            self.selected_chat = idx;
+            return;
+        }
+        let id = self.next_chat_id();
+        let mut chat = Chat::new(id, self.model_picker().clone());
+        chat.ephemeral = true;
+        self.chats.insert(0, chat);
+        self.virtual_list.borrow_mut().items_inserted_at(0);
+        self.selected_chat = 0;
     }
 
     fn remove_chat(&mut self, idx: usize) {
-        self.chats.remove(idx);
-        if self.chats.is_empty() {
+        let chat = self.chats.remove(idx);
+        chat.stop_generating();
+        self.recently_removed = Some((idx, chat, Instant::now()));
+        self.removal_placeholder = None;
+        // scratchpads aren't saved, there must always be a regular chat
+        if self.chats.iter().all(|c| c.ephemeral) {
             self.add_default_chat();
-            self.selected_chat = 0;
+            self.selected_chat = self.chats.len() - 1;
+            self.removal_placeholder = Some(self.last_chat_id);
         } else if self.selected_chat >= self.chats.len() {
             self.selected_chat = self.chats.len() - 1;
         }
     }
 
+    /// Put the last removed chat back where it was and select it
+    fn undo_remove_chat(&mut self) {
+        let Some((mut idx, chat, _)) = self.recently_removed.take() else {
+            return;
+        };
+        let placeholder = self.removal_placeholder.take().and_then(|id| {
+            self.chats
+                .iter()
+                .position(|c| c.id() == id && c.is_untouched())
+        });
+        if let Some(pos) = placeholder {
+            self.chats.remove(pos);
+            if pos < idx {
+                idx -= 1;
+            }
+            self.virtual_list = Rc::new(RefCell::new(VirtualList::new()));
+        }
+        let idx = idx.min(self.chats.len());
+        self.chats.insert(idx, chat);
+        self.virtual_list.borrow_mut().items_inserted_at(idx);
+        self.selected_chat = idx;
+        self.edited_chat = None;
+    }
+
+    /// Show a button to bring back the last removed chat until the undo times out
+    fn show_undo_remove(&mut self, ui: &mut egui::Ui) {
+        let Some((_, chat, removed_at)) = &self.recently_removed else {
+            return;
+        };
+        let Some(remaining) = UNDO_REMOVE_TIMEOUT.checked_sub(removed_at.elapsed()) else {
+            self.recently_removed = None;
+            self.removal_placeholder = None;
+            return;
+        };
+        ui.ctx().request_repaint_after(remaining);
+
+        let summary = if chat.summary.is_empty() {
+            "New Chat"
+        } else {
+            chat.summary.as_str()
+        };
+        if ui
+            .add(
+                egui::Button::new(format!("↩ Undo removing \"{summary}\""))
+                    .truncate()
+                    .min_size(vec2(ui.available_width(), 24.0)),
+            )
+            .on_hover_text(format!("Available for {}s", remaining.as_secs() + 1))
+            .clicked()
+        {
+            self.undo_remove_chat();
+        }
+    }
+
     /// Returns whether any chat was removed
     fn show_chat_frame(&mut self, ui: &mut egui::Ui, idx: usize, modal: &Modal) -> bool {
         let Some(chat) = &self.chats.get(idx) else {
@@ -749,9 +4195,30 @@ impl Sessions {
             .unwrap_or_else(|| "No recent messages".to_string());
 
         let summary = chat.summary.clone();
+        let ephemeral = chat.ephemeral;
+        let has_unread = chat.has_unread;
+        // the selected chat's draft is right there in the chatbox
+        let draft = if idx == self.selected_chat {
+            None
+        } else {
+            chat.draft()
+        };
 
         ui.horizontal(|ui| {
-            if summary.is_empty() {
+            if has_unread {
+                let (rect, resp) = ui.allocate_exact_size(vec2(8.0, 8.0), egui::Sense::hover());
+                ui.painter()
+                    .circle_filled(rect.center(), 4.0, ui.visuals().selection.bg_fill);
+                resp.on_hover_text("New response");
+            }
+            if ephemeral {
+                ui.add(
+                    egui::Label::new("📝 Scratchpad")
+                        .selectable(false)
+                        .truncate(),
+                )
+                .on_hover_text("This chat is never saved");
+            } else if summary.is_empty() {
                 ui.add(egui::Label::new("New Chat").selectable(false).truncate());
             } else {
                 EmojiLabel::new(summary)
@@ -769,7 +4236,11 @@ impl Sessions {
                             .fill(Color32::TRANSPARENT)
                             .stroke(Stroke::NONE),
                     )
-                    .on_hover_text("Remove chat")
+                    .on_hover_text(shortcuts::hint(
+                        ui.ctx(),
+                        "Remove chat",
+                        &shortcuts::CLOSE_CHAT,
+                    ))
                     .clicked()
                 {
                     if self.chats[idx].messages.is_empty() || ui.input(|i| i.modifiers.shift) {
@@ -781,6 +4252,32 @@ impl Sessions {
                     }
                     ignore_click = true;
                 }
+                if ui
+                    .add(
+                        egui::Button::new("🗐")
+                            .small()
+                            .fill(Color32::TRANSPARENT)
+                            .stroke(Stroke::NONE),
+                    )
+                    .on_hover_text("Duplicate chat")
+                    .clicked()
+                {
+                    self.chat_to_duplicate = Some(idx);
+                    ignore_click = true;
+                }
+                if ui
+                    .add(
+                        egui::Button::new("🗗")
+                            .small()
+                            .fill(Color32::TRANSPARENT)
+                            .stroke(Stroke::NONE),
+                    )
+                    .on_hover_text("Open in new window")
+                    .clicked()
+                {
+                    self.popped_out = Some(self.chats[idx].id());
+                    ignore_click = true;
+                }
                 if ui
                     .add(
                         egui::Button::new("\u{270f}")
@@ -803,19 +4300,33 @@ impl Sessions {
             });
         });
 
-        ui.add_enabled(
-            false,
-            egui::Label::new(last_message).selectable(false).truncate(),
-        );
+        if let Some(draft) = draft {
+            ui.add(
+                egui::Label::new(
+                    RichText::new(format!("\u{270f} Draft: {draft}"))
+                        .color(ui.visuals().warn_fg_color),
+                )
+                .selectable(false)
+                .truncate(),
+            )
+            .on_hover_text("Unsent message, kept when switching chats and restarting");
+        } else {
+            ui.add_enabled(
+                false,
+                egui::Label::new(last_message).selectable(false).truncate(),
+            );
+        }
         ignore_click
     }
 
     /// Returns whether the chat should be selected as the current one
     fn show_chat_in_sidepanel(&mut self, ui: &mut egui::Ui, idx: usize, modal: &Modal) -> bool {
         let mut ignore_click = false;
+        let ephemeral = self.chats.get(idx).is_some_and(|c| c.ephemeral);
+        let stroke = Stroke::new(2.0, ui.style().visuals.window_stroke.color);
         let resp = Frame::group(ui.style())
             .rounding(Rounding::same(6.0))
-            .stroke(Stroke::new(2.0, ui.style().visuals.window_stroke.color))
+            .stroke(if ephemeral { Stroke::NONE } else { stroke })
             .fill(if self.selected_chat == idx {
                 ui.style().visuals.faint_bg_color
             } else {
@@ -826,6 +4337,51 @@ impl Sessions {
             })
             .response;
 
+        // drag the chat to reorder the list
+        let drag = ui.interact(
+            resp.rect,
+            ui.id().with(("drag_chat", idx)),
+            egui::Sense::drag(),
+        );
+        if drag.dragged() {
+            drag.dnd_set_drag_payload(DraggedChat(idx));
+            ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
+        }
+        if let Some(dragged) = resp.dnd_hover_payload::<DraggedChat>() {
+            if dragged.0 != idx {
+                let before = ui
+                    .input(|i| i.pointer.interact_pos())
+                    .is_some_and(|p| p.y < resp.rect.center().y);
+                let y = if before {
+                    resp.rect.top() - 1.0
+                } else {
+                    resp.rect.bottom() + 1.0
+                };
+                ui.painter().hline(
+                    resp.rect.x_range(),
+                    y,
+                    Stroke::new(2.0, ui.visuals().selection.bg_fill),
+                );
+                if let Some(dragged) = resp.dnd_release_payload::<DraggedChat>() {
+                    self.chat_to_move = Some((dragged.0, if before { idx } else { idx + 1 }));
+                }
+            }
+        }
+
+        // scratchpads get a dashed border
+        if ephemeral {
+            let rect = resp.rect.shrink(stroke.width * 0.5);
+            let points = [
+                rect.left_top(),
+                rect.right_top(),
+                rect.right_bottom(),
+                rect.left_bottom(),
+                rect.left_top(),
+            ];
+            ui.painter()
+                .extend(egui::Shape::dashed_line(&points, stroke, 6.0, 4.0));
+        }
+
         // very hacky way to determine if the group has been clicked, for some reason
         // egui doens't register clicked() events on it
         let (primary_clicked, hovered) = if modal.is_open() {
@@ -844,16 +4400,52 @@ impl Sessions {
 
         if hovered {
             ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+            if self.settings.chat_previews {
+                self.show_chat_preview(idx, resp);
+            }
         }
 
         !ignore_click && primary_clicked && hovered
     }
 
+    /// Show the last few messages of a chat in a tooltip
+    fn show_chat_preview(&self, idx: usize, resp: egui::Response) {
+        const PREVIEW_MESSAGES: usize = 3;
+        const MAX_PREVIEW_CHARS: usize = 200;
+
+        let Some(chat) = self.chats.get(idx) else {
+            return;
+        };
+        let messages = chat.recent_messages(PREVIEW_MESSAGES);
+        if messages.is_empty() {
+            return;
+        }
+        resp.on_hover_ui_at_pointer(|ui| {
+            ui.set_max_width(320.0);
+            for (role, content) in messages {
+                ui.label(RichText::new(role).strong());
+                let mut text: String = content.chars().take(MAX_PREVIEW_CHARS).collect();
+                if text.len() < content.len() {
+                    text.push('…');
+                }
+                ui.add(egui::Label::new(text).wrap());
+            }
+        });
+    }
+
     fn show_chats(&mut self, ui: &mut egui::Ui, modal: &Modal) {
-        ui.vertical_centered_justified(|ui| {
+        ui.horizontal(|ui| {
+            let scratchpad_width = 28.0;
             if ui
-                .add(egui::Button::new("➕ New Chat").min_size(vec2(0.0, 24.0)))
-                .on_hover_text("Create a new chat")
+                .add(egui::Button::new("➕ New Chat").min_size(vec2(
+                    ui.available_width() - scratchpad_width - ui.spacing().item_spacing.x,
+                    24.0,
+                )))
+                .on_hover_text(shortcuts::hint(
+                    ui.ctx(),
+                    "Create a new chat",
+                    &shortcuts::NEW_CHAT,
+                ))
                 .clicked()
             {
                 self.add_default_chat();
@@ -861,8 +4453,60 @@ impl Sessions {
                 self.edited_chat = None;
                 self.settings_open = false;
             }
+            if ui
+                .add(egui::Button::new("📝").min_size(vec2(scratchpad_width, 24.0)))
+                .on_hover_text("Open a scratchpad, a throwaway chat that is never saved")
+                .clicked()
+            {
+                self.open_scratchpad();
+                self.edited_chat = None;
+                self.settings_open = false;
+            }
         });
 
+        // all chats, including the ones that aren't shown
+        let generating = self.chats.iter().filter(|c| c.flower_active()).count();
+        if generating > 0
+            && ui
+                .add(
+                    egui::Button::new(format!("⏹ Stop All ({generating})"))
+                        .min_size(vec2(ui.available_width(), 24.0)),
+                )
+                .on_hover_text("Stop generating responses in all chats")
+                .clicked()
+        {
+            log::info!("stopping {generating} generations");
+            for chat in &self.chats {
+                chat.stop_generating();
+            }
+        }
+        if let Some(warmup) = &self.warmup {
+            let mut cancel = false;
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.add(
+                    egui::Label::new(
+                        RichText::new(format!("Loading {}…", warmup.model))
+                            .small()
+                            .weak(),
+                    )
+                    .truncate(),
+                )
+                .on_hover_text(format!(
+                    "Loading `{}` into memory so it responds faster",
+                    warmup.model
+                ));
+                cancel = ui
+                    .small_button("❌")
+                    .on_hover_text("Cancel loading the model")
+                    .clicked();
+            });
+            if cancel {
+                self.cancel_warmup();
+            }
+        }
+        self.show_undo_remove(ui);
+
         ui.add_space(2.0);
 
         let vlist = self.virtual_list.clone();
@@ -879,5 +4523,113 @@ impl Sessions {
                     1
                 });
         });
+        if let Some(idx) = self.chat_to_duplicate.take() {
+            self.duplicate_chat(idx);
+        }
+        if let Some((from, to)) = self.chat_to_move.take() {
+            self.move_chat(from, to);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// Chats as they are after loading, all with the same id
+    fn loaded_sessions() -> Sessions {
+        serde_json::from_str(r#"{"chats":[{"summary":"a"},{"summary":"b"},{"summary":"c"}]}"#)
+            .unwrap()
+    }
+
+    fn chat_ids(sessions: &Sessions) -> Vec<usize> {
+        sessions.chats.iter().map(Chat::id).collect()
+    }
+
+    fn assert_unique_ids(sessions: &Sessions) {
+        let ids = chat_ids(sessions);
+        let unique: HashSet<_> = ids.iter().collect();
+        assert_eq!(unique.len(), ids.len(), "chat ids aren't unique: {ids:?}");
+    }
+
+    #[test]
+    fn loaded_chats_get_unique_ids() {
+        let mut sessions = loaded_sessions();
+        sessions.assign_chat_ids();
+        assert_unique_ids(&sessions);
+    }
+
+    #[test]
+    fn new_chats_dont_reuse_ids() {
+        let mut sessions = loaded_sessions();
+        sessions.assign_chat_ids();
+        sessions.remove_chat(2);
+        let removed = sessions.recently_removed.as_ref().unwrap().1.id();
+        sessions.add_default_chat();
+        sessions.duplicate_chat(0);
+        sessions.open_scratchpad();
+        assert_unique_ids(&sessions);
+        assert!(!chat_ids(&sessions).contains(&removed));
+
+        sessions.undo_remove_chat();
+        assert_unique_ids(&sessions);
+    }
+
+    #[test]
+    fn pops_out_the_chosen_chat_after_loading() {
+        let mut sessions = loaded_sessions();
+        sessions.assign_chat_ids();
+        sessions.popped_out = Some(sessions.chats[2].id());
+        sessions.lend_popped_out(&Ollama::default());
+
+        let popout = sessions.popout.clone().unwrap();
+        let (idx, chat) = popout.lock().chat.take().unwrap();
+        assert_eq!((idx, chat.summary.as_str()), (2, "c"));
+        let left: Vec<_> = sessions.chats.iter().map(|c| c.summary.as_str()).collect();
+        assert_eq!(left, ["a", "b"]);
+    }
+
+    fn summaries(sessions: &Sessions) -> Vec<&str> {
+        sessions.chats.iter().map(|c| c.summary.as_str()).collect()
+    }
+
+    #[test]
+    fn undoing_removal_of_last_chat_drops_its_placeholder() {
+        let mut sessions: Sessions =
+            serde_json::from_str(r#"{"chats":[{"summary":"a"}]}"#).unwrap();
+        sessions.assign_chat_ids();
+        sessions.open_scratchpad();
+        sessions.remove_chat(1);
+        assert_eq!(summaries(&sessions), ["", ""]);
+
+        sessions.undo_remove_chat();
+        assert_eq!(summaries(&sessions), ["", "a"]);
+        assert!(sessions.chats[0].ephemeral);
+        assert_eq!(sessions.selected_chat, 1);
+    }
+
+    #[test]
+    fn undoing_removal_keeps_a_used_placeholder() {
+        let mut sessions: Sessions =
+            serde_json::from_str(r#"{"chats":[{"summary":"a"}]}"#).unwrap();
+        sessions.assign_chat_ids();
+        sessions.remove_chat(0);
+        sessions.chats[0].system_prompt = "Be brief.".to_owned();
+
+        sessions.undo_remove_chat();
+        assert_eq!(summaries(&sessions), ["a", ""]);
+        assert_eq!(sessions.selected_chat, 0);
+    }
+
+    #[test]
+    fn attaches_dropped_files_to_the_chat_they_were_dropped_on() {
+        let mut sessions = loaded_sessions();
+        sessions.assign_chat_ids();
+        let id = sessions.chats[1].id();
+        sessions.attach_files(id, vec![PathBuf::from("cat.png")]);
+
+        let images: Vec<_> = sessions.chats.iter().map(|c| c.images.len()).collect();
+        assert_eq!(images, [0, 1, 0]);
     }
 }
@@ -0,0 +1,126 @@
+use crate::widgets::SendKey;
+use eframe::egui::{self, Key, KeyboardShortcut, Modifiers, RichText};
+
+pub const NEW_CHAT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::N);
+pub const CLOSE_CHAT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::W);
+pub const NEXT_CHAT: [KeyboardShortcut; 2] = [
+    KeyboardShortcut::new(Modifiers::COMMAND, Key::Tab),
+    KeyboardShortcut::new(Modifiers::COMMAND, Key::PageDown),
+];
+pub const PREV_CHAT: [KeyboardShortcut; 2] = [
+    KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::Tab),
+    KeyboardShortcut::new(Modifiers::COMMAND, Key::PageUp),
+];
+pub const LOCK: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::L);
+pub const STOP: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::Escape);
+pub const HELP: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Slash);
+const PREV_PROMPT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::ArrowUp);
+const NEXT_PROMPT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::ArrowDown);
+const CANCEL: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::Escape);
+
+/// Part of the app a shortcut works in, the shortcuts are grouped by it in the help overlay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Area {
+    App,
+    Chats,
+    Chatbox,
+    Messages,
+}
+
+impl Area {
+    const ALL: [Self; 4] = [Self::App, Self::Chats, Self::Chatbox, Self::Messages];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::App => "App",
+            Self::Chats => "Chats",
+            Self::Chatbox => "Chatbox",
+            Self::Messages => "Messages",
+        }
+    }
+}
+
+pub struct Shortcut {
+    pub area: Area,
+    pub description: &'static str,
+    /// Any of these triggers it
+    pub keys: Vec<KeyboardShortcut>,
+}
+
+impl Shortcut {
+    fn new(area: Area, description: &'static str, keys: &[KeyboardShortcut]) -> Self {
+        Self {
+            area,
+            description,
+            keys: keys.to_vec(),
+        }
+    }
+}
+
+/// Every shortcut of the app, the chatbox ones depend on the send key setting
+pub fn all(send_key: SendKey) -> Vec<Shortcut> {
+    vec![
+        Shortcut::new(Area::App, "Show keyboard shortcuts", &[HELP]),
+        Shortcut::new(Area::App, "Lock the app", &[LOCK]),
+        Shortcut::new(Area::Chats, "New chat", &[NEW_CHAT]),
+        Shortcut::new(Area::Chats, "Close chat", &[CLOSE_CHAT]),
+        Shortcut::new(Area::Chats, "Next chat", &NEXT_CHAT),
+        Shortcut::new(Area::Chats, "Previous chat", &PREV_CHAT),
+        Shortcut::new(Area::Chats, "Stop generating", &[STOP]),
+        Shortcut::new(Area::Chatbox, "Send message", &[send_key.shortcut()]),
+        Shortcut::new(Area::Chatbox, "New line", &[send_key.newline_shortcut()]),
+        Shortcut::new(Area::Chatbox, "Previous prompt", &[PREV_PROMPT]),
+        Shortcut::new(Area::Chatbox, "Next prompt", &[NEXT_PROMPT]),
+        Shortcut::new(Area::Messages, "Cancel editing a response", &[CANCEL]),
+        Shortcut::new(Area::Messages, "Close the image viewer", &[CANCEL]),
+    ]
+}
+
+/// Keys of a shortcut, like "Ctrl+Tab or Ctrl+PageDown"
+pub fn format(ctx: &egui::Context, keys: &[KeyboardShortcut]) -> String {
+    keys.iter()
+        .map(|k| ctx.format_shortcut(k))
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
+/// Hover text followed by its shortcut, like "Create a new chat (Ctrl+N)"
+pub fn hint(ctx: &egui::Context, text: &str, shortcut: &KeyboardShortcut) -> String {
+    format!("{text} ({})", ctx.format_shortcut(shortcut))
+}
+
+/// Shortcuts of an area, one per line
+pub fn list(ctx: &egui::Context, area: Area, send_key: SendKey) -> String {
+    all(send_key)
+        .iter()
+        .filter(|s| s.area == area)
+        .map(|s| format!("{}: {}", format(ctx, &s.keys), s.description))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Window listing all shortcuts grouped by area
+pub fn show_help(ctx: &egui::Context, open: &mut bool, send_key: SendKey) {
+    let shortcuts = all(send_key);
+    egui::Window::new("Keyboard Shortcuts")
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .open(open)
+        .show(ctx, |ui| {
+            for area in Area::ALL {
+                ui.strong(area.name());
+                egui::Grid::new(("shortcuts_grid", area))
+                    .num_columns(2)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for shortcut in shortcuts.iter().filter(|s| s.area == area) {
+                            ui.label(RichText::new(format(ctx, &shortcut.keys)).monospace());
+                            ui.label(shortcut.description);
+                            ui.end_row();
+                        }
+                    });
+                ui.add_space(8.0);
+            }
+        });
+}
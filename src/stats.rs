@@ -0,0 +1,155 @@
+use crate::chat::{Chat, Rating};
+use eframe::egui::{self, vec2, Color32, RichText};
+
+/// Number of days shown in the rating trend
+const TREND_DAYS: usize = 30;
+
+/// Ratings of the responses of one model
+struct ModelRatings {
+    model: String,
+    up: usize,
+    down: usize,
+    /// Thumbs up and down per day of the last [`TREND_DAYS`] days, oldest first
+    daily: [(usize, usize); TREND_DAYS],
+}
+
+impl ModelRatings {
+    fn new(model: &str) -> Self {
+        Self {
+            model: model.to_owned(),
+            up: 0,
+            down: 0,
+            daily: [(0, 0); TREND_DAYS],
+        }
+    }
+
+    #[inline]
+    fn total(&self) -> usize {
+        self.up + self.down
+    }
+
+    #[inline]
+    fn ratio(&self) -> f32 {
+        self.up as f32 / self.total().max(1) as f32
+    }
+}
+
+/// Count the ratings of all chats per model, most rated models first
+fn aggregate(chats: &[Chat], now: chrono::DateTime<chrono::Utc>) -> Vec<ModelRatings> {
+    let mut models: Vec<ModelRatings> = Vec::new();
+    for (model, rating, time) in chats.iter().flat_map(|chat| chat.ratings()) {
+        let idx = match models.iter().position(|m| m.model == model) {
+            Some(idx) => idx,
+            None => {
+                models.push(ModelRatings::new(model));
+                models.len() - 1
+            }
+        };
+        let ratings = &mut models[idx];
+        let days_ago = (now - time).num_days();
+        let day = (0..TREND_DAYS as i64)
+            .contains(&days_ago)
+            .then(|| TREND_DAYS - 1 - days_ago as usize);
+        match rating {
+            Rating::Up => {
+                ratings.up += 1;
+                if let Some(day) = day {
+                    ratings.daily[day].0 += 1;
+                }
+            }
+            Rating::Down => {
+                ratings.down += 1;
+                if let Some(day) = day {
+                    ratings.daily[day].1 += 1;
+                }
+            }
+        }
+    }
+    models.sort_by(|a, b| b.total().cmp(&a.total()));
+    models
+}
+
+/// Bars of the ratings per day, thumbs up stacked on thumbs down
+fn show_trend(ui: &mut egui::Ui, daily: &[(usize, usize); TREND_DAYS]) -> egui::Response {
+    const BAR_WIDTH: f32 = 3.0;
+    let height = ui.spacing().interact_size.y;
+    let (rect, resp) = ui.allocate_exact_size(
+        vec2(BAR_WIDTH * TREND_DAYS as f32, height),
+        egui::Sense::hover(),
+    );
+    let max = daily
+        .iter()
+        .map(|(up, down)| up + down)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let visuals = ui.visuals();
+    let (up_color, down_color) = (Color32::from_rgb(90, 170, 90), visuals.error_fg_color);
+    for (i, (up, down)) in daily.iter().enumerate() {
+        let x = rect.left() + i as f32 * BAR_WIDTH;
+        let up_height = height * *up as f32 / max as f32;
+        let down_height = height * *down as f32 / max as f32;
+        let bar = |top: f32, bottom: f32| {
+            egui::Rect::from_x_y_ranges(x..=x + BAR_WIDTH - 1.0, top..=bottom)
+        };
+        ui.painter().rect_filled(
+            bar(rect.bottom() - down_height, rect.bottom()),
+            0.0,
+            down_color,
+        );
+        ui.painter().rect_filled(
+            bar(
+                rect.bottom() - down_height - up_height,
+                rect.bottom() - down_height,
+            ),
+            0.0,
+            up_color,
+        );
+    }
+    ui.painter().hline(
+        rect.x_range(),
+        rect.bottom(),
+        visuals.widgets.noninteractive.bg_stroke,
+    );
+
+    let (up, down) = daily
+        .iter()
+        .fold((0, 0), |(u, d), (up, down)| (u + up, d + down));
+    resp.on_hover_text(format!(
+        "Last {TREND_DAYS} days: {up} 👍, {down} 👎, by the day the response was generated"
+    ))
+}
+
+/// Ratings of the responses per model, from all chats
+pub fn show(ui: &mut egui::Ui, chats: &[Chat]) {
+    let ratings = aggregate(chats, chrono::Utc::now());
+    if ratings.is_empty() {
+        ui.label(RichText::new("Rate responses with 👍 or 👎 to compare how the models do").weak());
+        return;
+    }
+
+    egui::ScrollArea::vertical()
+        .auto_shrink(false)
+        .show(ui, |ui| {
+            egui::Grid::new("model_ratings_grid")
+                .num_columns(4)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("Model");
+                    ui.strong("Ratings");
+                    ui.strong("👍");
+                    ui.strong("Trend");
+                    ui.end_row();
+
+                    for model in &ratings {
+                        ui.add(egui::Label::new(&model.model).truncate())
+                            .on_hover_text(&model.model);
+                        ui.label(model.total().to_string())
+                            .on_hover_text(format!("{} 👍, {} 👎", model.up, model.down));
+                        ui.label(format!("{:.0}%", model.ratio() * 100.0));
+                        show_trend(ui, &model.daily);
+                        ui.end_row();
+                    }
+                });
+        });
+}
@@ -1,4 +1,39 @@
-use eframe::egui::{self, FontTweak};
+use eframe::egui::{self, Color32, FontTweak, Stroke};
+
+/// Size of the smallest text style in the default egui style
+pub const DEFAULT_MIN_FONT_SIZE: f32 = 9.0;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Theme {
+    /// Follow the theme of the OS
+    #[default]
+    System,
+    Dark,
+    Light,
+}
+
+impl Theme {
+    pub const ALL: [Self; 3] = [Self::System, Self::Dark, Self::Light];
+
+    /// Whether to use dark visuals, `system_dark` is `None` if the OS theme is unknown
+    pub fn is_dark(self, system_dark: Option<bool>) -> bool {
+        match self {
+            Self::System => system_dark.unwrap_or(true),
+            Self::Dark => true,
+            Self::Light => false,
+        }
+    }
+}
+
+/// Style settings that can change at runtime, see [`apply_appearance`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Appearance {
+    pub min_font_size: f32,
+    pub high_contrast: bool,
+    pub dark_mode: bool,
+    /// Color of selections, `None` for the default of the theme
+    pub accent: Option<Color32>,
+}
 
 pub fn set_style(ctx: &egui::Context) {
     ctx.style_mut(|s| {
@@ -61,3 +96,106 @@ pub fn set_style(ctx: &egui::Context) {
     ctx.set_zoom_factor(1.09);
     ctx.set_fonts(fonts);
 }
+
+fn high_contrast_id() -> egui::Id {
+    egui::Id::new("high_contrast")
+}
+
+/// Scale the text styles so the smallest one is at least the minimum font size, independent
+/// of the zoom factor, and set up the visuals of the theme. Can be called again whenever the
+/// appearance changes.
+pub fn apply_appearance(ctx: &egui::Context, appearance: Appearance) {
+    ctx.style_mut(|s| {
+        let defaults = egui::Style::default().text_styles;
+        let scale = (appearance.min_font_size / DEFAULT_MIN_FONT_SIZE).max(1.0);
+        s.text_styles = defaults
+            .into_iter()
+            .map(|(style, mut font)| {
+                font.size *= scale;
+                (style, font)
+            })
+            .collect();
+
+        s.visuals = if appearance.dark_mode {
+            egui::Visuals::dark()
+        } else {
+            let mut visuals = egui::Visuals::light();
+            // the default is additive, which doesn't show up on the light background
+            visuals.faint_bg_color = Color32::from_gray(236);
+            visuals
+        };
+        s.visuals.interact_cursor = Some(egui::CursorIcon::PointingHand);
+        if let Some(accent) = appearance.accent {
+            set_accent(&mut s.visuals, accent);
+        }
+        if appearance.high_contrast {
+            make_high_contrast(&mut s.visuals);
+        }
+    });
+    ctx.data_mut(|d| d.insert_temp(high_contrast_id(), appearance.high_contrast));
+}
+
+fn set_accent(visuals: &mut egui::Visuals, accent: Color32) {
+    // keep the selected text readable on light and dark accents
+    let luminance =
+        0.299 * accent.r() as f32 + 0.587 * accent.g() as f32 + 0.114 * accent.b() as f32;
+    let fg = if luminance > 140.0 {
+        Color32::BLACK
+    } else {
+        Color32::WHITE
+    };
+    visuals.selection.bg_fill = accent;
+    visuals.selection.stroke.color = fg;
+    visuals.text_cursor.stroke.color = accent;
+}
+
+fn make_high_contrast(visuals: &mut egui::Visuals) {
+    let (fg, bg, faint) = if visuals.dark_mode {
+        (Color32::WHITE, Color32::BLACK, Color32::from_gray(48))
+    } else {
+        (Color32::BLACK, Color32::WHITE, Color32::from_gray(215))
+    };
+    visuals.override_text_color = Some(fg);
+    visuals.window_fill = bg;
+    visuals.panel_fill = bg;
+    visuals.extreme_bg_color = bg;
+    // selected chats and suggestions
+    visuals.faint_bg_color = faint;
+    visuals.window_stroke = Stroke::new(2.0, fg);
+    visuals.selection.stroke = Stroke::new(2.0, fg);
+    let widgets = &mut visuals.widgets;
+    for widget in [
+        &mut widgets.noninteractive,
+        &mut widgets.inactive,
+        &mut widgets.hovered,
+        &mut widgets.active,
+        &mut widgets.open,
+    ] {
+        widget.bg_stroke = Stroke::new(widget.bg_stroke.width.max(1.0) * 2.0, fg);
+        widget.fg_stroke = Stroke::new(widget.fg_stroke.width * 2.0, fg);
+    }
+}
+
+/// Whether high contrast is enabled in the appearance settings
+pub fn is_high_contrast(ctx: &egui::Context) -> bool {
+    ctx.data(|d| d.get_temp(high_contrast_id()).unwrap_or(false))
+}
+
+/// Background of floating buttons, opaque so the messages don't show through it
+pub fn floating_button_fill(visuals: &egui::Visuals, hovered: bool) -> Color32 {
+    if hovered {
+        let c = visuals.faint_bg_color;
+        Color32::from_rgb(c.r(), c.g(), c.b())
+    } else {
+        visuals.window_fill
+    }
+}
+
+/// Stroke of the icons painted on floating buttons
+pub fn icon_stroke(ui: &egui::Ui) -> Stroke {
+    if is_high_contrast(ui.ctx()) {
+        Stroke::new(2.5, ui.visuals().strong_text_color())
+    } else {
+        Stroke::new(2.0, Color32::DARK_GRAY)
+    }
+}
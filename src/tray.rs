@@ -0,0 +1,136 @@
+use eframe::egui::{self, ViewportCommand};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tray_icon::{
+    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    TrayIcon, TrayIconBuilder,
+};
+
+const SHOW_ID: &str = "show";
+const NEW_CHAT_ID: &str = "new_chat";
+const QUIT_ID: &str = "quit";
+
+/// Set by the tray menu, which runs outside of the UI thread
+#[derive(Default)]
+struct TrayState {
+    /// The icon was created, the window can't be hidden to the tray before that
+    icon_ready: AtomicBool,
+    hidden: AtomicBool,
+    new_chat: AtomicBool,
+    quit: AtomicBool,
+}
+
+/// Icon in the system tray, the window is hidden to it instead of closing
+pub struct Tray {
+    state: Arc<TrayState>,
+    /// The icon is removed when it's dropped. On Linux it lives on the GTK thread instead
+    #[cfg(not(target_os = "linux"))]
+    _icon: TrayIcon,
+}
+
+fn set_hidden(ctx: &egui::Context, state: &TrayState, hidden: bool) {
+    state.hidden.store(hidden, Ordering::SeqCst);
+    ctx.send_viewport_cmd(ViewportCommand::Visible(!hidden));
+    if !hidden {
+        ctx.send_viewport_cmd(ViewportCommand::Focus);
+    }
+    ctx.request_repaint();
+}
+
+fn build_icon() -> anyhow::Result<TrayIcon> {
+    let icon = crate::load_icon();
+    let menu = Menu::with_items(&[
+        &MenuItem::with_id(SHOW_ID, "Show/Hide", true, None),
+        &MenuItem::with_id(NEW_CHAT_ID, "New Chat", true, None),
+        &PredefinedMenuItem::separator(),
+        &MenuItem::with_id(QUIT_ID, "Quit", true, None),
+    ])?;
+    Ok(TrayIconBuilder::new()
+        .with_menu(Box::new(menu))
+        .with_tooltip(crate::TITLE)
+        .with_icon(tray_icon::Icon::from_rgba(
+            icon.rgba,
+            icon.width,
+            icon.height,
+        )?)
+        .build()?)
+}
+
+impl Tray {
+    pub fn new(ctx: &egui::Context) -> anyhow::Result<Self> {
+        let state = Arc::new(TrayState::default());
+        {
+            let ctx = ctx.clone();
+            let state = state.clone();
+            MenuEvent::set_event_handler(Some(move |event: MenuEvent| match event.id.0.as_str() {
+                SHOW_ID => {
+                    let hidden = !state.hidden.load(Ordering::SeqCst);
+                    set_hidden(&ctx, &state, hidden);
+                }
+                NEW_CHAT_ID => {
+                    state.new_chat.store(true, Ordering::SeqCst);
+                    set_hidden(&ctx, &state, false);
+                }
+                QUIT_ID => {
+                    state.quit.store(true, Ordering::SeqCst);
+                    set_hidden(&ctx, &state, false);
+                    ctx.send_viewport_cmd(ViewportCommand::Close);
+                }
+                _ => (),
+            }));
+        }
+
+        // the icon has to be created on a thread running the GTK main loop
+        #[cfg(target_os = "linux")]
+        {
+            let state = state.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = gtk::init() {
+                    log::error!("failed to initialize GTK for the tray icon: {e}");
+                    return;
+                }
+                match build_icon() {
+                    Ok(_icon) => {
+                        state.icon_ready.store(true, Ordering::SeqCst);
+                        gtk::main();
+                    }
+                    Err(e) => log::error!("failed to create tray icon: {e}"),
+                }
+            });
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        let icon = build_icon()?;
+        #[cfg(not(target_os = "linux"))]
+        state.icon_ready.store(true, Ordering::SeqCst);
+
+        Ok(Self {
+            state,
+            #[cfg(not(target_os = "linux"))]
+            _icon: icon,
+        })
+    }
+
+    /// Whether the icon is shown, so the window can be hidden to it
+    pub fn is_ready(&self) -> bool {
+        self.state.icon_ready.load(Ordering::SeqCst)
+    }
+
+    /// Hide the window, it can be shown again from the tray menu
+    pub fn hide(&self, ctx: &egui::Context) {
+        log::debug!("hiding window to the tray");
+        set_hidden(ctx, &self.state, true);
+    }
+
+    /// "Quit" was clicked in the tray menu, the window should close instead of hiding
+    pub fn quit_requested(&self) -> bool {
+        self.state.quit.load(Ordering::SeqCst)
+    }
+
+    /// "New Chat" was clicked in the tray menu since the last call
+    pub fn take_new_chat(&self) -> bool {
+        self.state.new_chat.swap(false, Ordering::SeqCst)
+    }
+}
@@ -8,12 +8,156 @@ use eframe::{
 };
 use egui_modal::{Icon, Modal};
 use ollama_rs::{
-    generation::options::GenerationOptions,
+    generation::{
+        options::GenerationOptions,
+        parameters::{FormatType, KeepAlive, TimeUnit},
+    },
     models::{LocalModel, ModelInfo},
     Ollama,
 };
+use std::collections::{BTreeMap, HashMap};
 use url::Url;
 
+/// What to do with the oldest messages of a chat when they don't fit into the context window
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum AutoTrim {
+    /// Send everything, Ollama truncates the start of the conversation itself
+    #[default]
+    Off,
+    /// Leave out the oldest messages, they are still shown in the chat
+    Drop,
+    /// Leave out the oldest messages and send a summary of them made by the model instead
+    Summarize,
+}
+
+impl AutoTrim {
+    const ALL: [Self; 3] = [Self::Off, Self::Drop, Self::Summarize];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Drop => "Drop oldest messages",
+            Self::Summarize => "Summarize oldest messages",
+        }
+    }
+}
+
+/// How the time a message was sent is shown next to its role
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TimestampFormat {
+    Hidden,
+    /// "5 minutes ago"
+    #[default]
+    Relative,
+    /// "14:05"
+    Absolute24h,
+    /// "2:05 PM"
+    Absolute12h,
+}
+
+impl TimestampFormat {
+    const ALL: [Self; 4] = [
+        Self::Hidden,
+        Self::Relative,
+        Self::Absolute24h,
+        Self::Absolute12h,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Hidden => "Hidden",
+            Self::Relative => "Relative",
+            Self::Absolute24h => "24-hour clock",
+            Self::Absolute12h => "12-hour clock",
+        }
+    }
+
+    /// Format a message time, absolute times older than today include the date
+    pub fn format(self, time: chrono::DateTime<chrono::Utc>) -> Option<String> {
+        let local = time.with_timezone(&chrono::Local);
+        let date = if local.date_naive() == chrono::Local::now().date_naive() {
+            ""
+        } else {
+            "%b %-d, "
+        };
+        match self {
+            Self::Hidden => None,
+            Self::Relative => {
+                Some(timeago::Formatter::new().convert_chrono(time, chrono::Utc::now()))
+            }
+            Self::Absolute24h => Some(local.format(&format!("{date}%H:%M")).to_string()),
+            Self::Absolute12h => Some(local.format(&format!("{date}%-I:%M %p")).to_string()),
+        }
+    }
+}
+
+/// Key combination that sends the message in the chatbox, plain Enter inserts a new line
+/// unless it sends
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum SendKey {
+    /// Shift+Enter inserts a new line
+    #[default]
+    Enter,
+    CtrlEnter,
+    ShiftEnter,
+}
+
+impl SendKey {
+    const ALL: [Self; 3] = [Self::Enter, Self::CtrlEnter, Self::ShiftEnter];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Enter => "Enter",
+            Self::CtrlEnter => "Ctrl+Enter",
+            Self::ShiftEnter => "Shift+Enter",
+        }
+    }
+
+    /// Shortcut that sends the message
+    pub const fn shortcut(self) -> egui::KeyboardShortcut {
+        let modifiers = match self {
+            Self::Enter => egui::Modifiers::NONE,
+            Self::CtrlEnter => egui::Modifiers::COMMAND,
+            Self::ShiftEnter => egui::Modifiers::SHIFT,
+        };
+        egui::KeyboardShortcut::new(modifiers, egui::Key::Enter)
+    }
+
+    /// Shortcut that inserts a new line in the chatbox
+    pub const fn newline_shortcut(self) -> egui::KeyboardShortcut {
+        let modifiers = match self {
+            Self::Enter => egui::Modifiers::SHIFT,
+            Self::CtrlEnter | Self::ShiftEnter => egui::Modifiers::NONE,
+        };
+        egui::KeyboardShortcut::new(modifiers, egui::Key::Enter)
+    }
+
+    /// Whether the send key was pressed this frame
+    pub fn pressed(self, input: &egui::InputState) -> bool {
+        let modifiers = input.modifiers;
+        input.key_pressed(egui::Key::Enter)
+            && match self {
+                Self::Enter => modifiers.is_none(),
+                Self::CtrlEnter => modifiers.command_only(),
+                Self::ShiftEnter => modifiers.shift_only(),
+            }
+    }
+}
+
+/// Output format forced on a model's responses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ResponseFormat {
+    Json,
+}
+
+impl From<ResponseFormat> for FormatType {
+    fn from(value: ResponseFormat) -> Self {
+        match value {
+            ResponseFormat::Json => Self::Json,
+        }
+    }
+}
+
 #[derive(Default, Clone, serde::Serialize, serde::Deserialize)]
 pub struct SelectedModel {
     pub name: String,
@@ -37,18 +181,191 @@ impl From<LocalModel> for SelectedModel {
     }
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum ModelSort {
+    /// Order returned by the server
+    #[default]
+    Default,
+    Name,
+    Size,
+    Modified,
+}
+
+impl ModelSort {
+    const ALL: [Self; 4] = [Self::Default, Self::Name, Self::Size, Self::Modified];
+
+    fn sort(self, models: &mut [&LocalModel]) {
+        match self {
+            Self::Default => (),
+            Self::Name => models.sort_by_cached_key(|m| m.name.to_lowercase()),
+            Self::Size => models.sort_by_key(|m| std::cmp::Reverse(m.size)),
+            Self::Modified => models.sort_by_cached_key(|m| {
+                std::cmp::Reverse(chrono::DateTime::parse_from_rfc3339(&m.modified_at).ok())
+            }),
+        }
+    }
+}
+
+/// Family of a model, its name without the tag
+fn model_family(name: &str) -> &str {
+    name.split_once(':').map_or(name, |(family, _)| family)
+}
+
+/// Who selected the model in a [`ModelPicker`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum SelectionSource {
+    /// Picked by [`ModelPicker::select_best_model`], may be replaced when the model list changes
+    #[default]
+    Auto,
+    /// Picked by the user, never replaced automatically
+    User,
+}
+
 #[derive(Default, Clone, serde::Deserialize, serde::Serialize)]
 pub struct ModelPicker {
     pub selected: SelectedModel,
+    #[serde(default)]
+    pub selection_source: SelectionSource,
     pub info: Option<ModelInfo>,
+    #[serde(default)]
+    pub details: Option<ModelDetails>,
     settings: ModelSettings,
     pub template: Option<String>,
+    /// Settings and templates of the models that aren't selected, keyed by model name
+    #[serde(default)]
+    overrides: HashMap<String, ModelOverrides>,
+    #[serde(default)]
+    sort: ModelSort,
+    /// Show the models under a header for each family, the name before the tag
+    #[serde(default)]
+    group_by_family: bool,
+    #[serde(skip)]
+    filter: String,
+    #[serde(skip)]
+    popup_open: bool,
+    /// Previous selection, set when the user picks a model
+    #[serde(skip)]
+    user_selection: Option<SelectedModel>,
+    /// Name typed into the preset name field
+    #[serde(skip)]
+    preset_name: String,
+}
+
+/// Settings and template override saved for a model in a [`ModelPicker`], or as a preset
+#[derive(Default, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ModelOverrides {
+    settings: ModelSettings,
+    template: Option<String>,
 }
 
 pub enum RequestInfoType<'a> {
     Models,
     ModelInfo(&'a str),
     LoadSettings,
+    LoadModel {
+        name: &'a str,
+        keep_alive: Option<KeepAlive>,
+    },
+    RunningModels,
+    UnloadModel(&'a str),
+    PullModel(&'a str),
+    ExportAllChats,
+}
+
+/// Prompt from the prompt library, inserted into the chatbox with the 📋 button
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct SavedPrompt {
+    pub name: String,
+    pub text: String,
+}
+
+/// Favorite and recently picked models, shown at the top of the model list
+#[derive(Default, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ModelShortlist {
+    favorites: Vec<String>,
+    /// Most recent first
+    recent: Vec<String>,
+}
+
+/// How many recently picked models are remembered
+const RECENT_MODELS: usize = 5;
+
+impl ModelShortlist {
+    #[inline]
+    fn is_favorite(&self, name: &str) -> bool {
+        self.favorites.iter().any(|f| f == name)
+    }
+
+    fn toggle_favorite(&mut self, name: &str) {
+        if self.is_favorite(name) {
+            self.favorites.retain(|f| f != name);
+        } else {
+            self.favorites.push(name.to_owned());
+        }
+    }
+
+    fn add_recent(&mut self, name: &str) {
+        self.recent.retain(|r| r != name);
+        self.recent.insert(0, name.to_owned());
+        self.recent.truncate(RECENT_MODELS);
+    }
+
+    /// Favorites come first, then recently picked models, then the rest
+    fn rank(&self, name: &str) -> (u8, usize) {
+        if self.is_favorite(name) {
+            (0, 0)
+        } else if let Some(i) = self.recent.iter().position(|r| r == name) {
+            (1, i)
+        } else {
+            (2, 0)
+        }
+    }
+}
+
+/// Details of a model returned by `/api/show`, which ollama-rs doesn't expose
+#[derive(Debug, Default, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ModelDetails {
+    /// e.g. "8.0B"
+    #[serde(default)]
+    pub parameter_size: String,
+    /// e.g. "Q4_K_M"
+    #[serde(default)]
+    pub quantization_level: String,
+}
+
+/// A model that is currently loaded into memory, as returned by `/api/ps`
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct RunningModel {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub size_vram: u64,
+    #[serde(default)]
+    pub expires_at: String,
+}
+
+impl RunningModel {
+    fn expires_in(&self) -> String {
+        let Ok(expires_at) = chrono::DateTime::parse_from_rfc3339(&self.expires_at) else {
+            return "Unknown".to_owned();
+        };
+        let secs = (expires_at.with_timezone(&chrono::Utc) - chrono::Utc::now()).num_seconds();
+        match secs {
+            ..=0 => "Now".to_owned(),
+            1..=59 => format!("{secs}s"),
+            60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+            3600..=86399 => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+            // models kept alive forever expire in a couple hundred years
+            86400..=31_535_999 => format!("{}d", secs / 86400),
+            _ => "Never".to_owned(),
+        }
+    }
+}
+
+/// Temp data key of the heading of the [`collapsing_frame`] to open and scroll to
+fn reveal_frame_id() -> egui::Id {
+    egui::Id::new("reveal_collapsing_frame")
 }
 
 fn collapsing_frame<R>(
@@ -56,6 +373,15 @@ fn collapsing_frame<R>(
     heading: &str,
     show: impl FnOnce(&mut egui::Ui) -> R,
 ) -> egui::Response {
+    let reveal = ui.data_mut(|d| {
+        let reveal = d
+            .get_temp::<String>(reveal_frame_id())
+            .is_some_and(|h| h == heading);
+        if reveal {
+            d.remove::<String>(reveal_frame_id());
+        }
+        reveal
+    });
     let style = ui.style();
 
     egui::Frame {
@@ -80,6 +406,10 @@ fn collapsing_frame<R>(
             if resp.clicked() {
                 state.toggle(ui);
             }
+            if reveal {
+                state.set_open(true);
+                resp.scroll_to_me(Some(egui::Align::TOP));
+            }
 
             state.show_body_unindented(ui, |ui| {
                 ui.separator();
@@ -101,42 +431,151 @@ const TEMPLATE_HINT_TEXT: &str = r#"{{ if .System }}<|im_start|>system
 {{ end }}<|im_start|>assistant"#;
 
 impl ModelPicker {
+    fn show_model_list(
+        &mut self,
+        ui: &mut egui::Ui,
+        models: &[LocalModel],
+        shortlist: &mut ModelShortlist,
+    ) {
+        let just_opened = !self.popup_open;
+
+        let search = ui.add(
+            egui::TextEdit::singleline(&mut self.filter)
+                .hint_text("Search models…")
+                .desired_width(f32::INFINITY),
+        );
+        if just_opened {
+            search.request_focus();
+        }
+        ui.horizontal(|ui| {
+            ui.label("Sort by");
+            for sort in ModelSort::ALL {
+                ui.selectable_value(&mut self.sort, sort, format!("{sort:?}"));
+            }
+            ui.separator();
+            ui.checkbox(&mut self.group_by_family, "Group")
+                .on_hover_text("Group the models by family, e.g. all llama3 tags together");
+        });
+        ui.separator();
+
+        let filter = self.filter.to_lowercase();
+        let mut shown: Vec<&LocalModel> = models
+            .iter()
+            .filter(|m| m.name.to_lowercase().contains(&filter))
+            .collect();
+        self.sort.sort(&mut shown);
+        // stable, so the models keep the chosen order within each group
+        shown.sort_by_key(|m| shortlist.rank(&m.name));
+
+        if self.group_by_family {
+            // families are in the order of their first model
+            let mut families: Vec<(&str, Vec<&LocalModel>)> = Vec::new();
+            for model in shown.iter().copied() {
+                let family = model_family(&model.name);
+                match families.iter_mut().find(|(f, _)| *f == family) {
+                    Some((_, models)) => models.push(model),
+                    None => families.push((family, vec![model])),
+                }
+            }
+            for (family, models) in families {
+                let has_selected = models.iter().any(|m| self.selected_model() == m.name);
+                egui::CollapsingHeader::new(format!("{family} ({})", models.len()))
+                    .id_source(("model_family", family))
+                    .default_open(has_selected)
+                    .open((!filter.is_empty()).then_some(true))
+                    .show(ui, |ui| {
+                        self.show_model_grid(ui, family, &models, shortlist, just_opened);
+                    });
+            }
+        } else {
+            self.show_model_grid(ui, "", &shown, shortlist, just_opened);
+        }
+
+        if models.is_empty() {
+            ui.label("No models found, is the server running?");
+        } else if shown.is_empty() {
+            ui.label("No models match the search");
+        }
+    }
+
+    /// Rows of the model list with the model sizes, `id` tells the grids apart
+    fn show_model_grid(
+        &mut self,
+        ui: &mut egui::Ui,
+        id: &str,
+        models: &[&LocalModel],
+        shortlist: &mut ModelShortlist,
+        just_opened: bool,
+    ) {
+        egui::Grid::new(("model_selector_grid", id))
+            .num_columns(3)
+            .show(ui, |ui| {
+                for model in models {
+                    let favorite = shortlist.is_favorite(&model.name);
+                    if ui
+                        .add(
+                            egui::Button::new(if favorite { "★" } else { "☆" })
+                                .small()
+                                .fill(Color32::TRANSPARENT),
+                        )
+                        .on_hover_text(if favorite {
+                            "Remove from favorites"
+                        } else {
+                            "Add to favorites"
+                        })
+                        .clicked()
+                    {
+                        shortlist.toggle_favorite(&model.name);
+                    }
+                    let is_selected = self.selected_model() == model.name;
+                    let resp = ui.selectable_label(is_selected, &model.name);
+                    if resp.clicked() {
+                        self.select(model);
+                        ui.memory_mut(|mem| mem.close_popup());
+                    }
+                    if is_selected && just_opened {
+                        resp.scroll_to_me(Some(egui::Align::Center));
+                    }
+                    ui.with_layout(Layout::right_to_left(egui::Align::Center), |ui| {
+                        ui.add_enabled(
+                            false,
+                            egui::Label::new(format!("{}", bytesize::ByteSize(model.size))),
+                        );
+                    });
+                    ui.end_row();
+                }
+            });
+    }
+
     pub fn show<R>(
         &mut self,
         ui: &mut egui::Ui,
         models: Option<&[LocalModel]>,
+        shortlist: &mut ModelShortlist,
+        presets: &mut BTreeMap<String, ModelOverrides>,
         request_info: &mut R,
     ) where
         R: FnMut(RequestInfoType<'_>),
     {
+        let mut missing = false;
         if let Some(models) = models {
+            missing = self.has_selection() && !models.iter().any(|m| m.name == self.selected.name);
+            let selected_text = if missing {
+                RichText::new(format!("{} (not installed)", self.selected_model())).weak()
+            } else {
+                RichText::new(self.selected_model())
+            };
+            let previous = self.selected.name.clone();
             ui.horizontal(|ui| {
-                egui::ComboBox::from_id_source("model_selector_combobox")
-                    .selected_text(self.selected_model())
-                    .show_ui(ui, |ui| {
-                        for model in models {
-                            ui.horizontal(|ui| {
-                                if ui
-                                    .selectable_label(
-                                        self.selected_model() == model.name,
-                                        &model.name,
-                                    )
-                                    .clicked()
-                                {
-                                    self.selected = model.clone().into();
-                                    self.info = None;
-                                }
-                                // TODO: make this stick to the right
-                                ui.add_enabled(
-                                    false,
-                                    egui::Label::new(format!("{}", bytesize::ByteSize(model.size))),
-                                );
-                            });
-                        }
-                        if models.is_empty() {
-                            ui.label("No models found, is the server running?");
-                        }
-                    });
+                let popup = egui::ComboBox::from_id_source("model_selector_combobox")
+                    .selected_text(selected_text)
+                    .height(320.0)
+                    .close_behavior(egui::PopupCloseBehavior::CloseOnClickOutside)
+                    .show_ui(ui, |ui| self.show_model_list(ui, models, shortlist));
+                self.popup_open = popup.inner.is_some();
+                if self.selected.name != previous {
+                    shortlist.add_recent(&self.selected.name);
+                }
                 if ui
                     .add(egui::Button::new("⟳").small().fill(Color32::TRANSPARENT))
                     .on_hover_text("Refresh model list")
@@ -144,6 +583,25 @@ impl ModelPicker {
                 {
                     request_info(RequestInfoType::Models);
                 }
+                if missing {
+                    if ui
+                        .button("Pull")
+                        .on_hover_text("Download the model from the Ollama library")
+                        .clicked()
+                    {
+                        request_info(RequestInfoType::PullModel(self.selected_model()));
+                    }
+                } else if self.has_selection()
+                    && ui
+                        .button("Load now")
+                        .on_hover_text("Load the model into memory so the next message is fast")
+                        .clicked()
+                {
+                    request_info(RequestInfoType::LoadModel {
+                        name: self.selected_model(),
+                        keep_alive: self.get_keep_alive(),
+                    });
+                }
             });
         } else {
             ui.horizontal(|ui| {
@@ -156,10 +614,76 @@ impl ModelPicker {
             return;
         }
 
-        ui.collapsing("Inference Settings", |ui| {
-            self.settings.show(ui, &mut self.template);
+        let has_overrides = self.has_overrides();
+        let saved = self.overrides.len();
+        egui::CollapsingHeader::new(if has_overrides {
+            "Inference Settings •"
+        } else {
+            "Inference Settings"
+        })
+        .id_source("inference_settings")
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(has_overrides, egui::Button::new("Reset Settings"))
+                    .on_hover_text("Reset the settings and template of this model")
+                    .clicked()
+                {
+                    self.settings = ModelSettings::default();
+                    self.template = None;
+                }
+                if ui
+                    .add_enabled(
+                        has_overrides || saved > 0,
+                        egui::Button::new("Clear All Models"),
+                    )
+                    .on_hover_text(format!(
+                        "Reset the settings and templates of all models ({} saved)",
+                        saved + has_overrides as usize
+                    ))
+                    .clicked()
+                {
+                    self.settings = ModelSettings::default();
+                    self.template = None;
+                    self.overrides.clear();
+                }
+            });
+            self.show_presets(ui, presets);
+            let changed = self.settings.changed();
+            if !changed.is_empty() {
+                ui.horizontal_wrapped(|ui| {
+                    ui.spacing_mut().item_spacing.x = 0.0;
+                    for (i, (text, heading)) in changed.iter().enumerate() {
+                        if i > 0 {
+                            ui.weak(", ");
+                        }
+                        if ui
+                            .add(
+                                egui::Label::new(RichText::new(text).code())
+                                    .sense(egui::Sense::click()),
+                            )
+                            .on_hover_text("Show the setting")
+                            .clicked()
+                        {
+                            ui.data_mut(|d| d.insert_temp(reveal_frame_id(), heading.to_string()));
+                        }
+                    }
+                });
+            }
+            if has_overrides {
+                ui.label(RichText::new("This model has custom settings").weak());
+            }
+            self.settings.show(ui);
         });
 
+        if missing {
+            ui.label(
+                RichText::new("This model is not installed on the server, pull it to use it")
+                    .weak(),
+            );
+            return;
+        }
+
         egui::Grid::new("selected_model_info_grid")
             .num_columns(2)
             .show(ui, |ui| {
@@ -172,6 +696,19 @@ impl ModelPicker {
                 ui.add(egui::Label::new(&self.selected.modified_ago).truncate())
                     .on_hover_text(&self.selected.modified_at);
                 ui.end_row();
+
+                if let Some(details) = &self.details {
+                    if !details.parameter_size.is_empty() {
+                        ui.label("Parameter count");
+                        ui.label(&details.parameter_size);
+                        ui.end_row();
+                    }
+                    if !details.quantization_level.is_empty() {
+                        ui.label("Quantization");
+                        ui.label(&details.quantization_level);
+                        ui.end_row();
+                    }
+                }
             });
 
         if let Some(info) = &self.info {
@@ -253,15 +790,83 @@ impl ModelPicker {
         }
     }
 
-    pub fn on_new_model_info(&mut self, name: &str, info: &ModelInfo) {
+    /// Row to apply, save and delete named settings presets
+    fn show_presets(&mut self, ui: &mut egui::Ui, presets: &mut BTreeMap<String, ModelOverrides>) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("settings_preset_combobox")
+                .selected_text(if presets.is_empty() {
+                    "No presets"
+                } else {
+                    "Apply preset…"
+                })
+                .show_ui(ui, |ui| {
+                    for (name, preset) in presets.iter() {
+                        if ui.selectable_label(false, name).clicked() {
+                            self.settings = preset.settings.clone();
+                            self.template.clone_from(&preset.template);
+                            self.preset_name.clone_from(name);
+                        }
+                    }
+                })
+                .response
+                .on_hover_text("Replace the settings and template with a saved preset");
+
+            ui.add(
+                egui::TextEdit::singleline(&mut self.preset_name)
+                    .hint_text("Preset name")
+                    .desired_width(100.0),
+            );
+            let name = self.preset_name.trim();
+            let exists = presets.contains_key(name);
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new("Save"))
+                .on_hover_text(if exists {
+                    "Overwrite the preset with the current settings and template"
+                } else {
+                    "Save the current settings and template as a preset"
+                })
+                .clicked()
+            {
+                presets.insert(
+                    name.to_owned(),
+                    ModelOverrides {
+                        settings: self.settings.clone(),
+                        template: self.template.clone(),
+                    },
+                );
+            }
+            if ui
+                .add_enabled(exists, egui::Button::new("Delete"))
+                .on_hover_text("Delete the preset")
+                .clicked()
+            {
+                presets.remove(name);
+            }
+        });
+    }
+
+    pub fn on_new_model_info(
+        &mut self,
+        name: &str,
+        info: &ModelInfo,
+        details: Option<&ModelDetails>,
+    ) {
         if self.selected_model() == name {
             self.info = Some(info.clone());
+            self.details = details.cloned();
         }
     }
 
+    /// Select the largest model, unless the user picked a model or the selected model is still
+    /// available
     pub fn select_best_model(&mut self, models: &[LocalModel]) {
+        if self.selection_source == SelectionSource::User
+            || (self.has_selection() && models.iter().any(|m| m.name == self.selected.name))
+        {
+            return;
+        }
         if let Some(m) = models.iter().max_by_key(|m| m.size) {
-            self.selected = m.clone().into();
+            self.set_selected(m.clone().into());
         }
 
         if self.has_selection() {
@@ -272,6 +877,70 @@ impl ModelPicker {
         }
     }
 
+    /// Select a model as if the user picked it from the list
+    pub fn select(&mut self, model: &LocalModel) {
+        let previous = self.selected.clone();
+        self.set_selected(model.clone().into());
+        self.selection_source = SelectionSource::User;
+        self.info = None;
+        self.details = None;
+        self.user_selection.get_or_insert(previous);
+    }
+
+    /// If the user picked a model since the last call, returns the model that was selected
+    /// before
+    #[inline]
+    pub fn take_user_selection(&mut self) -> Option<SelectedModel> {
+        self.user_selection.take()
+    }
+
+    /// Go back to a previous selection, e.g. when the license of the picked model is declined
+    pub fn revert_selection(&mut self, previous: SelectedModel) {
+        self.set_selected(previous);
+        self.info = None;
+        self.details = None;
+        if !self.has_selection() {
+            self.selection_source = SelectionSource::Auto;
+        }
+    }
+
+    /// Change the selected model, saving the settings of the previous model and restoring the
+    /// settings of the new one
+    pub fn set_selected(&mut self, selected: SelectedModel) {
+        if selected.name == self.selected.name {
+            self.selected = selected;
+            return;
+        }
+
+        // settings changed before any model was selected apply to the first one
+        if self.has_selection() {
+            let previous = ModelOverrides {
+                settings: std::mem::take(&mut self.settings),
+                template: self.template.take(),
+            };
+            if previous.settings != ModelSettings::default() || previous.template.is_some() {
+                self.overrides.insert(self.selected.name.clone(), previous);
+            } else {
+                self.overrides.remove(&self.selected.name);
+            }
+            let restored = self.overrides.remove(&selected.name).unwrap_or_default();
+            self.settings = restored.settings;
+            self.template = restored.template;
+        }
+        self.selected = selected;
+    }
+
+    /// Whether the selected model has settings or a template that differ from the defaults
+    #[inline]
+    pub fn has_overrides(&self) -> bool {
+        self.settings != ModelSettings::default() || self.template.is_some()
+    }
+
+    #[inline]
+    pub fn set_temperature(&mut self, temperature: f32) {
+        self.settings.temperature = Some(temperature);
+    }
+
     #[inline]
     pub fn has_selection(&self) -> bool {
         !self.selected.name.is_empty()
@@ -282,12 +951,89 @@ impl ModelPicker {
         self.settings.clone().into()
     }
 
+    /// Context window size used for requests, the Ollama default if it isn't overridden
+    #[inline]
+    pub fn num_ctx(&self) -> u32 {
+        self.settings.num_ctx.unwrap_or(DEFAULT_NUM_CTX)
+    }
+
+    #[inline]
+    pub fn num_predict(&self) -> Option<i32> {
+        self.settings.num_predict
+    }
+
+    #[inline]
+    pub fn stop_sequences(&self) -> &[String] {
+        self.settings.stop.as_deref().unwrap_or_default()
+    }
+
+    #[inline]
+    pub fn response_format(&self) -> Option<ResponseFormat> {
+        self.settings.response_format
+    }
+
+    #[inline]
+    pub fn get_keep_alive(&self) -> Option<KeepAlive> {
+        self.settings
+            .keep_alive
+            .as_deref()
+            .and_then(parse_keep_alive)
+    }
+
     #[inline]
     pub fn selected_model(&self) -> &str {
         &self.selected.name
     }
 }
 
+const KEEP_ALIVE_PRESETS: &[(&str, &str)] = &[
+    ("5m", "5 minutes"),
+    ("30m", "30 minutes"),
+    ("1h", "1 hour"),
+    ("-1", "Forever"),
+    ("0", "Unload immediately"),
+];
+
+/// Parse a duration like `30s`, `5m`, `1h30m`, `-1` (forever) or `0` (unload) into a
+/// [`KeepAlive`]. A number without a unit is treated as seconds.
+fn parse_keep_alive(s: &str) -> Option<KeepAlive> {
+    let s: String = s.split_whitespace().collect();
+    match s.as_str() {
+        "" => return None,
+        "-1" => return Some(KeepAlive::Indefinitely),
+        "0" => return Some(KeepAlive::UnloadOnCompletion),
+        _ => (),
+    }
+    // several parts are added up in the smallest unit among them
+    let mut seconds = 0u64;
+    let mut smallest = u64::MAX;
+    let mut rest = s.as_str();
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let num = rest[..digits].parse::<u64>().ok()?;
+        let unit_secs = match rest[digits..].chars().next() {
+            Some('s') | None => 1,
+            Some('m') => 60,
+            Some('h') => 60 * 60,
+            Some(_) => return None,
+        };
+        seconds = seconds.checked_add(num.checked_mul(unit_secs)?)?;
+        smallest = smallest.min(unit_secs);
+        rest = rest.get(digits + 1..).unwrap_or_default();
+    }
+    let unit = match smallest {
+        1 => TimeUnit::Seconds,
+        60 => TimeUnit::Minutes,
+        _ => TimeUnit::Hours,
+    };
+    Some(KeepAlive::Until {
+        time: seconds / smallest,
+        unit,
+    })
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 enum MirostatKind {
     Disabled,
@@ -311,7 +1057,7 @@ impl MirostatKind {
     }
 }
 
-#[derive(Default, Clone, serde::Deserialize, serde::Serialize)]
+#[derive(Default, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
 struct ModelSettings {
     /// Enable Mirostat sampling for controlling perplexity. (default: 0, 0 = disabled, 1 = Mirostat, 2 = Mirostat 2.0)
     pub mirostat: Option<MirostatKind>,
@@ -345,6 +1091,10 @@ struct ModelSettings {
     pub top_k: Option<u32>,
     /// Works together with top-k. A higher value (e.g., 0.95) will lead to more diverse text, while a lower value (e.g., 0.5) will generate more focused and conservative text. (Default: 0.9)
     pub top_p: Option<f32>,
+    /// Controls how long the model will stay loaded into memory following the request. (Default: 5m, -1 = forever, 0 = unload immediately)
+    pub keep_alive: Option<String>,
+    /// Force the model to respond in a format. (Default: free text)
+    pub response_format: Option<ResponseFormat>,
 }
 
 impl From<ModelSettings> for GenerationOptions {
@@ -470,12 +1220,45 @@ impl ModelSettings {
         });
     }
 
-    fn show(&mut self, ui: &mut egui::Ui, template: &mut Option<String>) {
-        if ui.button("Reset Settings").clicked() {
-            *self = Self::default();
-            *template = None;
+    /// Settings that differ from the defaults as `name=value` with the heading of their
+    /// control, using the Ollama parameter names
+    fn changed(&self) -> Vec<(String, &'static str)> {
+        let mut changed = Vec::new();
+        macro_rules! add {
+            ($field:ident, $heading:literal) => {
+                if let Some(value) = &self.$field {
+                    changed.push((format!("{}={value}", stringify!($field)), $heading));
+                }
+            };
+        }
+        if let Some(mirostat) = self.mirostat {
+            changed.push((format!("mirostat={}", mirostat.to_u8()), "Mirostat"));
+        }
+        add!(mirostat_eta, "Mirostat eta");
+        add!(mirostat_tau, "Mirostat tau");
+        add!(num_ctx, "Context Window");
+        add!(num_gqa, "Number of GQA Groups");
+        add!(num_gpu, "GPU Layers");
+        add!(num_thread, "Number of Threads");
+        add!(repeat_last_n, "Repeat Last N");
+        add!(repeat_penalty, "Repeat Penalty");
+        add!(temperature, "Temperature");
+        add!(seed, "Seed");
+        if let Some(stop) = &self.stop {
+            changed.push((format!("stop={stop:?}"), "Stop Sequence"));
+        }
+        add!(tfs_z, "Tail-Free Sampling Z");
+        add!(num_predict, "Number to Predict");
+        add!(top_k, "Top-K");
+        add!(top_p, "Top-P");
+        if self.response_format.is_some() {
+            changed.push(("format=json".to_owned(), "JSON Mode"));
         }
+        add!(keep_alive, "Keep Alive");
+        changed
+    }
 
+    fn show(&mut self, ui: &mut egui::Ui) {
         collapsing_frame(ui, "Mirostat", |ui| {
             ui.label("Enable Mirostat sampling for controlling perplexity.");
 
@@ -606,6 +1389,67 @@ impl ModelSettings {
         Self::edit_numeric(ui, &mut self.num_predict, 128, 1.0, "Number to Predict", "Maximum number of tokens to predict when generating text. (Default: 128, -1 = infinite generation, -2 = fill context)");
         Self::edit_numeric(ui, &mut self.top_k, 40, 1.0, "Top-K", "Reduces the probability of generating nonsense. A higher value (e.g. 100) will give more diverse answers, while a lower value (e.g. 10) will be more conservative.");
         Self::edit_numeric(ui, &mut self.top_p, 0.9, 0.01, "Top-P", "Works together with top-k. A higher value (e.g., 0.95) will lead to more diverse text, while a lower value (e.g., 0.5) will generate more focused and conservative text.");
+
+        collapsing_frame(ui, "JSON Mode", |ui| {
+            ui.label(
+                "Force the model to respond with valid JSON. \
+                It's a good idea to also ask for JSON in the prompt.",
+            );
+            let mut enabled = self.response_format == Some(ResponseFormat::Json);
+            ui.horizontal(|ui| {
+                ui.add(toggle(&mut enabled));
+                ui.label("Enable");
+            });
+            self.response_format = enabled.then_some(ResponseFormat::Json);
+        });
+
+        collapsing_frame(ui, "Keep Alive", |ui| {
+            ui.label(
+                "Controls how long the model will stay loaded into memory following the request. \
+                Loading a model can take a while, so keeping it loaded makes responses start faster.",
+            );
+            let mut enabled = self.keep_alive.is_some();
+
+            ui.horizontal(|ui| {
+                ui.add(toggle(&mut enabled));
+                ui.label("Enable");
+            });
+
+            if !enabled {
+                self.keep_alive = None;
+            } else if self.keep_alive.is_none() {
+                self.keep_alive = Some("5m".to_owned());
+            }
+
+            ui.add_enabled_ui(self.keep_alive.is_some(), |ui| {
+                if let Some(ref mut keep_alive) = self.keep_alive {
+                    ui.horizontal(|ui| {
+                        let selected = KEEP_ALIVE_PRESETS
+                            .iter()
+                            .find(|(value, _)| *value == keep_alive.as_str())
+                            .map_or("Custom", |(_, name)| name);
+                        egui::ComboBox::from_id_source("keep_alive_combobox")
+                            .selected_text(selected)
+                            .show_ui(ui, |ui| {
+                                for (value, name) in KEEP_ALIVE_PRESETS {
+                                    ui.selectable_value(keep_alive, value.to_string(), *name);
+                                }
+                            });
+                        ui.add(
+                            egui::TextEdit::singleline(keep_alive)
+                                .hint_text("5m")
+                                .desired_width(64.0),
+                        );
+                    });
+                    if parse_keep_alive(keep_alive).is_none() {
+                        ui.label(
+                            RichText::new("Invalid duration, expected e.g. 30s, 5m, 1h or -1")
+                                .color(ui.visuals().error_fg_color),
+                        );
+                    }
+                }
+            });
+        });
     }
 }
 
@@ -668,7 +1512,8 @@ pub fn suggestion(ui: &mut egui::Ui, text: &str, subtext: &str) -> egui::Respons
 
     // for some reason egui sets `Frame::group` to not sense clicks, so we
     // have to hack it here
-    resp.clicked = resp.hovered()
+    resp.clicked = ui.is_enabled()
+        && resp.hovered()
         && ui.input(|i| {
             i.pointer.any_click()
                 && i.pointer
@@ -723,7 +1568,7 @@ fn toggle_ui(ui: &mut egui::Ui, on: &mut bool) -> egui::Response {
 }
 
 #[inline]
-fn toggle(on: &mut bool) -> impl egui::Widget + '_ {
+pub fn toggle(on: &mut bool) -> impl egui::Widget + '_ {
     move |ui: &mut egui::Ui| toggle_ui(ui, on)
 }
 
@@ -735,14 +1580,192 @@ fn help(ui: &mut egui::Ui, text: &str, add_contents: impl FnOnce(&mut egui::Ui))
     });
 }
 
+#[cfg(feature = "tts")]
+#[derive(Default, serde::Deserialize, serde::Serialize, Clone)]
+pub struct TtsSettings {
+    /// Name of the selected voice, `None` for the system default
+    pub voice: Option<String>,
+    pub rate: Option<f32>,
+    pub pitch: Option<f32>,
+    /// Read responses aloud when they finish generating
+    #[serde(default)]
+    pub auto_read: bool,
+    /// With `auto_read`, start reading sentences as soon as they are generated
+    #[serde(default)]
+    pub read_while_generating: bool,
+}
+
+/// How attached audio files are transcribed
+#[derive(Default, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum TranscriptionBackend {
+    /// Audio attachments are rejected
+    #[default]
+    None,
+    /// Command template, `{file}` is replaced with the path of the audio file and the
+    /// transcript is read from stdout
+    Command(String),
+    /// URL of a whisper.cpp or OpenAI compatible transcription endpoint
+    Server(String),
+}
+
 #[derive(serde::Deserialize, serde::Serialize, Clone)]
 pub struct Settings {
     pub endpoint: String,
     endpoint_error: String,
     pub model_picker: ModelPicker,
     pub inherit_chat_picker: bool,
+    /// Shared by the model pickers of all chats
+    #[serde(default)]
+    pub model_shortlist: ModelShortlist,
+    /// Named inference settings and templates that can be applied to any model
+    #[serde(default)]
+    pub model_presets: BTreeMap<String, ModelOverrides>,
+    /// Prompt library shared by all chats
+    #[serde(default)]
+    pub saved_prompts: Vec<SavedPrompt>,
+    #[cfg(feature = "tts")]
+    #[serde(default)]
+    pub tts: TtsSettings,
+    /// Show the last messages when hovering a chat in the sidebar
+    #[serde(default = "default_true")]
+    pub chat_previews: bool,
+    /// Show a desktop notification when a response finishes while the window is unfocused
+    #[serde(default)]
+    pub notify_on_completion: bool,
+    /// Play a sound when a response finishes while the window is unfocused
+    #[cfg(feature = "sound")]
+    #[serde(default)]
+    pub sound_on_completion: bool,
+    /// Hide the window to the system tray when it's closed instead of quitting
+    #[cfg(feature = "tray")]
+    #[serde(default)]
+    pub minimize_to_tray: bool,
+    /// Load the model of the selected chat in the background if it isn't loaded
+    #[serde(default)]
+    pub prewarm_models: bool,
+    /// Ask before sending the same message twice within a few seconds
+    #[serde(default = "default_true")]
+    pub confirm_duplicate_sends: bool,
+    /// Show the actions of user messages without hovering them
+    #[serde(default)]
+    pub always_show_actions: bool,
+    /// Warn before sending messages with a larger request than this, in KiB. 0 to never warn
+    #[serde(default = "default_max_message_kib")]
+    pub max_message_kib: usize,
+    /// Longest side of the images sent to models, larger ones are downscaled. `None` to send
+    /// them in their original size
+    #[serde(default = "default_max_image_dimension")]
+    pub max_image_dimension: Option<u32>,
+    /// Attached text files are truncated to this size, in KiB
+    #[serde(default = "default_max_text_file_kib")]
+    pub max_text_file_kib: usize,
+    #[serde(default)]
+    pub transcription: TranscriptionBackend,
+    /// Maximum height of the chatbox, as a fraction of the window height
+    #[serde(default = "default_chatbox_max_height")]
+    pub chatbox_max_height: f32,
+    /// Applies to chats that keep all messages in the context
+    #[serde(default)]
+    pub auto_trim: AutoTrim,
+    #[serde(default)]
+    pub timestamp_format: TimestampFormat,
+    #[serde(default)]
+    pub send_key: SendKey,
+    /// Size of the smallest text, before zooming
+    #[serde(default = "default_min_font_size")]
+    pub min_font_size: f32,
+    #[serde(default)]
+    pub high_contrast: bool,
+    #[serde(default)]
+    pub theme: crate::style::Theme,
+    /// Color of selections, `None` for the default of the theme
+    #[serde(default)]
+    pub accent_color: Option<[u8; 3]>,
+    /// Widest the messages and the chatbox get, `None` to use the full width of the window
+    #[serde(default = "default_chat_max_width")]
+    pub chat_max_width: Option<f32>,
+    /// Tint each prompt together with the responses to it
+    #[serde(default)]
+    pub group_exchanges: bool,
+    /// Format of messages copied with "Copy as quote", see [`crate::chat::fill_template`]
+    #[serde(default = "default_quote_template")]
+    pub quote_template: String,
+    /// Sent instead of an empty message with only images attached, empty to send no text
+    #[serde(default = "default_image_prompt")]
+    pub image_prompt: String,
+    /// Language responses are translated to
+    #[serde(default = "default_translation_language")]
+    pub translation_language: String,
+    /// Model that translates responses, empty for the model of the chat
+    #[serde(default)]
+    pub translation_model: String,
+    /// Lock the app after this many minutes without input, 0 to never lock it
+    #[serde(default)]
+    pub auto_lock_minutes: u32,
+    /// Hash of the PIN that unlocks the app, the passphrase is used instead if the chats
+    /// are encrypted
+    #[serde(default)]
+    pub lock_pin_hash: Option<String>,
+    /// Ask to accept a model's license the first time it's picked
+    #[serde(default)]
+    pub license_prompt: bool,
+    /// Licenses the user accepted, keyed by model name
+    #[serde(default)]
+    pub accepted_licenses: BTreeMap<String, String>,
+}
+
+#[inline]
+const fn default_true() -> bool {
+    true
 }
 
+#[inline]
+const fn default_chatbox_max_height() -> f32 {
+    0.4
+}
+
+const fn default_max_message_kib() -> usize {
+    1024
+}
+
+const DEFAULT_MAX_IMAGE_DIMENSION: u32 = 1120;
+
+const fn default_max_image_dimension() -> Option<u32> {
+    Some(DEFAULT_MAX_IMAGE_DIMENSION)
+}
+
+const fn default_max_text_file_kib() -> usize {
+    64
+}
+
+const fn default_min_font_size() -> f32 {
+    crate::style::DEFAULT_MIN_FONT_SIZE
+}
+
+const DEFAULT_CHAT_MAX_WIDTH: f32 = 760.0;
+
+/// Accent color picked when a custom one is enabled, the selection color of the dark theme
+const DEFAULT_ACCENT_COLOR: [u8; 3] = [0, 92, 128];
+
+const fn default_chat_max_width() -> Option<f32> {
+    Some(DEFAULT_CHAT_MAX_WIDTH)
+}
+
+fn default_quote_template() -> String {
+    "{quote}\n\n— {model}, {date} via Ellama".to_owned()
+}
+
+fn default_image_prompt() -> String {
+    "Describe the attached image(s).".to_owned()
+}
+
+fn default_translation_language() -> String {
+    "English".to_owned()
+}
+
+/// Context window size Ollama uses when `num_ctx` isn't set
+const DEFAULT_NUM_CTX: u32 = 2048;
+
 const DEFAULT_HOST: &str = "http://127.0.0.1:11434";
 
 impl Default for Settings {
@@ -751,7 +1774,43 @@ impl Default for Settings {
             endpoint: DEFAULT_HOST.to_owned(),
             model_picker: ModelPicker::default(),
             inherit_chat_picker: true,
+            model_shortlist: ModelShortlist::default(),
+            model_presets: BTreeMap::new(),
+            saved_prompts: Vec::new(),
             endpoint_error: String::new(),
+            chat_previews: true,
+            notify_on_completion: false,
+            #[cfg(feature = "sound")]
+            sound_on_completion: false,
+            #[cfg(feature = "tray")]
+            minimize_to_tray: false,
+            prewarm_models: false,
+            confirm_duplicate_sends: true,
+            always_show_actions: false,
+            max_message_kib: default_max_message_kib(),
+            max_text_file_kib: default_max_text_file_kib(),
+            max_image_dimension: default_max_image_dimension(),
+            transcription: TranscriptionBackend::None,
+            chatbox_max_height: default_chatbox_max_height(),
+            auto_trim: AutoTrim::default(),
+            timestamp_format: TimestampFormat::default(),
+            send_key: SendKey::default(),
+            min_font_size: default_min_font_size(),
+            high_contrast: false,
+            theme: crate::style::Theme::default(),
+            accent_color: None,
+            chat_max_width: default_chat_max_width(),
+            group_exchanges: false,
+            quote_template: default_quote_template(),
+            image_prompt: default_image_prompt(),
+            translation_language: default_translation_language(),
+            translation_model: String::new(),
+            auto_lock_minutes: 0,
+            lock_pin_hash: None,
+            license_prompt: false,
+            accepted_licenses: BTreeMap::new(),
+            #[cfg(feature = "tts")]
+            tts: TtsSettings::default(),
         }
     }
 }
@@ -765,12 +1824,127 @@ impl Settings {
         Ok(url)
     }
 
+    /// Host and port of the endpoint, for displaying to the user
+    pub fn endpoint_host(&self) -> String {
+        self.parse_endpoint()
+            .ok()
+            .and_then(|url| {
+                let host = url.host_str()?.to_owned();
+                Some(match url.port_or_known_default() {
+                    Some(port) => format!("{host}:{port}"),
+                    None => host,
+                })
+            })
+            .unwrap_or_else(|| self.endpoint.clone())
+    }
+
+    #[inline]
+    pub fn endpoint_url(&self) -> Url {
+        self.parse_endpoint()
+            .unwrap_or_else(|_| Url::parse(DEFAULT_HOST).unwrap())
+    }
+
     #[inline]
     pub fn make_ollama(&self) -> Ollama {
-        Ollama::from_url(
-            self.parse_endpoint()
-                .unwrap_or_else(|_| Url::parse(DEFAULT_HOST).unwrap()),
-        )
+        Ollama::from_url(self.endpoint_url())
+    }
+
+    fn show_license_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add(toggle(&mut self.license_prompt));
+            ui.label("Show a model's license the first time it's picked");
+        });
+        if self.accepted_licenses.is_empty() {
+            return;
+        }
+        ui.label(
+            "Accepted licenses, revoking one shows it again the next time the model is picked",
+        );
+        let mut revoked = None;
+        for (name, license) in &self.accepted_licenses {
+            ui.horizontal(|ui| {
+                if ui
+                    .small_button("Revoke")
+                    .on_hover_text("Ask to accept the license again")
+                    .clicked()
+                {
+                    revoked = Some(name.clone());
+                }
+                ui.collapsing(name, |ui| {
+                    egui::ScrollArea::vertical()
+                        .id_source(("accepted_license", name))
+                        .max_height(240.0)
+                        .show(ui, |ui| {
+                            ui.code_editor(&mut license.as_str());
+                        });
+                });
+            });
+        }
+        if let Some(name) = revoked {
+            log::info!("revoked license of `{name}`");
+            self.accepted_licenses.remove(&name);
+        }
+    }
+
+    fn show_transcription_settings(ui: &mut egui::Ui, backend: &mut TranscriptionBackend) {
+        ui.label("Transcribe attached audio files into the chatbox");
+        let selected = match backend {
+            TranscriptionBackend::None => "Disabled",
+            TranscriptionBackend::Command(_) => "External command",
+            TranscriptionBackend::Server(_) => "Whisper server",
+        };
+        egui::ComboBox::from_label("Backend")
+            .selected_text(selected)
+            .show_ui(ui, |ui| {
+                if ui
+                    .selectable_label(*backend == TranscriptionBackend::None, "Disabled")
+                    .clicked()
+                {
+                    *backend = TranscriptionBackend::None;
+                }
+                if ui
+                    .selectable_label(
+                        matches!(backend, TranscriptionBackend::Command(_)),
+                        "External command",
+                    )
+                    .clicked()
+                    && !matches!(backend, TranscriptionBackend::Command(_))
+                {
+                    *backend = TranscriptionBackend::Command(String::new());
+                }
+                if ui
+                    .selectable_label(
+                        matches!(backend, TranscriptionBackend::Server(_)),
+                        "Whisper server",
+                    )
+                    .clicked()
+                    && !matches!(backend, TranscriptionBackend::Server(_))
+                {
+                    *backend = TranscriptionBackend::Server(String::new());
+                }
+            });
+        match backend {
+            TranscriptionBackend::None => (),
+            TranscriptionBackend::Command(command) => {
+                ui.add(
+                    egui::TextEdit::singleline(command)
+                        .hint_text("whisper-cli -m ggml-base.bin -nt -f {file}")
+                        .desired_width(f32::INFINITY),
+                )
+                .on_hover_text(
+                    "{file} is replaced with the path of the audio file, \
+                    the transcript is read from the standard output",
+                );
+            }
+            TranscriptionBackend::Server(url) => {
+                ui.add(
+                    egui::TextEdit::singleline(url)
+                        .hint_text("http://127.0.0.1:8080/inference")
+                        .desired_width(f32::INFINITY),
+                )
+                .on_hover_text("The audio file is uploaded as the `file` field of a form");
+            }
+        }
     }
 
     pub fn show_modal(&mut self, modal: &Modal) {
@@ -813,10 +1987,76 @@ impl Settings {
             .map_err(|e| log::error!("failed to save settings: {e}"));
     }
 
+    fn show_running_models<R>(
+        ui: &mut egui::Ui,
+        running_models: Option<&[RunningModel]>,
+        request_info: &mut R,
+    ) where
+        R: FnMut(RequestInfoType<'_>),
+    {
+        ui.horizontal(|ui| {
+            ui.label("Models currently loaded into memory");
+            if ui
+                .add_enabled(
+                    running_models.is_some(),
+                    egui::Button::new("⟳").small().fill(Color32::TRANSPARENT),
+                )
+                .on_hover_text("Refresh running models")
+                .clicked()
+            {
+                request_info(RequestInfoType::RunningModels);
+            }
+        });
+
+        let Some(running_models) = running_models else {
+            ui.horizontal(|ui| {
+                ui.add(egui::Spinner::new());
+                ui.label("Loading running models…");
+            });
+            return;
+        };
+
+        if running_models.is_empty() {
+            ui.add_enabled(false, egui::Label::new("No models are loaded"));
+            return;
+        }
+
+        egui::Grid::new("running_models_grid")
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label(RichText::new("Model").strong());
+                ui.label(RichText::new("VRAM").strong());
+                ui.label(RichText::new("Expires In").strong());
+                ui.end_row();
+
+                for model in running_models {
+                    ui.label(&model.name);
+                    ui.label(format!("{}", bytesize::ByteSize(model.size_vram)))
+                        .on_hover_text(format!(
+                            "{} total, {} in VRAM",
+                            bytesize::ByteSize(model.size),
+                            bytesize::ByteSize(model.size_vram)
+                        ));
+                    ui.label(model.expires_in())
+                        .on_hover_text(&model.expires_at);
+                    if ui
+                        .button("Unload")
+                        .on_hover_text("Unload the model from memory")
+                        .clicked()
+                    {
+                        request_info(RequestInfoType::UnloadModel(&model.name));
+                    }
+                    ui.end_row();
+                }
+            });
+    }
+
     pub fn show<R>(
         &mut self,
         ui: &mut egui::Ui,
         models: Option<&[LocalModel]>,
+        running_models: Option<&[RunningModel]>,
         request_info: &mut R,
         modal: &Modal,
     ) where
@@ -867,17 +2107,323 @@ impl Settings {
             });
         });
         ui.add_space(2.0);
-        self.model_picker.show(ui, models, request_info);
+        self.model_picker.show(
+            ui,
+            models,
+            &mut self.model_shortlist,
+            &mut self.model_presets,
+            request_info,
+        );
+
+        ui.separator();
+
+        ui.heading("Running Models");
+        ui.horizontal(|ui| {
+            ui.add(toggle(&mut self.prewarm_models));
+            ui.label("Load the model of the selected chat in the background")
+                .on_hover_text(
+                    "The first response of a model that isn't loaded takes a while. \
+                    Models are loaded once they stay selected for a moment, at most every \
+                    few minutes and never while a response is generating",
+                );
+        });
+        Self::show_running_models(ui, running_models, request_info);
+
+        ui.separator();
+
+        ui.heading("Licenses");
+        self.show_license_settings(ui);
+
+        ui.separator();
+
+        ui.heading("Transcription");
+        Self::show_transcription_settings(ui, &mut self.transcription);
+
+        ui.separator();
+
+        ui.heading("Translation");
+        ui.horizontal(|ui| {
+            ui.label("Translate responses to");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.translation_language)
+                    .hint_text(default_translation_language()),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Translate with");
+            egui::ComboBox::from_id_source("translation_model_combobox")
+                .selected_text(if self.translation_model.is_empty() {
+                    "The model of the chat"
+                } else {
+                    self.translation_model.as_str()
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(
+                        &mut self.translation_model,
+                        String::new(),
+                        "The model of the chat",
+                    );
+                    for model in models.unwrap_or_default() {
+                        ui.selectable_value(
+                            &mut self.translation_model,
+                            model.name.clone(),
+                            &model.name,
+                        );
+                    }
+                });
+        });
+
+        ui.separator();
+
+        ui.heading("Appearance");
+
+        ui.horizontal(|ui| {
+            ui.label("Theme");
+            for theme in crate::style::Theme::ALL {
+                ui.selectable_value(&mut self.theme, theme, format!("{theme:?}"));
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let mut custom = self.accent_color.is_some();
+            ui.add(toggle(&mut custom));
+            ui.label("Accent color");
+            let mut color = self.accent_color.unwrap_or(DEFAULT_ACCENT_COLOR);
+            ui.add_enabled_ui(custom, |ui| {
+                egui::color_picker::color_edit_button_srgb(ui, &mut color);
+            });
+            self.accent_color = custom.then_some(color);
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Minimum font size");
+            ui.add(
+                egui::Slider::new(
+                    &mut self.min_font_size,
+                    crate::style::DEFAULT_MIN_FONT_SIZE..=24.0,
+                )
+                .step_by(0.5),
+            )
+            .on_hover_text("Scales all text so the smallest text is at least this size");
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(toggle(&mut self.high_contrast));
+            ui.label("High contrast");
+        });
+
+        ui.horizontal(|ui| {
+            let mut limited = self.chat_max_width.is_some();
+            ui.add(toggle(&mut limited));
+            ui.label("Limit the width of chats to");
+            let mut width = self.chat_max_width.unwrap_or(DEFAULT_CHAT_MAX_WIDTH);
+            ui.add_enabled(
+                limited,
+                egui::DragValue::new(&mut width)
+                    .clamp_range(320.0..=4096.0)
+                    .speed(4)
+                    .suffix(" px"),
+            )
+            .on_hover_text("Long lines are hard to read on wide windows");
+            self.chat_max_width = limited.then_some(width);
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(toggle(&mut self.group_exchanges));
+            ui.label("Group prompts with their responses")
+                .on_hover_text("Alternates the background of each prompt and its answers");
+        });
 
         ui.separator();
 
         ui.heading("Miscellaneous");
 
+        ui.horizontal(|ui| {
+            ui.add(toggle(&mut self.chat_previews));
+            ui.label("Preview recent messages when hovering chats in the sidebar");
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(toggle(&mut self.notify_on_completion));
+            ui.label("Notify when a response finishes while the window is unfocused");
+        });
+
+        #[cfg(feature = "sound")]
+        ui.horizontal(|ui| {
+            ui.add(toggle(&mut self.sound_on_completion));
+            ui.label("Play a sound when a response finishes while the window is unfocused");
+        });
+
+        #[cfg(feature = "tray")]
+        ui.horizontal(|ui| {
+            ui.add(toggle(&mut self.minimize_to_tray));
+            ui.label("Keep running in the system tray when the window is closed")
+                .on_hover_text("The tray icon is added or removed after a restart");
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(toggle(&mut self.confirm_duplicate_sends));
+            ui.label("Ask before sending the same message twice in a row");
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(toggle(&mut self.always_show_actions));
+            ui.label("Always show the actions of your messages")
+                .on_hover_text("Otherwise they show when hovering a message or holding Shift");
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Warn about messages larger than");
+            ui.add(
+                egui::DragValue::new(&mut self.max_message_kib)
+                    .speed(16)
+                    .suffix(" KiB"),
+            )
+            .on_hover_text(
+                "Counts the text and the attached images and files, \
+                large requests may be rejected by Ollama. 0 to never warn",
+            );
+        });
+
+        ui.horizontal(|ui| {
+            let mut limited = self.max_image_dimension.is_some();
+            ui.add(toggle(&mut limited));
+            ui.label("Downscale images larger than");
+            let mut dimension = self
+                .max_image_dimension
+                .unwrap_or(DEFAULT_MAX_IMAGE_DIMENSION);
+            ui.add_enabled(
+                limited,
+                egui::DragValue::new(&mut dimension)
+                    .clamp_range(64..=8192)
+                    .speed(8)
+                    .suffix(" px"),
+            )
+            .on_hover_text(
+                "Longest side of the images sent to models, most vision models downscale \
+                larger images anyway. Right click an attached image to send it as is",
+            );
+            self.max_image_dimension = limited.then_some(dimension);
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Truncate attached text files to");
+            ui.add(
+                egui::DragValue::new(&mut self.max_text_file_kib)
+                    .speed(4)
+                    .clamp_range(1..=usize::MAX)
+                    .suffix(" KiB"),
+            )
+            .on_hover_text("Only the beginning of larger files is attached");
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Maximum chatbox height");
+            ui.add(
+                egui::Slider::new(&mut self.chatbox_max_height, 0.1..=0.9)
+                    .custom_formatter(|n, _| format!("{:.0}%", n * 100.0))
+                    .custom_parser(|s| {
+                        s.trim_end_matches('%')
+                            .trim()
+                            .parse::<f64>()
+                            .ok()
+                            .map(|n| n / 100.0)
+                    }),
+            )
+            .on_hover_text("Fraction of the window height the chatbox can grow to");
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Message timestamps");
+            egui::ComboBox::from_id_source("timestamp_format_combobox")
+                .selected_text(self.timestamp_format.name())
+                .show_ui(ui, |ui| {
+                    for format in TimestampFormat::ALL {
+                        ui.selectable_value(&mut self.timestamp_format, format, format.name());
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Send messages with");
+            egui::ComboBox::from_id_source("send_key_combobox")
+                .selected_text(self.send_key.name())
+                .show_ui(ui, |ui| {
+                    for key in SendKey::ALL {
+                        ui.selectable_value(&mut self.send_key, key, key.name());
+                    }
+                });
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Quote template");
+            if ui
+                .small_button("Reset")
+                .on_hover_text("Reset the template to the default")
+                .clicked()
+            {
+                self.quote_template = default_quote_template();
+            }
+        });
+        ui.add(
+            egui::TextEdit::multiline(&mut self.quote_template)
+                .desired_rows(2)
+                .desired_width(f32::INFINITY),
+        )
+        .on_hover_text(
+            "Used by \"Copy as quote\" on messages. {quote} is the message as a Markdown \
+            blockquote, {content} the plain message, {model} the model or \"You\" and {date} \
+            the day it was sent",
+        );
+
+        ui.horizontal(|ui| {
+            ui.label("Image-only prompt");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.image_prompt)
+                    .hint_text("Send images without text"),
+            )
+            .on_hover_text(
+                "Sent when a message has images attached but no text. Leave it empty to send \
+                just the images.",
+            );
+            if ui
+                .small_button("Reset")
+                .on_hover_text("Reset the prompt to the default")
+                .clicked()
+            {
+                self.image_prompt = default_image_prompt();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Auto-trim long chats");
+            egui::ComboBox::from_id_source("auto_trim_combobox")
+                .selected_text(self.auto_trim.name())
+                .show_ui(ui, |ui| {
+                    for auto_trim in AutoTrim::ALL {
+                        ui.selectable_value(&mut self.auto_trim, auto_trim, auto_trim.name());
+                    }
+                })
+                .response
+                .on_hover_text(
+                    "When a chat doesn't fit into the model's context window, leave out the \
+                    oldest messages instead of letting Ollama cut off the start of the \
+                    conversation with the system prompt. The model won't know what was said in \
+                    dropped messages, summaries keep only the gist of them and take an extra \
+                    request to make. Messages are still shown in the chat.",
+                );
+        });
+
         ui.label("Reset global settings to defaults");
         if ui.button("Reset").clicked() {
             modal.open();
         }
 
+        ui.label("Export every chat into a folder, with the format selected when editing a chat");
+        if ui.button("Export All Chats…").clicked() {
+            request_info(RequestInfoType::ExportAllChats);
+        }
+
         ui.label("Save and load settings as JSON");
         ui.horizontal(|ui| {
             if ui.button("Save").clicked() {
@@ -892,3 +2438,67 @@ impl Settings {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn until(s: &str) -> Option<(u64, &'static str)> {
+        match parse_keep_alive(s)? {
+            KeepAlive::Until { time, unit } => Some((
+                time,
+                match unit {
+                    TimeUnit::Seconds => "s",
+                    TimeUnit::Minutes => "m",
+                    TimeUnit::Hours => "h",
+                },
+            )),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_special_keep_alives() {
+        assert!(matches!(
+            parse_keep_alive("-1"),
+            Some(KeepAlive::Indefinitely)
+        ));
+        assert!(matches!(
+            parse_keep_alive(" 0 "),
+            Some(KeepAlive::UnloadOnCompletion)
+        ));
+    }
+
+    #[test]
+    fn parses_keep_alive_durations() {
+        assert_eq!(until("5m"), Some((5, "m")));
+        assert_eq!(until("30s"), Some((30, "s")));
+        assert_eq!(until("2h"), Some((2, "h")));
+        assert_eq!(until("45"), Some((45, "s")));
+        assert_eq!(until("5 m"), Some((5, "m")));
+    }
+
+    #[test]
+    fn adds_up_keep_alive_parts() {
+        assert_eq!(until("1h30m"), Some((90, "m")));
+        assert_eq!(until("1m30s"), Some((90, "s")));
+        assert_eq!(until("1h 15s"), Some((3615, "s")));
+    }
+
+    #[test]
+    fn rejects_invalid_keep_alives() {
+        for s in [
+            "",
+            "  ",
+            "m",
+            "5x",
+            "-5m",
+            "1.5h",
+            "h1",
+            "5mm",
+            "99999999999999999999h",
+        ] {
+            assert!(parse_keep_alive(s).is_none(), "{s:?} should be rejected");
+        }
+    }
+}